@@ -4,8 +4,17 @@ use std::fs::create_dir_all;
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
 use sophon::msg::{
-    BalanceResponse, ClaimsResponse, DelegateResponse, HandleMsg, InitMsg, InvestmentResponse,
-    QueryMsg, TokenInfoResponse,
+    AccountResponse, AllAllowancesResponse, AllDelegationsResponse, AllowanceResponse,
+    BackedRateResponse, BalanceResponse, BalanceSheetResponse, BlendedCommissionResponse,
+    CanBondResponse, ClaimsBreakdownResponse, ClaimsResponse, ContractBalanceResponse,
+    DelegateResponse, DelegatorCountResponse, EffectiveRateResponse, ExitTaxPreviewResponse,
+    ExpiredCountResponse, FeesEarnedResponse, HandleMsg, InitMsg, InvestmentResponse,
+    IsDelegatorResponse, MigrateMsg, MinMeaningfulBondResponse, NextExpiryResponse,
+    OwnerFeesResponse, PendingUnbondCountResponse, QueryMsg, RankedValidatorsResponse,
+    RedeemableResponse, ReinvestPreviewResponse, TargetValidatorsResponse, TokenInfoResponse,
+    TvlResponse, UnbondScheduleResponse, UnbondStatusResponse, UnbondingResponse,
+    UnrealizedGainResponse, ValidatorActiveResponse, ValidatorHistoryResponse,
+    ValidatorRewardsResponse, ValidatorStatsResponse,
 };
 use sophon::state::{InvestmentInfo, Supply};
 
@@ -18,11 +27,45 @@ fn main() {
     export_schema(&schema_for!(InitMsg), &out_dir);
     export_schema(&schema_for!(HandleMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
+    export_schema(&schema_for!(AccountResponse), &out_dir);
+    export_schema(&schema_for!(AllAllowancesResponse), &out_dir);
+    export_schema(&schema_for!(AllDelegationsResponse), &out_dir);
+    export_schema(&schema_for!(AllowanceResponse), &out_dir);
+    export_schema(&schema_for!(BackedRateResponse), &out_dir);
     export_schema(&schema_for!(BalanceResponse), &out_dir);
+    export_schema(&schema_for!(BalanceSheetResponse), &out_dir);
+    export_schema(&schema_for!(BlendedCommissionResponse), &out_dir);
+    export_schema(&schema_for!(CanBondResponse), &out_dir);
+    export_schema(&schema_for!(ClaimsBreakdownResponse), &out_dir);
     export_schema(&schema_for!(ClaimsResponse), &out_dir);
+    export_schema(&schema_for!(ContractBalanceResponse), &out_dir);
     export_schema(&schema_for!(DelegateResponse), &out_dir);
+    export_schema(&schema_for!(DelegatorCountResponse), &out_dir);
+    export_schema(&schema_for!(EffectiveRateResponse), &out_dir);
+    export_schema(&schema_for!(ExitTaxPreviewResponse), &out_dir);
+    export_schema(&schema_for!(ExpiredCountResponse), &out_dir);
+    export_schema(&schema_for!(FeesEarnedResponse), &out_dir);
     export_schema(&schema_for!(InvestmentResponse), &out_dir);
+    export_schema(&schema_for!(IsDelegatorResponse), &out_dir);
+    export_schema(&schema_for!(MinMeaningfulBondResponse), &out_dir);
+    export_schema(&schema_for!(NextExpiryResponse), &out_dir);
+    export_schema(&schema_for!(OwnerFeesResponse), &out_dir);
+    export_schema(&schema_for!(PendingUnbondCountResponse), &out_dir);
+    export_schema(&schema_for!(RankedValidatorsResponse), &out_dir);
+    export_schema(&schema_for!(RedeemableResponse), &out_dir);
+    export_schema(&schema_for!(ReinvestPreviewResponse), &out_dir);
+    export_schema(&schema_for!(TargetValidatorsResponse), &out_dir);
     export_schema(&schema_for!(TokenInfoResponse), &out_dir);
+    export_schema(&schema_for!(TvlResponse), &out_dir);
+    export_schema(&schema_for!(UnbondScheduleResponse), &out_dir);
+    export_schema(&schema_for!(UnbondStatusResponse), &out_dir);
+    export_schema(&schema_for!(UnbondingResponse), &out_dir);
+    export_schema(&schema_for!(UnrealizedGainResponse), &out_dir);
+    export_schema(&schema_for!(ValidatorActiveResponse), &out_dir);
+    export_schema(&schema_for!(ValidatorHistoryResponse), &out_dir);
+    export_schema(&schema_for!(ValidatorRewardsResponse), &out_dir);
+    export_schema(&schema_for!(ValidatorStatsResponse), &out_dir);
     export_schema(&schema_for!(InvestmentInfo), &out_dir);
     export_schema(&schema_for!(Supply), &out_dir);
 }
@@ -1,37 +1,85 @@
+use std::cmp::Ordering;
+
 use cosmwasm_std::{
-    attr, coin, Coin, to_binary, Api, CosmosMsg, BankMsg, Binary, Decimal, Env, Extern, HandleResponse, HumanAddr,
-    InitResponse, MessageInfo, Querier, QueryRequest, StakingMsg, StakingQuery, StdError,
-    StdResult, Storage, Uint128, Validator, ValidatorsResponse
+    attr, coin, to_binary, Api, Attribute, BankMsg, Binary, CanonicalAddr, Coin, CosmosMsg,
+    Decimal, Env, Extern, HandleResponse, HumanAddr, InitResponse, MessageInfo, MigrateResponse,
+    Order, Querier, QueryRequest, ReadonlyStorage, StakingMsg, StakingQuery, StdError, StdResult,
+    Storage, Uint128, Validator, ValidatorsResponse, WasmMsg,
 };
 
-use crate::errors::{StakingError, Unauthorized};
+use crate::errors::{StakingError, TooManyDelegators, Unauthorized};
 use crate::msg::{
-    BalanceResponse, ClaimsResponse, DelegateResponse, HandleMsg, InitMsg, InvestmentResponse,
-    QueryMsg, TokenInfoResponse,
+    AccountResponse, AllAllowancesResponse, AllDelegationsResponse, AllowanceInfo,
+    AllowanceResponse, BackedRateResponse, BalanceResponse, BalanceSheetResponse,
+    BlendedCommissionResponse, CanBondResponse, ClaimsBreakdownResponse, ClaimsResponse,
+    ContractBalanceResponse, Cw20ReceiveMsg, DelegateResponse, DelegatorCountResponse,
+    EffectiveRateResponse, ExitTaxPreviewResponse, Expiration, ExpiredCountResponse,
+    FeesEarnedResponse, HandleMsg, InitMsg, InvestmentResponse, IsDelegatorResponse, MigrateMsg,
+    MinMeaningfulBondResponse, NextExpiryResponse, OwnerFeesResponse, PendingUnbondCountResponse,
+    QueryMsg, RankedValidatorsResponse, RedeemableResponse, ReinvestPreviewResponse,
+    SelectionStrategy, SudoMsg, TargetValidatorsResponse, TokenInfoResponse, TvlResponse,
+    UnbondScheduleResponse, UnbondStatusResponse, UnbondingResponse, UnrealizedGainResponse,
+    ValidatorActiveResponse, ValidatorHistoryEntry, ValidatorHistoryResponse, ValidatorPower,
+    ValidatorRewardsResponse, ValidatorStatsResponse,
 };
 use crate::state::{
-    balances, balances_read, claims_read, delegations, delegations_read, delegators,
-    delegators_read, invest_info, invest_info_read, token_info, token_info_read, total_supply,
-    total_supply_read, InvestmentInfo, Supply,
+    allowances, allowances_read, balance_snapshots, balance_snapshots_read, balances,
+    balances_read, claim_heights, claim_heights_read, claims, claims_read, contract_version,
+    contract_version_read, delegations, delegations_read, delegator_count, delegator_count_read,
+    delegators, delegators_read, fees_earned, fees_earned_read, invest_info, invest_info_read,
+    legacy_claims_read, nonces, nonces_read, pending_unbonds, pending_unbonds_read, sweep_cursor,
+    sweep_cursor_read, token_info, token_info_read, total_supply, total_supply_read,
+    validator_history, validator_history_read, validator_powers, validator_powers_read, Claim,
+    DelegateInfo, InvestmentInfo, Supply, DEFAULT_BLOCKS_PER_YEAR, SECONDS_PER_YEAR,
 };
 
 const FALLBACK_RATIO: Decimal = Decimal::one();
+/// Decimal::one() in atomics, Decimal keeps 18 fractional digits
+const DECIMAL_FRACTIONAL: u128 = 1_000_000_000_000_000_000;
+/// the windows below are durations in seconds, turned into blocks with the configured
+/// blocks_per_year (see InvestmentInfo::blocks_for_duration)
+///
+/// stake that was redelegated cannot be redelegated again until the unbonding period
+/// (21 days) has passed
+const REDELEGATE_COOLDOWN_SECONDS: u64 = 21 * 24 * 60 * 60;
+/// unbonded stake is claimable once the chain has released it, after the same 21 days
+const UNBONDING_SECONDS: u64 = 21 * 24 * 60 * 60;
+/// delegations older than this (1.5 days) are unbonded or reinvested by the expiry sweep
+const EXPIRY_SECONDS: u64 = 36 * 60 * 60;
+/// claims untouched for a year are considered abandoned
+const CLAIM_EXPIRY_SECONDS: u64 = SECONDS_PER_YEAR;
+const DEFAULT_SWEEP_LIMIT: u32 = 10;
+const MAX_SWEEP_LIMIT: u32 = 30;
+/// most claim entries released for one address in a single call
+const MAX_CLAIM_ENTRIES: usize = 30;
+/// storage layout version, bumped whenever migrate has to rewrite existing state or a
+/// query response changes shape. Reported as InvestmentResponse::schema_version
+const CONTRACT_VERSION: u16 = 7;
+/// page sizes for list queries
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
 
 pub fn init<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     msg: InitMsg,
 ) -> StdResult<InitResponse> {
-    // ensure the validator is registered
-    let vals = deps.querier.query_validators()?;
-    if !vals.iter().any(|v| v.address == msg.validator) {
-        return Err(StdError::generic_err(format!(
-            "{} is not in the current validator set",
-            msg.validator
-        )));
+    // ensure the validators are registered
+    assert_validator_active(&deps.querier, &msg.validator)?;
+    let validators = match msg.validators {
+        Some(validators) => validators,
+        None => vec![msg.validator.clone()],
+    };
+    if validators.is_empty() {
+        return Err(StdError::generic_err("At least one validator is required"));
+    }
+    for validator in validators.iter() {
+        assert_validator_active(&deps.querier, validator)?;
     }
 
+    validate_name(&msg.name)?;
+    validate_symbol(&msg.symbol)?;
     let token = TokenInfoResponse {
         name: msg.name,
         symbol: msg.symbol,
@@ -41,12 +89,71 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
 
     let denom = deps.querier.query_bonded_denom()?;
 
+    let fee_recipients = msg
+        .fee_recipients
+        .unwrap_or_else(|| vec![(info.sender.clone(), Decimal::one())]);
+    validate_fee_recipients(&deps.api, &fee_recipients)?;
+
+    let min_commission = msg.min_commission.unwrap_or_else(Decimal::zero);
+    let max_commission = msg.max_commission.unwrap_or_else(Decimal::one);
+    if min_commission > max_commission || max_commission > Decimal::one() {
+        return Err(StdError::generic_err("Invalid commission band"));
+    }
+    let max_validator_share = msg.max_validator_share.unwrap_or_else(Decimal::one);
+    if max_validator_share.is_zero() || max_validator_share > Decimal::one() {
+        return Err(StdError::generic_err("Invalid max_validator_share"));
+    }
+    let compound_ratio = msg.compound_ratio.unwrap_or_else(Decimal::one);
+    if compound_ratio > Decimal::one() {
+        return Err(StdError::generic_err("Invalid compound_ratio"));
+    }
+    let instant_unbond_fee = msg.instant_unbond_fee;
+    if msg.snapshot_retention == Some(0) {
+        return Err(StdError::generic_err("snapshot_retention must be nonzero"));
+    }
+    if matches!(instant_unbond_fee, Some(fee) if fee > Decimal::one()) {
+        return Err(StdError::generic_err("Invalid instant_unbond_fee"));
+    }
+    let reward_fee = msg.reward_fee.unwrap_or_else(Decimal::zero);
+    if reward_fee > Decimal::one() {
+        return Err(StdError::generic_err("Invalid reward_fee"));
+    }
+    let tax_tiers = msg.tax_tiers.unwrap_or_default();
+    validate_tax_tiers(&tax_tiers)?;
+
     let invest = InvestmentInfo {
         owner: deps.api.canonical_address(&info.sender)?,
         exit_tax: msg.exit_tax,
         bond_denom: denom,
         validator: msg.validator,
+        validators,
         min_withdrawal: msg.min_withdrawal,
+        selection_strategy: msg
+            .selection_strategy
+            .unwrap_or(SelectionStrategy::LowestCommission),
+        max_delegators: msg.max_delegators,
+        distribute_rewards: msg.distribute_rewards,
+        compound_ratio,
+        reinvest_min_ratio: msg.reinvest_min_ratio,
+        display_denom: msg.display_denom,
+        display_exponent: msg.display_exponent,
+        min_age_before_redelegate: msg.min_age_before_redelegate.unwrap_or_default(),
+        min_hold_blocks: msg.min_hold_blocks.unwrap_or_default(),
+        blocks_per_year: msg.blocks_per_year.unwrap_or(DEFAULT_BLOCKS_PER_YEAR),
+        tax_tiers,
+        auto_callback: msg.auto_callback.unwrap_or(true),
+        min_commission,
+        max_commission,
+        max_validator_share,
+        instant_unbond_fee,
+        reward_fee,
+        snapshot_retention: msg.snapshot_retention,
+        deny_list: msg.deny_list.unwrap_or_default(),
+        withdraw_address: None,
+        bond_stray: msg.bond_stray.unwrap_or_default(),
+        fee_recipients,
+        init_height: env.block.height,
+        init_time: env.block.time,
     };
     invest_info(&mut deps.storage).save(&invest)?;
 
@@ -54,33 +161,181 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
     let supply = Supply::default();
     total_supply(&mut deps.storage).save(&supply)?;
 
+    // start with an empty delegator list, so sweeps have something to load
+    delegators(&mut deps.storage).save(&vec![])?;
+    delegator_count(&mut deps.storage).save(&0)?;
+    pending_unbonds(&mut deps.storage).save(&0)?;
+    contract_version(&mut deps.storage).save(&CONTRACT_VERSION)?;
+    fees_earned(&mut deps.storage).save(&Uint128::zero())?;
+
     Ok(InitResponse::default())
 }
 
+// validate_tax_tiers checks the thresholds are strictly ascending and every rate is at
+// most 1
+fn validate_tax_tiers(tiers: &[(u64, Decimal)]) -> StdResult<()> {
+    if tiers.iter().any(|(_, rate)| *rate > Decimal::one()) {
+        return Err(StdError::generic_err("Tax tier rates must be at most 1"));
+    }
+    if tiers.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+        return Err(StdError::generic_err("Tax tiers must be sorted"));
+    }
+    Ok(())
+}
+
+// validate_fee_recipients checks every recipient is a valid address and that the
+// weights add up to exactly 1
+fn validate_fee_recipients<A: Api>(api: &A, recipients: &[(HumanAddr, Decimal)]) -> StdResult<()> {
+    let mut total = Decimal::zero();
+    for (addr, weight) in recipients.iter() {
+        validate_address(api, addr, "fee recipient")?;
+        total = total + *weight;
+    }
+    if total != Decimal::one() {
+        return Err(StdError::generic_err(
+            "Fee recipient weights must add up to 1",
+        ));
+    }
+    Ok(())
+}
+
 pub fn handle<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
     info: MessageInfo,
     msg: HandleMsg,
 ) -> Result<HandleResponse, StakingError> {
-    match msg {
+    let height = env.block.height;
+    let res = match msg {
         HandleMsg::Transfer { recipient, amount } => {
             Ok(transfer(deps, env, info, recipient, amount)?)
         }
-        HandleMsg::Bond {} => Ok(bond(deps, env, info)?),
-        HandleMsg::Unbond {} => Ok(reserve_unbond(deps, env, info)?),
+        HandleMsg::TransferWithNonce {
+            recipient,
+            amount,
+            nonce,
+        } => Ok(transfer_with_nonce(
+            deps, env, info, recipient, amount, nonce,
+        )?),
+        HandleMsg::Send {
+            contract,
+            amount,
+            msg,
+        } => Ok(send(deps, env, info, contract, amount, msg)?),
+        HandleMsg::IncreaseAllowance {
+            spender,
+            amount,
+            expires,
+        } => Ok(increase_allowance(
+            deps, env, info, spender, amount, expires,
+        )?),
+        HandleMsg::DecreaseAllowance {
+            spender,
+            amount,
+            expires,
+        } => Ok(decrease_allowance(
+            deps, env, info, spender, amount, expires,
+        )?),
+        HandleMsg::TransferFrom {
+            owner,
+            recipient,
+            amount,
+        } => Ok(transfer_from(deps, env, info, owner, recipient, amount)?),
+        HandleMsg::Bond { validator } => bond(deps, env, info, validator),
+        HandleMsg::Unbond { amount } => Ok(reserve_unbond(deps, env, info, amount)?),
+        HandleMsg::InstantUnbond {} => Ok(instant_unbond(deps, env, info)?),
+        HandleMsg::Reinvest {} => Ok(reinvest_rewards(deps, env, info)?),
+        HandleMsg::ReinvestAll {} => Ok(reinvest_all(deps, env, info)?),
+        HandleMsg::WithdrawRewards {} => withdraw_rewards(deps, env, info),
+        HandleMsg::SetAutoReinvest { enabled } => Ok(set_auto_reinvest(deps, env, info, enabled)?),
+        HandleMsg::BondStray {} => Ok(bond_stray(deps, env, info)?),
+        HandleMsg::SetBondStray { enabled } => set_bond_stray(deps, env, info, enabled),
+        HandleMsg::ProcessExpired { limit } => Ok(is_expired(deps, env, limit)?),
+        HandleMsg::SetValidatorPowers { powers } => set_validator_powers(deps, env, info, powers),
+        HandleMsg::SetDenyList { validators } => set_deny_list(deps, env, info, validators),
+        HandleMsg::SetWithdrawAddress { address } => set_withdraw_address(deps, env, info, address),
+        HandleMsg::ExpireClaims { address } => expire_claims(deps, env, info, address),
+        HandleMsg::Claim {} => Ok(claim_for(deps, env, info.sender)?),
+        HandleMsg::ClaimFor { address } => Ok(claim_for(deps, env, address)?),
+        HandleMsg::EvacuateValidator { from } => evacuate_validator(deps, env, info, from),
+        HandleMsg::UpdateTokenInfo { name, symbol } => {
+            update_token_info(deps, env, info, name, symbol)
+        }
+        HandleMsg::Rebalance {} => Ok(rebalance(deps, env, info)?),
+        HandleMsg::WithdrawFees {} => withdraw_fees(deps, env, info),
+        HandleMsg::SetReward { address, amount } => set_reward(deps, env, info, address, amount),
+        HandleMsg::MigrateDelegator { from, to } => migrate_delegator(deps, env, info, from, to),
+        HandleMsg::SetMinWithdrawal { min_withdrawal } => {
+            set_min_withdrawal(deps, env, info, min_withdrawal)
+        }
         HandleMsg::_BondAllTokens {} => _bond_all_tokens(deps, env, info),
+    }?;
+    record_validators(&mut deps.storage, &res.messages, height)?;
+    Ok(res)
+}
+
+// record_validators adds the destinations of any delegations among `messages` to the
+// validator history. Every handler's messages pass through here, so none is missed
+fn record_validators<S: Storage>(
+    storage: &mut S,
+    messages: &[CosmosMsg],
+    height: u64,
+) -> StdResult<()> {
+    for msg in messages {
+        let validator = match msg {
+            CosmosMsg::Staking(StakingMsg::Delegate { validator, .. }) => validator,
+            CosmosMsg::Staking(StakingMsg::Redelegate { dst_validator, .. }) => dst_validator,
+            _ => continue,
+        };
+        let key = validator.as_str().as_bytes();
+        if validator_history_read(storage).may_load(key)?.is_none() {
+            validator_history(storage).save(key, &height)?;
+        }
+    }
+    Ok(())
+}
+
+// validate_address canonicalizes an address passed in a message, replacing the low-level
+// decode failure with an error that names the offending argument
+fn validate_address<A: Api>(api: &A, addr: &HumanAddr, name: &str) -> StdResult<CanonicalAddr> {
+    api.canonical_address(addr)
+        .map_err(|_| StdError::generic_err(format!("Invalid {} address: {}", name, addr)))
+}
+
+// snapshot_balance records the holder's current balance at `height` for BalanceAt, if
+// snapshots are on, dropping its oldest snapshots beyond snapshot_retention
+fn snapshot_balance<S: Storage>(
+    storage: &mut S,
+    key: &CanonicalAddr,
+    height: u64,
+) -> StdResult<()> {
+    let retention = match invest_info_read(storage).load()?.snapshot_retention {
+        Some(retention) => retention as usize,
+        None => return Ok(()),
+    };
+    let balance = balances_read(storage)
+        .may_load(key.as_slice())?
+        .unwrap_or_default();
+    let mut snapshots = balance_snapshots(storage, key);
+    snapshots.save(&height.to_be_bytes(), &balance)?;
+    let heights = snapshots
+        .range(None, None, Order::Ascending)
+        .map(|item| item.map(|(k, _)| k))
+        .collect::<StdResult<Vec<_>>>()?;
+    for k in heights.iter().take(heights.len().saturating_sub(retention)) {
+        snapshots.remove(k);
     }
+    Ok(())
 }
 
 pub fn transfer<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     recipient: HumanAddr,
     send: Uint128,
 ) -> StdResult<HandleResponse> {
-    let rcpt_raw = deps.api.canonical_address(&recipient)?;
+    let rcpt_raw = validate_address(&deps.api, &recipient, "recipient")?;
     let sender_raw = deps.api.canonical_address(&info.sender)?;
 
     let mut accounts = balances(&mut deps.storage);
@@ -90,6 +345,8 @@ pub fn transfer<S: Storage, A: Api, Q: Querier>(
     accounts.update(&rcpt_raw, |balance: Option<Uint128>| -> StdResult<_> {
         Ok(balance.unwrap_or_default() + send)
     })?;
+    snapshot_balance(&mut deps.storage, &sender_raw, env.block.height)?;
+    snapshot_balance(&mut deps.storage, &rcpt_raw, env.block.height)?;
 
     let res = HandleResponse {
         messages: vec![],
@@ -104,81 +361,449 @@ pub fn transfer<S: Storage, A: Api, Q: Querier>(
     Ok(res)
 }
 
-// get_bonded returns the total amount of delegations from contract
-// it ensures they are all the same denom
-fn get_bonded<Q: Querier>(querier: &Q, contract: &HumanAddr) -> StdResult<Uint128> {
-    let bonds = querier.query_all_delegations(contract)?;
-    if bonds.is_empty() {
-        return Ok(Uint128(0));
-    }
-    let denom = bonds[0].amount.denom.as_str();
-    bonds.iter().fold(Ok(Uint128(0)), |racc, d| {
-        let acc = racc?;
-        if d.amount.denom.as_str() != denom {
-            Err(StdError::generic_err(format!(
-                "different denoms in bonds: '{}' vs '{}'",
-                denom, &d.amount.denom
-            )))
-        } else {
-            Ok(acc + d.amount.amount)
-        }
-    })
+/// transfer_with_nonce transfers only if `nonce` is the sender's next one, and then
+/// advances it, rejecting reused and out-of-order nonces
+pub fn transfer_with_nonce<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    recipient: HumanAddr,
+    send: Uint128,
+    nonce: u64,
+) -> StdResult<HandleResponse> {
+    let sender_raw = deps.api.canonical_address(&info.sender)?;
+    let expected = nonces_read(&deps.storage)
+        .may_load(sender_raw.as_slice())?
+        .unwrap_or_default();
+    if nonce != expected {
+        return Err(StdError::generic_err(format!(
+            "Invalid nonce {}, expected {}",
+            nonce, expected
+        )));
+    }
+    nonces(&mut deps.storage).save(sender_raw.as_slice(), &(nonce + 1))?;
+
+    let mut res = transfer(deps, env, info, recipient, send)?;
+    res.attributes.push(attr("nonce", nonce));
+    Ok(res)
+}
+
+pub fn send<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    contract: HumanAddr,
+    amount: Uint128,
+    msg: Option<Binary>,
+) -> StdResult<HandleResponse> {
+    let rcpt_raw = validate_address(&deps.api, &contract, "contract")?;
+    let sender_raw = deps.api.canonical_address(&info.sender)?;
+
+    let mut accounts = balances(&mut deps.storage);
+    accounts.update(&sender_raw, |balance: Option<Uint128>| {
+        balance.unwrap_or_default() - amount
+    })?;
+    accounts.update(&rcpt_raw, |balance: Option<Uint128>| -> StdResult<_> {
+        Ok(balance.unwrap_or_default() + amount)
+    })?;
+    snapshot_balance(&mut deps.storage, &sender_raw, env.block.height)?;
+    snapshot_balance(&mut deps.storage, &rcpt_raw, env.block.height)?;
+
+    // let the receiving contract know it got the tokens
+    let callback = Cw20ReceiveMsg {
+        sender: info.sender.clone(),
+        amount,
+        msg,
+    }
+    .into_cosmos_msg(contract.clone())?;
+
+    let res = HandleResponse {
+        messages: vec![callback],
+        attributes: vec![
+            attr("action", "send"),
+            attr("from", info.sender),
+            attr("to", contract),
+            attr("amount", amount),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+pub fn increase_allowance<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    info: MessageInfo,
+    spender: HumanAddr,
+    amount: Uint128,
+    expires: Option<Expiration>,
+) -> StdResult<HandleResponse> {
+    let spender_raw = validate_address(&deps.api, &spender, "spender")?;
+    let owner_raw = deps.api.canonical_address(&info.sender)?;
+    if spender_raw == owner_raw {
+        return Err(StdError::generic_err("Cannot set allowance to own account"));
+    }
+
+    allowances(&mut deps.storage, &owner_raw).update(
+        spender_raw.as_slice(),
+        |allow| -> StdResult<_> {
+            let mut allow = allow.unwrap_or_default();
+            if let Some(exp) = expires {
+                allow.expires = exp;
+            }
+            allow.allowance += amount;
+            Ok(allow)
+        },
+    )?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "increase_allowance"),
+            attr("owner", info.sender),
+            attr("spender", spender),
+            attr("amount", amount),
+        ],
+        data: None,
+    };
+    Ok(res)
 }
 
-fn assert_bonds(supply: &Supply, bonded: Uint128) -> StdResult<()> {
-    if supply.bonded != bonded {
-        Err(StdError::generic_err(format!(
-            "Stored bonded {}, but query bonded: {}",
-            supply.bonded, bonded
-        )))
+pub fn decrease_allowance<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    info: MessageInfo,
+    spender: HumanAddr,
+    amount: Uint128,
+    expires: Option<Expiration>,
+) -> StdResult<HandleResponse> {
+    let spender_raw = validate_address(&deps.api, &spender, "spender")?;
+    let owner_raw = deps.api.canonical_address(&info.sender)?;
+    if spender_raw == owner_raw {
+        return Err(StdError::generic_err("Cannot set allowance to own account"));
+    }
+
+    let mut bucket = allowances(&mut deps.storage, &owner_raw);
+    let mut allow = bucket.may_load(spender_raw.as_slice())?.unwrap_or_default();
+    if amount < allow.allowance {
+        allow.allowance = (allow.allowance - amount)?;
+        if let Some(exp) = expires {
+            allow.expires = exp;
+        }
+        bucket.save(spender_raw.as_slice(), &allow)?;
     } else {
-        Ok(())
+        bucket.remove(spender_raw.as_slice());
     }
+
+    let res = HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "decrease_allowance"),
+            attr("owner", info.sender),
+            attr("spender", spender),
+            attr("amount", amount),
+        ],
+        data: None,
+    };
+    Ok(res)
 }
 
-pub fn bond<S: Storage, A: Api, Q: Querier>(
+pub fn transfer_from<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
     info: MessageInfo,
+    owner: HumanAddr,
+    recipient: HumanAddr,
+    send: Uint128,
 ) -> StdResult<HandleResponse> {
+    let rcpt_raw = validate_address(&deps.api, &recipient, "recipient")?;
+    let owner_raw = validate_address(&deps.api, &owner, "owner")?;
+    let spender_raw = deps.api.canonical_address(&info.sender)?;
+
+    // an expired allowance is treated as zero
+    allowances(&mut deps.storage, &owner_raw).update(
+        spender_raw.as_slice(),
+        |allow| -> StdResult<_> {
+            let mut allow = allow.unwrap_or_default();
+            if allow.expires.is_expired(&env.block) {
+                allow.allowance = Uint128::zero();
+            }
+            allow.allowance = (allow.allowance - send)?;
+            Ok(allow)
+        },
+    )?;
+
+    let mut accounts = balances(&mut deps.storage);
+    accounts.update(&owner_raw, |balance: Option<Uint128>| {
+        balance.unwrap_or_default() - send
+    })?;
+    accounts.update(&rcpt_raw, |balance: Option<Uint128>| -> StdResult<_> {
+        Ok(balance.unwrap_or_default() + send)
+    })?;
+    snapshot_balance(&mut deps.storage, &owner_raw, env.block.height)?;
+    snapshot_balance(&mut deps.storage, &rcpt_raw, env.block.height)?;
+
+    let res = HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "transfer_from"),
+            attr("from", owner),
+            attr("to", recipient),
+            attr("by", info.sender),
+            attr("amount", send),
+        ],
+        data: None,
+    };
+    Ok(res)
+}
+
+// get_bonded returns the total amount of bond_denom delegations from contract.
+// Delegations in other denoms are ignored, so queries never lock up. Accounting reads
+// the stored supply instead
+fn get_bonded<Q: Querier>(
+    querier: &Q,
+    contract: &HumanAddr,
+    bond_denom: &str,
+) -> StdResult<Uint128> {
+    Ok(querier
+        .query_all_delegations(contract)?
+        .iter()
+        .filter(|d| d.amount.denom == bond_denom)
+        .map(|d| d.amount.amount)
+        .sum())
+}
+
+// validator_rewards returns the bond_denom rewards the contract has accumulated with each
+// validator it delegates to
+fn validator_rewards<Q: Querier>(
+    querier: &Q,
+    contract: &HumanAddr,
+    denom: &str,
+) -> StdResult<Vec<(HumanAddr, Uint128)>> {
+    let mut rewards = vec![];
+    for delegation in querier.query_all_delegations(contract)? {
+        let full = querier.query_delegation(contract.clone(), delegation.validator.clone())?;
+        let reward: Uint128 = full
+            .map(|d| d.accumulated_rewards)
+            .unwrap_or_default()
+            .iter()
+            .filter(|c| c.denom == denom)
+            .map(|c| c.amount)
+            .sum();
+        rewards.push((delegation.validator, reward));
+    }
+    Ok(rewards)
+}
+
+// pending_rewards returns the bond_denom rewards the contract can withdraw from each
+// validator it delegates to, skipping validators that have nothing accumulated
+fn pending_rewards<Q: Querier>(
+    querier: &Q,
+    contract: &HumanAddr,
+    denom: &str,
+) -> StdResult<Vec<(HumanAddr, Uint128)>> {
+    Ok(validator_rewards(querier, contract, denom)?
+        .into_iter()
+        .filter(|(_, reward)| !reward.is_zero())
+        .collect())
+}
+
+fn assert_validator_active<Q: Querier>(querier: &Q, validator: &HumanAddr) -> StdResult<()> {
+    if !querier
+        .query_validators()?
+        .iter()
+        .any(|v| &v.address == validator)
+    {
+        return Err(StdError::generic_err(format!(
+            "{} is not in the current validator set",
+            validator
+        )));
+    }
+    Ok(())
+}
+
+// delegate_msg builds a Delegate after checking what the staking module would reject
+// it for, so a bad delegation fails with a clear error rather than a raw one from the
+// chain. CosmWasm 0.11 has no submessages to catch the failure afterwards
+fn delegate_msg<Q: Querier>(
+    querier: &Q,
+    validator: HumanAddr,
+    amount: Coin,
+) -> StdResult<CosmosMsg> {
+    if amount.amount.is_zero() {
+        return Err(StdError::generic_err(format!(
+            "Cannot delegate zero {} to {}",
+            amount.denom, validator
+        )));
+    }
+    assert_validator_active(querier, &validator)?;
+    Ok(StakingMsg::Delegate { validator, amount }.into())
+}
+
+// undelegate_msgs takes `amount` of bond_denom stake off the contract's delegations,
+// from `first` (the delegator's validator) before any other, and errors if not enough
+// is delegated to cover it
+fn undelegate_msgs<Q: Querier>(
+    querier: &Q,
+    contract: &HumanAddr,
+    denom: &str,
+    first: &HumanAddr,
+    amount: Uint128,
+) -> StdResult<Vec<CosmosMsg>> {
+    let mut delegations: Vec<_> = querier
+        .query_all_delegations(contract)?
+        .into_iter()
+        .filter(|d| d.amount.denom == denom)
+        .collect();
+    // the sort is stable, so the rest keep the order the chain lists them in
+    delegations.sort_by_key(|d| &d.validator != first);
+
+    let mut remaining = amount;
+    let mut messages = vec![];
+    for delegation in delegations.into_iter() {
+        let share = remaining.min(delegation.amount.amount);
+        if share.is_zero() {
+            continue;
+        }
+        remaining = (remaining - share)?;
+        messages.push(
+            StakingMsg::Undelegate {
+                validator: delegation.validator,
+                amount: coin(share.u128(), denom),
+            }
+            .into(),
+        );
+    }
+    if !remaining.is_zero() {
+        return Err(StdError::generic_err(format!(
+            "Cannot undelegate {} {}, only {} is delegated",
+            amount,
+            denom,
+            (amount - remaining)?
+        )));
+    }
+    Ok(messages)
+}
+
+pub fn bond<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    validator: Option<HumanAddr>,
+) -> Result<HandleResponse, StakingError> {
     let delegator_raw = deps.api.canonical_address(&info.sender)?;
-    let best_validator = select_validator(deps)?;
+    let best_validator = match validator {
+        Some(addr) => addr,
+        None => select_validator(deps)?.address,
+    };
+    // make sure the pick is still in the active set the Delegate will be executed against
+    assert_validator_active(&deps.querier, &best_validator)?;
 
     let invest = invest_info_read(&deps.storage).load()?;
+    if invest.deny_list.contains(&best_validator) {
+        return Err(StdError::generic_err(format!("{} is denied", best_validator)).into());
+    }
     let info_clone = info.clone();
     let payment = info_clone
         .sent_funds
         .iter()
         .find(|x| x.denom == invest.bond_denom)
         .ok_or_else(|| StdError::generic_err(format!("No {} tokens sent", &invest.bond_denom)))?;
+    if payment.amount.is_zero() {
+        return Err(StdError::generic_err("cannot bond zero").into());
+    }
+
+    // first bond registers the sender as a delegator, as long as there is room
+    let is_new = delegations_read(&deps.storage)
+        .may_load(delegator_raw.as_slice())?
+        .is_none();
+    let mut delegator_list = delegators_read(&deps.storage)
+        .may_load()?
+        .unwrap_or_default();
+    if let Some(max) = invest.max_delegators {
+        if is_new && delegator_list.len() >= max as usize {
+            return Err(TooManyDelegators { max }.build());
+        }
+    }
+
+    // calculate to_mint and update total supply
+    let mut totals = total_supply(&mut deps.storage);
+    let mut supply = totals.load()?;
+    let to_mint = if supply.issued.is_zero() || supply.bonded.is_zero() {
+        FALLBACK_RATIO * payment.amount
+    } else {
+        checked_mint(payment.amount, supply.issued, supply.bonded)?
+    };
+    supply.bonded = checked_add(supply.bonded, payment.amount, "Token supply")?;
+    supply.issued = checked_add(supply.issued, to_mint, "Token supply")?;
+    totals.save(&supply)?;
+
+    // update the balance of the sender
+    balances(&mut deps.storage).update(delegator_raw.as_slice(), |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default() + to_mint)
+    })?;
+    snapshot_balance(&mut deps.storage, &delegator_raw, env.block.height)?;
 
+    if is_new {
+        delegator_list.push(info.sender.clone());
+        delegators(&mut deps.storage).save(&delegator_list)?;
+        delegator_count(&mut deps.storage).save(&(delegator_list.len() as u64))?;
+    }
+
+    let index = total_supply_read(&deps.storage).load()?.reward_index;
     delegations(&mut deps.storage).update(
         delegator_raw.as_slice(),
         |delegate_info| -> StdResult<_> {
-            let mut new_delegate_info = delegate_info.unwrap();
-            new_delegate_info.undelegate_reward = Uint128::zero();
-            new_delegate_info.amount = payment.clone().amount;
-            new_delegate_info.validator = best_validator.address.clone();
+            let mut new_delegate_info = delegate_info.unwrap_or_else(|| DelegateInfo {
+                delegator: info.sender.clone(),
+                last_reward_index: index,
+                first_bond_height: env.block.height,
+                ..DelegateInfo::default()
+            });
+            // settle what the old amount earned before it changes
+            new_delegate_info.undelegate_reward += accrued_reward(
+                new_delegate_info.amount,
+                new_delegate_info.last_reward_index,
+                index,
+            );
+            new_delegate_info.last_reward_index = index;
+            new_delegate_info.amount += payment.amount;
+            new_delegate_info.cost_basis += payment.amount;
+            new_delegate_info.validator = best_validator.clone();
             new_delegate_info.last_delegate_height = env.clone().block.height;
+            new_delegate_info.last_bond_height = env.block.height;
             Ok(new_delegate_info)
         },
     )?;
 
-    is_expired(deps, env, info.clone());
+    // expired delegations are left to ProcessExpired, so a bond only ever carries
+    // its own Delegates and never half of a sweep's state changes
+    let allocations = allocate_under_cap(
+        deps,
+        &env.contract.address,
+        &invest,
+        &best_validator,
+        payment.amount,
+        supply.bonded,
+    )?;
 
-    let attributes = vec![
+    let mut attributes = vec![
         attr("action", "bond"),
         attr("from", info.sender),
-        attr("validator", best_validator.address.clone()),
+        attr("validator", best_validator.clone()),
         attr("bonded", payment.clone().amount),
+        attr("denom", &payment.denom),
     ];
+    attributes.extend(supply_attributes(&deps.storage)?);
 
     let r = HandleResponse {
-        messages: vec![StakingMsg::Delegate {
-            validator: best_validator.address.clone(),
-            amount: payment.clone(),
-        }
-        .into()],
+        messages: allocations
+            .into_iter()
+            .map(|(validator, amount)| {
+                let amount = coin(amount.u128(), &payment.denom);
+                delegate_msg(&deps.querier, validator, amount)
+            })
+            .collect::<StdResult<_>>()?,
         attributes,
         data: None,
     };
@@ -186,129 +811,651 @@ pub fn bond<S: Storage, A: Api, Q: Querier>(
     Ok(r)
 }
 
+// allocate_under_cap splits a bond of `amount` so no validator ends up with more than
+// max_validator_share of `total` (the bonded stake including this bond). `first` is
+// filled first, then the ranked validators in order. Without a cap it all goes to `first`
+fn allocate_under_cap<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    contract: &HumanAddr,
+    invest: &InvestmentInfo,
+    first: &HumanAddr,
+    amount: Uint128,
+    total: Uint128,
+) -> StdResult<Vec<(HumanAddr, Uint128)>> {
+    if invest.max_validator_share == Decimal::one() {
+        return Ok(vec![(first.clone(), amount)]);
+    }
+
+    let cap = total * invest.max_validator_share;
+    let delegations = deps.querier.query_all_delegations(contract)?;
+    let mut candidates = vec![first.clone()];
+    for validator in rank_validators(deps)?.into_iter() {
+        if &validator.address != first {
+            candidates.push(validator.address);
+        }
+    }
+
+    let mut remaining = amount;
+    let mut allocations = vec![];
+    for validator in candidates.into_iter() {
+        if remaining.is_zero() {
+            break;
+        }
+        let current: Uint128 = delegations
+            .iter()
+            .filter(|d| d.validator == validator)
+            .map(|d| d.amount.amount)
+            .sum();
+        let room = (cap - current).unwrap_or_default();
+        let share = remaining.min(room);
+        if !share.is_zero() {
+            remaining = (remaining - share)?;
+            allocations.push((validator, share));
+        }
+    }
+    if !remaining.is_zero() {
+        return Err(StdError::generic_err(
+            "Bond would put more than max_validator_share on every validator",
+        ));
+    }
+    Ok(allocations)
+}
+
+/// reserve_unbond burns `amount` of the sender's derivative tokens (all of them by
+/// default) after the exit tax, undelegates the native tokens they are worth and claims
+/// them for the sender once the unbonding period has passed. Unbonding everything also
+/// flags the delegation, so the sweep settles its remaining rewards
 pub fn reserve_unbond<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
     info: MessageInfo,
+    amount: Option<Uint128>,
 ) -> StdResult<HandleResponse> {
-    let delegator = info.clone().sender;
-    claim(deps, env.clone(), delegator);
+    assert_min_hold(deps, &env, &info.sender)?;
 
+    let invest = invest_info_read(&deps.storage).load()?;
     let delegator_raw = deps.api.canonical_address(&info.sender)?;
+    let balance = balances_read(&deps.storage)
+        .may_load(delegator_raw.as_slice())?
+        .unwrap_or_default();
+    let amount = amount.unwrap_or(balance);
+    let remaining = (balance - amount)?;
+
+    let _ = claim(deps, env.clone(), info.sender.clone());
+    let mut newly_flagged = false;
+    let mut first_bond_height = 0;
+    let mut validator = HumanAddr::default();
     delegations(&mut deps.storage).update(
         delegator_raw.as_slice(),
         |delegate_info| -> StdResult<_> {
-            let mut new_delegate_info = delegate_info.unwrap();
-            new_delegate_info.unbond_flag = true;
+            let mut new_delegate_info =
+                delegate_info.ok_or_else(|| StdError::not_found("DelegateInfo"))?;
+            first_bond_height = new_delegate_info.first_bond_height;
+            validator = new_delegate_info.validator.clone();
+            // the stake behind the burned tokens stops earning from the reward index
+            if !balance.is_zero() {
+                let kept = |value: Uint128| value.multiply_ratio(remaining, balance);
+                new_delegate_info.amount = kept(new_delegate_info.amount);
+                new_delegate_info.cost_basis = kept(new_delegate_info.cost_basis);
+            }
+            if remaining.is_zero() && !new_delegate_info.unbond_flag {
+                newly_flagged = true;
+                new_delegate_info.unbond_flag = true;
+            }
             Ok(new_delegate_info)
         },
     )?;
+    if newly_flagged {
+        pending_unbonds(&mut deps.storage).update(|count| -> StdResult<_> { Ok(count + 1) })?;
+    }
 
-    return is_expired(deps, env, info);
-}
+    // the exit tax is charged in derivative tokens. It moves to the fee recipients and
+    // stays issued, only the rest is burned, so issued falls by amount - tax and bonded
+    // by the native value of what is burned, which is what the sender is owed
+    let held = env.block.height.saturating_sub(first_bond_height);
+    let tax = exit_tax_on(amount, invest.exit_tax_for(held));
+    let burned = (amount - tax)?;
+    let mut supply = total_supply(&mut deps.storage).load()?;
+    let unbonded = native_value(burned, &supply);
+    supply.bonded = (supply.bonded - unbonded)?;
+    supply.issued = (supply.issued - burned)?;
+    total_supply(&mut deps.storage).save(&supply)?;
 
-fn claim<S: Storage, A: Api, Q: Querier>(
-    deps: &mut Extern<S, A, Q>,
-    env: Env,
-    delegator: HumanAddr,
-) -> Result<HandleResponse, StakingError> {
-    let validator_addr = query_delegation(deps, delegator).unwrap().validator;
-    let all_delegations = query_all_delegations(deps).unwrap();
-    let delegations_of_val = all_delegations
-        .iter()
-        .filter(|delegation| delegation.validator == validator_addr);
+    balances(&mut deps.storage).save(delegator_raw.as_slice(), &remaining)?;
+    snapshot_balance(&mut deps.storage, &delegator_raw, env.block.height)?;
+    if !tax.is_zero() {
+        pay_fee(deps, tax, &invest, env.block.height)?;
+    }
 
-    let mut total_amount = Uint128::zero();
-    for delegation in delegations_of_val.clone() {
-        total_amount += delegation.amount
+    let mut messages = vec![];
+    if !unbonded.is_zero() {
+        let contract = &env.contract.address;
+        let denom = &invest.bond_denom;
+        messages = undelegate_msgs(&deps.querier, contract, denom, &validator, unbonded)?;
+        let release_height = env.block.height + invest.blocks_for_duration(UNBONDING_SECONDS);
+        add_claim(&mut deps.storage, &delegator_raw, unbonded, release_height)?;
     }
 
-    // find how many tokens we have to bond
-    let invest = invest_info_read(&deps.storage).load()?;
-    let balance = deps
-        .querier
-        .query_balance(&env.contract.address, &invest.bond_denom)?;
+    let symbol = token_info_read(&deps.storage).load()?.symbol;
+    let mut res = is_expired(deps, env, None)?;
+    messages.append(&mut res.messages);
+    res.messages = messages;
+    res.attributes.push(attr("exit_tax", tax));
+    res.attributes.push(attr("burned", burned));
+    res.attributes.push(attr("denom", symbol));
+    res.attributes.push(attr("unbonded", unbonded));
+    Ok(res)
+}
 
-    let reward = (balance.amount - total_amount).unwrap();
+// free_balance is the contract's bond_denom balance that is not reserved for claims or
+// fees, and so can be delegated or paid out as rewards
+fn free_balance<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    contract: &HumanAddr,
+    invest: &InvestmentInfo,
+) -> StdResult<Uint128> {
+    let balance = deps.querier.query_balance(contract, &invest.bond_denom)?;
+    let supply = total_supply_read(&deps.storage).load()?;
+    Ok(unreserved(balance.amount, &supply))
+}
 
-    for delegation in delegations_of_val {
-        let key = deps.api.canonical_address(&delegation.delegator)?;
-        delegations(&mut deps.storage).update(key.as_slice(), |delegate_info| -> StdResult<_> {
-            let mut new_delegate_info = delegate_info.unwrap();
-            new_delegate_info.undelegate_reward = reward
-                .clone()
-                .multiply_ratio(delegation.amount, total_amount);
-            Ok(new_delegate_info)
-        })?;
-    }
+// unreserved is what is left of `amount` once claims and fees are set aside. Every path
+// that spends the contract's balance on rewards goes through it, so money owed to
+// unbonding delegators and fee recipients is never bonded again
+fn unreserved(amount: Uint128, supply: &Supply) -> Uint128 {
+    (amount - supply.claims)
+        .and_then(|free| free - supply.fees)
+        .unwrap_or_default()
+}
 
-    Ok(HandleResponse {
-        messages: vec![],
-        attributes: vec![],
-        data: None,
-    })
+// supply_or_default is the supply for queries, which read a missing singleton as an
+// empty pool. Accounting paths load it strictly instead
+fn supply_or_default<S: ReadonlyStorage>(storage: &S) -> StdResult<Supply> {
+    Ok(total_supply_read(storage).may_load()?.unwrap_or_default())
 }
 
-/// reinvest will withdraw all pending rewards,
-/// then issue a callback to itself via _bond_all_tokens
+// supply_attributes reports the supply after a change, so indexers can follow it from
+// the events alone
+fn supply_attributes<S: ReadonlyStorage>(storage: &S) -> StdResult<Vec<Attribute>> {
+    let supply = total_supply_read(storage).load()?;
+    Ok(vec![
+        attr("issued", supply.issued),
+        attr("bonded", supply.bonded),
+        attr("claims", supply.claims),
+    ])
+}
+
+// assert_min_hold refuses to unbond within min_hold_blocks of the latest bond
+fn assert_min_hold<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: &Env,
+    delegator: &HumanAddr,
+) -> StdResult<()> {
+    let min_hold = invest_info_read(&deps.storage).load()?.min_hold_blocks;
+    let delegator_raw = deps.api.canonical_address(delegator)?;
+    let last_bond_height = delegations_read(&deps.storage)
+        .may_load(delegator_raw.as_slice())?
+        .map(|d| d.last_bond_height)
+        .unwrap_or_default();
+    let held = env.block.height.saturating_sub(last_bond_height);
+    if held < min_hold {
+        return Err(StdError::generic_err(format!(
+            "Cannot unbond for another {} blocks",
+            min_hold - held
+        )));
+    }
+    Ok(())
+}
+
+/// instant_unbond burns all of the sender's derivative tokens and pays what they are
+/// worth, with its rewards, out of the free balance right away, keeping
+/// instant_unbond_fee in the pool. The exit tax is charged as in reserve_unbond. The
+/// stake behind the tokens is undelegated and refills the free balance once released.
+/// When the free balance, less the rewards owed to the other delegators, can't cover
+/// the payout, it is a normal delayed unbond instead
+pub fn instant_unbond<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+) -> StdResult<HandleResponse> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    let fee_rate = invest
+        .instant_unbond_fee
+        .ok_or_else(|| StdError::generic_err("Instant unbond is not offered"))?;
+    assert_min_hold(deps, &env, &info.sender)?;
+    let _ = claim(deps, env.clone(), info.sender.clone());
+
+    let delegator_raw = deps.api.canonical_address(&info.sender)?;
+    let mut delegate_info = delegations(&mut deps.storage).load(delegator_raw.as_slice())?;
+    if delegate_info.unbond_flag {
+        return Err(StdError::generic_err("Unbond already requested"));
+    }
+    let balance = balances_read(&deps.storage)
+        .may_load(delegator_raw.as_slice())?
+        .unwrap_or_default();
+    let first_bond_height = delegate_info.first_bond_height;
+    let held = env.block.height.saturating_sub(first_bond_height);
+    let tax = exit_tax_on(balance, invest.exit_tax_for(held));
+    let burned = (balance - tax)?;
+    let mut supply = total_supply_read(&deps.storage).load()?;
+    let unbonded = native_value(burned, &supply);
+    let reward = delegate_info.undelegate_reward;
+    let payout = checked_add(unbonded, reward, "Payout")?;
+    let fee = payout * fee_rate;
+    let net = (payout - fee)?;
+    // the sender's own reward is part of the payout, the others' stay reserved
+    let others = (owed_rewards(deps)? - reward)?;
+    let free = free_balance(deps, &env.contract.address, &invest)?;
+    if net > (free - others).unwrap_or_default() {
+        return reserve_unbond(deps, env, info, None);
+    }
+
+    // taxed and burned like in an unbond, only the payout is fronted instead of claimed
+    supply.bonded = (supply.bonded - unbonded)?;
+    supply.issued = (supply.issued - burned)?;
+    total_supply(&mut deps.storage).save(&supply)?;
+    balances(&mut deps.storage).save(delegator_raw.as_slice(), &Uint128::zero())?;
+    snapshot_balance(&mut deps.storage, &delegator_raw, env.block.height)?;
+    if !tax.is_zero() {
+        pay_fee(deps, tax, &invest, env.block.height)?;
+    }
+
+    let validator = delegate_info.validator.clone();
+    delegate_info.amount = Uint128::zero();
+    delegate_info.undelegate_reward = Uint128::zero();
+    delegate_info.cost_basis = Uint128::zero();
+    delegations(&mut deps.storage).save(delegator_raw.as_slice(), &delegate_info)?;
+
+    let mut res = send_tokens(
+        env.contract.address.clone(),
+        info.sender,
+        vec![coin(net.u128(), &invest.bond_denom)],
+        "instant_unbond",
+    )?;
+    if !unbonded.is_zero() {
+        let contract = &env.contract.address;
+        let denom = &invest.bond_denom;
+        let undelegate = undelegate_msgs(&deps.querier, contract, denom, &validator, unbonded)?;
+        res.messages.extend(undelegate);
+    }
+    res.attributes.push(attr("amount", net));
+    res.attributes.push(attr("fee", fee));
+    res.attributes.push(attr("exit_tax", tax));
+    res.attributes.push(attr("burned", burned));
+    res.attributes.push(attr("unbonded", unbonded));
+    Ok(res)
+}
+
+// exit_tax_on is the part of `amount` taken as exit tax
+fn exit_tax_on(amount: Uint128, exit_tax: Decimal) -> Uint128 {
+    amount * exit_tax
+}
+
+// native_value converts derivative tokens to the staking tokens backing them
+fn native_value(amount: Uint128, supply: &Supply) -> Uint128 {
+    if supply.issued.is_zero() {
+        FALLBACK_RATIO * amount
+    } else {
+        amount.multiply_ratio(supply.bonded, supply.issued)
+    }
+}
+
+// checked_mint converts amount at the issued / bonded ratio, erroring instead of
+// overflowing for extreme ratios
+fn checked_mint(amount: Uint128, issued: Uint128, bonded: Uint128) -> StdResult<Uint128> {
+    amount
+        .u128()
+        .checked_mul(issued.u128())
+        .map(|product| Uint128(product / bonded.u128()))
+        .ok_or_else(|| {
+            StdError::generic_err(format!(
+                "Minting for {} at ratio {} / {} overflows",
+                amount, issued, bonded
+            ))
+        })
+}
+
+// compound_reward bonds `amount` of a delegator's settled rewards for them. It mints
+// derivative tokens at the current ratio like a bond, so the other holders' value is
+// unchanged. Returns the minted amount
+fn compound_reward<S: Storage>(
+    storage: &mut S,
+    key: &CanonicalAddr,
+    amount: Uint128,
+    height: u64,
+) -> StdResult<Uint128> {
+    let mut supply = total_supply_read(storage).load()?;
+    let to_mint = if supply.issued.is_zero() || supply.bonded.is_zero() {
+        FALLBACK_RATIO * amount
+    } else {
+        checked_mint(amount, supply.issued, supply.bonded)?
+    };
+    supply.bonded = checked_add(supply.bonded, amount, "Bonded supply")?;
+    supply.issued = checked_add(supply.issued, to_mint, "Token supply")?;
+    total_supply(storage).save(&supply)?;
+    balances(storage).update(key.as_slice(), |balance| -> StdResult<_> {
+        Ok(balance.unwrap_or_default() + to_mint)
+    })?;
+    snapshot_balance(storage, key, height)?;
+    Ok(to_mint)
+}
+
+// checked_add errors with "`what` overflows" instead of panicking
+fn checked_add(a: Uint128, b: Uint128, what: &str) -> StdResult<Uint128> {
+    a.u128()
+        .checked_add(b.u128())
+        .map(Uint128)
+        .ok_or_else(|| StdError::generic_err(format!("{} overflows", what)))
+}
+
+// weighted_shares splits `total` across addresses by weight (e.g. the fee recipients),
+// the rounding remainder goes to the first one
+fn weighted_shares(total: Uint128, weights: &[(HumanAddr, Decimal)]) -> Vec<Uint128> {
+    let mut shares: Vec<Uint128> = weights.iter().map(|(_, weight)| total * *weight).collect();
+    let assigned = shares
+        .iter()
+        .fold(Uint128::zero(), |acc, share| acc + *share);
+    if let Some(first) = shares.first_mut() {
+        *first += (total - assigned).unwrap_or_default();
+    }
+    shares
+}
+
+// pay_fee credits `fee` derivative tokens to the fee recipients by weight
+fn pay_fee<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    fee: Uint128,
+    invest: &InvestmentInfo,
+    height: u64,
+) -> StdResult<()> {
+    let shares = weighted_shares(fee, &invest.fee_recipients);
+    for ((addr, _), amount) in invest.fee_recipients.iter().zip(shares) {
+        let key = deps.api.canonical_address(addr)?;
+        balances(&mut deps.storage).update(key.as_slice(), |balance| -> StdResult<_> {
+            Ok(balance.unwrap_or_default() + amount)
+        })?;
+        snapshot_balance(&mut deps.storage, &key, height)?;
+        if key == invest.owner {
+            fees_earned(&mut deps.storage).update(|fees| -> StdResult<_> { Ok(fees + amount) })?;
+        }
+    }
+    Ok(())
+}
+
+/// withdraw_fees pays the native fees held by the contract out to the fee recipients.
+/// Only the owner can call it
+pub fn withdraw_fees<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+) -> Result<HandleResponse, StakingError> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    if deps.api.canonical_address(&info.sender)? != invest.owner {
+        return Err(Unauthorized {}.build());
+    }
+
+    // never touch what is reserved for claims, even if the fee books say otherwise
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, &invest.bond_denom)?;
+    let mut supply = total_supply_read(&deps.storage).load()?;
+    let free = (balance.amount - supply.claims).unwrap_or_default();
+    let amount = supply.fees.min(free);
+    if amount.is_zero() {
+        return Err(StdError::generic_err("No fees to withdraw").into());
+    }
+    supply.fees = (supply.fees - amount)?;
+    total_supply(&mut deps.storage).save(&supply)?;
+
+    let messages = invest
+        .fee_recipients
+        .iter()
+        .zip(weighted_shares(amount, &invest.fee_recipients))
+        .filter(|(_, share)| !share.is_zero())
+        .map(|((recipient, _), share)| {
+            BankMsg::Send {
+                from_address: env.contract.address.clone(),
+                to_address: recipient.clone(),
+                amount: vec![coin(share.u128(), &invest.bond_denom)],
+            }
+            .into()
+        })
+        .collect();
+
+    Ok(HandleResponse {
+        messages,
+        attributes: vec![
+            attr("action", "withdraw_fees"),
+            attr("amount", amount),
+            attr("denom", &invest.bond_denom),
+        ],
+        data: None,
+    })
+}
+
+/// claim settles the rewards the delegator earned since it last did, using the reward
+/// index, into its undelegate_reward
+fn claim<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    delegator: HumanAddr,
+) -> Result<HandleResponse, StakingError> {
+    let index = total_supply_read(&deps.storage).load()?.reward_index;
+    let key = deps.api.canonical_address(&delegator)?;
+    delegations(&mut deps.storage).update(key.as_slice(), |delegate_info| -> StdResult<_> {
+        let mut new_delegate_info =
+            delegate_info.ok_or_else(|| StdError::not_found("Delegation"))?;
+        new_delegate_info.undelegate_reward += accrued_reward(
+            new_delegate_info.amount,
+            new_delegate_info.last_reward_index,
+            index,
+        );
+        new_delegate_info.last_reward_index = index;
+        Ok(new_delegate_info)
+    })?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![],
+        data: None,
+    })
+}
+
+// accrued_reward is what `amount` of stake earned since the reward index was last
+// settled at `last_reward_index`
+fn accrued_reward(amount: Uint128, last_reward_index: Decimal, index: Decimal) -> Uint128 {
+    (index * amount - last_reward_index * amount).unwrap_or_default()
+}
+
+// owed_rewards is what the reward index owes the delegators, settled into
+// undelegate_reward or still accruing. It stays in the free balance until each
+// delegator is paid, so it is never bonded for the pool
+fn owed_rewards<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>) -> StdResult<Uint128> {
+    let index = supply_or_default(&deps.storage)?.reward_index;
+    let mut owed = Uint128::zero();
+    for item in delegations_read(&deps.storage).range(None, None, Order::Ascending) {
+        let (_, delegation) = item?;
+        let accrued = accrued_reward(delegation.amount, delegation.last_reward_index, index);
+        owed += delegation.undelegate_reward + accrued;
+    }
+    Ok(owed)
+}
+
+// take_reward_fee sets the reward_fee share of newly withdrawn rewards aside for the
+// fee recipients and returns it
+fn take_reward_fee<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    reward: Uint128,
+) -> StdResult<Uint128> {
+    let fee = reward * invest_info_read(&deps.storage).load()?.reward_fee;
+    if !fee.is_zero() {
+        total_supply(&mut deps.storage).update(|mut supply| -> StdResult<_> {
+            supply.fees = checked_add(supply.fees, fee, "Fees")?;
+            Ok(supply)
+        })?;
+    }
+    Ok(fee)
+}
+
+// bump_reward_index spreads newly withdrawn rewards, after the fee, over every
+// delegated token
+fn bump_reward_index<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    reward: Uint128,
+) -> StdResult<()> {
+    let delegator_list = delegators_read(&deps.storage)
+        .may_load()?
+        .unwrap_or_default();
+    let mut total_amount = Uint128::zero();
+    for delegator in delegator_list.iter() {
+        let key = deps.api.canonical_address(delegator)?;
+        total_amount += delegations_read(&deps.storage).load(key.as_slice())?.amount;
+    }
+    if total_amount.is_zero() || reward.is_zero() {
+        return Ok(());
+    }
+
+    total_supply(&mut deps.storage).update(|mut supply| -> StdResult<_> {
+        supply.reward_index = supply.reward_index + Decimal::from_ratio(reward, total_amount);
+        Ok(supply)
+    })?;
+    Ok(())
+}
+
+/// reinvest will withdraw all pending rewards,
+/// then issue a callback to itself via _bond_all_tokens
 /// to reinvest the new earnings (and anything else that accumulated)
 fn reinvest<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    info: MessageInfo,
     delegator: HumanAddr,
 ) -> StdResult<HandleResponse> {
+    // an idle delegation has nothing to compound (and accrues nothing from the index),
+    // so skip the storage writes and zero-value messages
+    let delegator_raw = deps.api.canonical_address(&delegator)?;
+    let idle = delegations_read(&deps.storage)
+        .may_load(delegator_raw.as_slice())?
+        .map(|d| d.amount.is_zero() && d.undelegate_reward.is_zero())
+        .unwrap_or_default();
+    if idle {
+        return Ok(HandleResponse::default());
+    }
+
     let _ = claim(deps, env.clone(), delegator.clone());
 
     let best_validator = select_validator(deps)?;
 
-    let delegator_raw = deps.api.canonical_address(&delegator)?;
     let delegate_info = delegations_read(&deps.storage)
         .may_load(delegator_raw.as_slice())
         .unwrap_or_default()
         .unwrap();
     let prev_validator = delegate_info.validator;
-    let undelegated_amount = delegate_info.undelegate_reward;
     let delegated_amount = delegate_info.amount;
 
+    // the reward may not have been withdrawn yet, so only what the free balance covers,
+    // after the rewards owed to the others, is compounded now and the rest stays owed
+    let invest = invest_info_read(&deps.storage).load()?;
+    let others = (owed_rewards(deps)? - delegate_info.undelegate_reward).unwrap_or_default();
+    let free = free_balance(deps, &env.contract.address, &invest)?;
+    let undelegated_amount = delegate_info
+        .undelegate_reward
+        .min((free - others).unwrap_or_default());
+    let still_owed = (delegate_info.undelegate_reward - undelegated_amount)?;
+
+    // the staking module rejects redelegating stake that is still in a redelegation
+    // cooldown, so within that window the stake stays where it is
+    let cooldown = invest.blocks_for_duration(REDELEGATE_COOLDOWN_SECONDS);
+    let in_cooldown = match delegate_info.last_redelegate_height {
+        Some(height) => env.block.height < height + cooldown,
+        None => false,
+    };
+    // fresh stake keeps compounding where it is until it is old enough to move
+    let min_age = invest.min_age_before_redelegate;
+    let too_young = env.block.height < delegate_info.last_delegate_height + min_age;
+    // stake on a validator that left the set is unbonded rather than redelegated, and
+    // comes back through _bond_all_tokens like an evacuation
+    let stale = !deps
+        .querier
+        .query_validators()?
+        .iter()
+        .any(|v| v.address == prev_validator);
+    let dst_validator = if !stale && (in_cooldown || too_young) {
+        prev_validator.clone()
+    } else {
+        best_validator.address.clone()
+    };
+    // a redelegation onto the same validator is rejected by the chain, so skip it
+    let redelegate = dst_validator != prev_validator && !stale;
+    let evacuate = stale && !delegated_amount.is_zero();
+    if evacuate {
+        total_supply(&mut deps.storage).update(|mut supply| -> StdResult<_> {
+            supply.evacuating += delegated_amount;
+            Ok(supply)
+        })?;
+    }
+    let total = checked_add(undelegated_amount, delegated_amount, "Reinvested amount")?;
+
     delegations(&mut deps.storage).update(
         delegator_raw.as_slice(),
         |delegate_info| -> StdResult<_> {
             let mut new_delegate_info = delegate_info.unwrap();
-            new_delegate_info.undelegate_reward = Uint128::zero();
-            new_delegate_info.amount += undelegated_amount;
-            new_delegate_info.validator = best_validator.address.clone();
+            new_delegate_info.undelegate_reward = still_owed;
+            new_delegate_info.amount = total;
+            new_delegate_info.validator = dst_validator.clone();
             new_delegate_info.last_delegate_height = env.block.height;
+            if redelegate {
+                new_delegate_info.last_redelegate_height = Some(env.block.height);
+            }
             Ok(new_delegate_info)
         },
     )?;
-
-    let token_info_res = query_token_info(deps)?;
+    if !undelegated_amount.is_zero() {
+        compound_reward(
+            &mut deps.storage,
+            &delegator_raw,
+            undelegated_amount,
+            env.block.height,
+        )?;
+    }
 
     let attributes = vec![
         attr("action", "reinvest"),
         attr("prev_validator", prev_validator.clone()),
-        attr("new_validator", best_validator.address.clone()),
-        attr(
-            "amount",
-            undelegated_amount.clone() + delegated_amount.clone(),
-        ),
+        attr("new_validator", dst_validator.clone()),
+        attr("amount", total),
     ];
 
-    let r = HandleResponse {
-        messages: vec![
+    let mut messages = vec![];
+    if !undelegated_amount.is_zero() {
+        messages.push(
             StakingMsg::Delegate {
-                amount: coin(undelegated_amount.u128(), token_info_res.name.clone()),
-                validator: best_validator.address.clone(),
+                amount: coin(undelegated_amount.u128(), &invest.bond_denom),
+                validator: dst_validator.clone(),
             }
             .into(),
+        );
+    }
+    if redelegate {
+        messages.push(
             StakingMsg::Redelegate {
-                amount: coin(delegated_amount.u128(), token_info_res.name),
-                dst_validator: best_validator.address,
+                amount: coin(delegated_amount.u128(), &invest.bond_denom),
+                dst_validator,
                 src_validator: prev_validator,
             }
             .into(),
-        ],
+        );
+    } else if evacuate {
+        messages.push(
+            StakingMsg::Undelegate {
+                amount: coin(delegated_amount.u128(), &invest.bond_denom),
+                validator: prev_validator,
+            }
+            .into(),
+        );
+    }
+
+    let r = HandleResponse {
+        messages,
         attributes,
         data: None,
     };
@@ -316,622 +1463,6836 @@ fn reinvest<S: Storage, A: Api, Q: Querier>(
     Ok(r)
 }
 
-pub fn _bond_all_tokens<S: Storage, A: Api, Q: Querier>(
+/// reinvest_all compounds the pending rewards of every delegator at once. The rewards
+/// are withdrawn and spread over the reward index a single time, then each delegator
+/// that compounds and isn't unbonding has their settled rewards bonded for them, with
+/// one Delegate to the selected validator. Shares the free balance can't cover stay
+/// owed, like in reinvest
+pub fn reinvest_all<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    info: MessageInfo,
-) -> Result<HandleResponse, StakingError> {
-    // this is just meant as a call-back to ourself
-    if info.sender != env.contract.address {
-        return Err(Unauthorized {}.build());
-    }
-
-    // find how many tokens we have to bond
+    _info: MessageInfo,
+) -> StdResult<HandleResponse> {
+    let contract_addr = env.contract.address.clone();
     let invest = invest_info_read(&deps.storage).load()?;
-    let mut balance = deps
-        .querier
-        .query_balance(&env.contract.address, &invest.bond_denom)?;
+    let rewards = pending_rewards(&deps.querier, &contract_addr, &invest.bond_denom)?;
+    let total_reward: Uint128 = rewards.iter().map(|(_, reward)| *reward).sum();
+    let mut messages: Vec<CosmosMsg> = rewards
+        .into_iter()
+        .map(|(validator, _)| {
+            StakingMsg::Withdraw {
+                validator,
+                recipient: None,
+            }
+            .into()
+        })
+        .collect();
+    // rewards paid to a withdraw address never reach the contract to be compounded
+    if invest.withdraw_address.is_some() {
+        return Ok(HandleResponse {
+            messages,
+            attributes: vec![
+                attr("action", "reinvest_all"),
+                attr("withdrawn", total_reward),
+                attr("compounded", 0),
+            ],
+            data: None,
+        });
+    }
+    let fee = take_reward_fee(deps, total_reward)?;
+    bump_reward_index(deps, (total_reward - fee)?)?;
 
-    // we deduct pending claims from our account balance before reinvesting.
-    // if there is not enough funds, we just return a no-op
-    match total_supply(&mut deps.storage).update(|mut supply| {
-        balance.amount = (balance.amount - supply.claims)?;
-        // this just triggers the "no op" case if we don't have min_withdrawal left to reinvest
-        (balance.amount - invest.min_withdrawal)?;
-        supply.bonded += balance.amount;
-        Ok(supply)
-    }) {
-        Ok(_) => {}
-        // if it is below the minimum, we do a no-op (do not revert other state from withdrawal)
-        Err(StdError::Underflow { .. }) => return Ok(HandleResponse::default()),
-        Err(e) => return Err(e.into()),
+    // settle everyone's rewards first, so what the others keep can be set aside
+    let mut compounding = vec![];
+    let mut kept = Uint128::zero();
+    for delegator in query_all_delegators(deps)? {
+        let _ = claim(deps, env.clone(), delegator.clone());
+        let key = deps.api.canonical_address(&delegator)?;
+        let delegate_info = delegations(&mut deps.storage).load(key.as_slice())?;
+        if delegate_info.auto_reinvest && !delegate_info.unbond_flag {
+            compounding.push((key, delegate_info));
+        } else {
+            kept += delegate_info.undelegate_reward;
+        }
     }
 
-    // and bond them to the validator
-    let res = HandleResponse {
-        messages: vec![StakingMsg::Delegate {
-            validator: invest.validator,
-            amount: balance.clone(),
+    // the withdrawals run before the delegation, so the rewards are spendable by then
+    let free = checked_add(
+        free_balance(deps, &contract_addr, &invest)?,
+        total_reward,
+        "Reinvested amount",
+    )?;
+    let mut available = (free - kept).unwrap_or_default();
+    let mut compounded = Uint128::zero();
+    for (key, mut delegate_info) in compounding.into_iter() {
+        let share = delegate_info.undelegate_reward.min(available);
+        if share.is_zero() {
+            continue;
         }
-        .into()],
-        attributes: vec![attr("action", "reinvest"), attr("bonded", balance.amount)],
-        data: None,
-    };
-    Ok(res)
-}
+        available = (available - share)?;
+        compounded += share;
+        delegate_info.undelegate_reward = (delegate_info.undelegate_reward - share)?;
+        delegate_info.amount = checked_add(delegate_info.amount, share, "Reinvested amount")?;
+        delegations(&mut deps.storage).save(key.as_slice(), &delegate_info)?;
+        compound_reward(&mut deps.storage, &key, share, env.block.height)?;
+    }
 
-fn select_validator<S: Storage, A: Api, Q: Querier>(
-    deps: &mut Extern<S, A, Q>,
-) -> StdResult<Validator> {
-    let validators = deps.querier.query_validators()?;
-    let min_commission = validators
-        .iter()
-        .min_by_key(|v| v.commission)
-        .unwrap()
-        .commission;
-    let validator = validators
-        .iter()
-        .filter(|v| v.commission == min_commission)
-        .min_by_key(|v| v.max_change_rate)
-        .unwrap();
-    Ok(validator.clone())
+    if !compounded.is_zero() {
+        let validator = select_validator(deps)?.address;
+        messages.push(
+            StakingMsg::Delegate {
+                validator,
+                amount: coin(compounded.u128(), &invest.bond_denom),
+            }
+            .into(),
+        );
+    }
+
+    let mut attributes = vec![
+        attr("action", "reinvest_all"),
+        attr("withdrawn", total_reward),
+        attr("fee", fee),
+        attr("compounded", compounded),
+    ];
+    attributes.extend(supply_attributes(&deps.storage)?);
+    Ok(HandleResponse {
+        messages,
+        attributes,
+        data: None,
+    })
 }
 
-fn unbond<S: Storage, A: Api, Q: Querier> (
+/// accrue_rewards is the sweep path for delegators that opted out of compounding:
+/// their share of the rewards is moved to claims and the stake is left in place
+fn accrue_rewards<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
     delegator: HumanAddr,
 ) -> StdResult<HandleResponse> {
-    // アドレスに対応するDelegateInfoのamountとundelegate_rewardをクエリする
-    let delegation = query_delegation(deps,delegator.clone())?;
-    let amount = delegation.amount;
-    let undelegate_reward = delegation.undelegate_reward;
-
-    // アドレスに対応するDelegateInfoのunbond_flagをfalseに、amountを0に更新する
-    let key = deps.api.canonical_address(&delegation.delegator)?;
-    delegations(&mut deps.storage).update(key.as_slice(), |delegate_info| -> StdResult<_> {
-        let mut new_delegate_info = delegate_info.unwrap();
-        new_delegate_info.unbond_flag = false;
-        new_delegate_info.amount = Uint128::zero();
-        new_delegate_info.undelegate_reward = Uint128::zero();
+    let _ = claim(deps, env.clone(), delegator.clone());
 
-        Ok(new_delegate_info)
-    })?;
+    let delegator_raw = deps.api.canonical_address(&delegator)?;
+    let mut delegate_info = delegations(&mut deps.storage).load(delegator_raw.as_slice())?;
+    let reward = delegate_info.undelegate_reward;
+    delegate_info.undelegate_reward = Uint128::zero();
+    delegate_info.last_delegate_height = env.block.height;
+    delegations(&mut deps.storage).save(delegator_raw.as_slice(), &delegate_info)?;
 
-    let unbound_amount = vec![Coin::new((amount + undelegate_reward).u128(), "stake")];
+    if !reward.is_zero() {
+        add_claim(&mut deps.storage, &delegator_raw, reward, env.block.height)?;
+    }
 
-    // 引数のアドレスに対して、amountの量のstakeを送金する
-    send_tokens(
-        env.contract.address,
-        delegator,
-        unbound_amount,
-        "approve",
-    )
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "accrue_rewards"),
+            attr("delegator", delegator),
+            attr("amount", reward),
+        ],
+        data: None,
+    })
 }
 
-fn send_tokens(
-    from_address: HumanAddr,
-    to_address: HumanAddr,
-    amount: Vec<Coin>,
-    action: &str,
-) -> StdResult<HandleResponse> {
-    let attributes = vec![attr("action", action), attr("to", to_address.clone())];
+/// withdraw_rewards settles the caller's rewards and sends them out instead of
+/// compounding them
+pub fn withdraw_rewards<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+) -> Result<HandleResponse, StakingError> {
+    claim(deps, env.clone(), info.sender.clone())?;
 
-    let r = HandleResponse {
-        messages: vec![CosmosMsg::Bank(BankMsg::Send {
-            from_address,
-            to_address,
-            amount,
-        })],
-        attributes,
+    let invest = invest_info_read(&deps.storage).load()?;
+    let key = deps.api.canonical_address(&info.sender)?;
+    let mut delegate_info = delegations(&mut deps.storage).load(key.as_slice())?;
+    let reward = delegate_info.undelegate_reward;
+    if reward < invest.min_withdrawal || reward.is_zero() {
+        return Err(StdError::generic_err("Rewards are below min_withdrawal").into());
+    }
+    if reward > free_balance(deps, &env.contract.address, &invest)? {
+        return Err(StdError::generic_err("Rewards are not withdrawn from validators yet").into());
+    }
+    delegate_info.undelegate_reward = Uint128::zero();
+    delegations(&mut deps.storage).save(key.as_slice(), &delegate_info)?;
+
+    Ok(HandleResponse {
+        messages: vec![BankMsg::Send {
+            from_address: env.contract.address,
+            to_address: info.sender.clone(),
+            amount: vec![coin(reward.u128(), &invest.bond_denom)],
+        }
+        .into()],
+        attributes: vec![
+            attr("action", "withdraw_rewards"),
+            attr("to", info.sender),
+            attr("amount", reward),
+        ],
         data: None,
-    };
-    Ok(r)
+    })
 }
 
-fn is_expired<S: Storage, A: Api, Q: Querier> (
+/// bond_stray delegates the part of the free balance no delegator has a claim on, such
+/// as bond_denom sent straight to the contract. It raises supply.bonded without minting,
+/// so the value goes to every holder rather than the sender
+pub fn bond_stray<S: Storage, A: Api, Q: Querier>(
     deps: &mut Extern<S, A, Q>,
     env: Env,
-    info: MessageInfo,
+    _info: MessageInfo,
 ) -> StdResult<HandleResponse> {
-    let delegator_list = query_all_delegators(deps).unwrap();
-    let block_height = env.block.height;
-    for address in delegator_list.into_iter() {
-        let delegation = query_delegation(deps, address.clone()).unwrap();
-        if delegation.last_delegate_height - block_height > 25920 {
-            if delegation.unbond_flag == true {
-                unbond(deps, env.clone(), address);
-            } else {
-                reinvest(deps, env.clone(), info.clone(), address);
-            };
-        };
-    };
+    let invest = invest_info_read(&deps.storage).load()?;
+    if !invest.bond_stray {
+        return Err(StdError::generic_err("Bonding stray funds is disabled"));
+    }
+
+    let owed = owed_rewards(deps)?;
+    let free = free_balance(deps, &env.contract.address, &invest)?;
+    let stray = (free - owed).unwrap_or_default();
+    if stray.is_zero() {
+        return Err(StdError::generic_err("No stray funds to bond"));
+    }
+
+    let validator = select_validator(deps)?.address;
+    total_supply(&mut deps.storage).update(|mut supply| -> StdResult<_> {
+        supply.bonded = checked_add(supply.bonded, stray, "Bonded supply")?;
+        Ok(supply)
+    })?;
 
+    let mut attributes = vec![
+        attr("action", "bond_stray"),
+        attr("validator", validator.clone()),
+        attr("amount", stray),
+    ];
+    attributes.extend(supply_attributes(&deps.storage)?);
     Ok(HandleResponse {
-        messages: vec![],
-        attributes: vec![],
+        messages: vec![StakingMsg::Delegate {
+            validator,
+            amount: coin(stray.u128(), &invest.bond_denom),
+        }
+        .into()],
+        attributes,
         data: None,
     })
+}
+
+pub fn set_bond_stray<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    info: MessageInfo,
+    enabled: bool,
+) -> Result<HandleResponse, StakingError> {
+    let mut invest = invest_info_read(&deps.storage).load()?;
+    if deps.api.canonical_address(&info.sender)? != invest.owner {
+        return Err(Unauthorized {}.build());
+    }
 
+    invest.bond_stray = enabled;
+    invest_info(&mut deps.storage).save(&invest)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![attr("action", "set_bond_stray"), attr("enabled", enabled)],
+        data: None,
+    })
 }
 
-pub fn query<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
+pub fn set_auto_reinvest<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
     _env: Env,
-    msg: QueryMsg,
-) -> StdResult<Binary> {
-    match msg {
-        QueryMsg::TokenInfo {} => to_binary(&query_token_info(deps)?),
-        QueryMsg::Investment {} => to_binary(&query_investment(deps)?),
-        QueryMsg::Balance { address } => to_binary(&query_balance(deps, address)?),
-        QueryMsg::Claims { address } => to_binary(&query_claims(deps, address)?),
-        QueryMsg::Validators {} => to_binary(&query_validators(deps)?),
+    info: MessageInfo,
+    enabled: bool,
+) -> StdResult<HandleResponse> {
+    let delegator_raw = deps.api.canonical_address(&info.sender)?;
+    delegations(&mut deps.storage).update(
+        delegator_raw.as_slice(),
+        |delegate_info| -> StdResult<_> {
+            let mut new_delegate_info =
+                delegate_info.ok_or_else(|| StdError::generic_err("Sender is not a delegator"))?;
+            new_delegate_info.auto_reinvest = enabled;
+            Ok(new_delegate_info)
+        },
+    )?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "set_auto_reinvest"),
+            attr("delegator", info.sender),
+            attr("enabled", enabled),
+        ],
+        data: None,
+    })
+}
+
+/// reinvest_rewards withdraws the contract's rewards from every validator that has some,
+/// then issues a callback to itself via _bond_all_tokens to bond the new earnings
+pub fn reinvest_rewards<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    _info: MessageInfo,
+) -> StdResult<HandleResponse> {
+    let contract_addr = env.contract.address;
+    let invest = invest_info_read(&deps.storage).load()?;
+    let rewards = pending_rewards(&deps.querier, &contract_addr, &invest.bond_denom)?;
+    let total_reward: Uint128 = rewards.iter().map(|(_, reward)| *reward).sum();
+    // rewards paid to a withdraw address never reach the contract, so there is nothing
+    // to charge or bond. Otherwise _bond_all_tokens compounds or distributes what is left
+    // after the fee, so it isn't credited through the reward index as well
+    let received = invest.withdraw_address.is_none();
+    let fee = if received {
+        take_reward_fee(deps, total_reward)?
+    } else {
+        Uint128::zero()
+    };
+
+    let mut messages: Vec<CosmosMsg> = rewards
+        .into_iter()
+        .map(|(validator, _)| {
+            StakingMsg::Withdraw {
+                validator,
+                recipient: None,
+            }
+            .into()
+        })
+        .collect();
+    if invest.auto_callback && received {
+        messages.push(
+            WasmMsg::Execute {
+                contract_addr,
+                msg: to_binary(&HandleMsg::_BondAllTokens {})?,
+                send: vec![],
+            }
+            .into(),
+        );
     }
+
+    Ok(HandleResponse {
+        messages,
+        attributes: vec![attr("action", "withdraw_rewards"), attr("fee", fee)],
+        data: None,
+    })
 }
 
-fn query_all_delegations<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-) -> StdResult<Vec<DelegateResponse>> {
-    let delegator_list = query_all_delegators(deps).unwrap();
-    let mut delegations = vec![];
-    for address in delegator_list.into_iter() {
-        let delegation = query_delegation(deps, address);
-        delegations.append(&mut vec![delegation.unwrap()])
+pub fn _bond_all_tokens<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+) -> Result<HandleResponse, StakingError> {
+    // this is just meant as a call-back to ourself, unless the owner runs it by hand
+    let invest = invest_info_read(&deps.storage).load()?;
+    let manual = !invest.auto_callback && deps.api.canonical_address(&info.sender)? == invest.owner;
+    if info.sender != env.contract.address && !manual {
+        return Err(Unauthorized {}.build());
+    }
+
+    // find how many tokens we have to bond. Pending claims, fees and the rewards owed
+    // to delegators are deducted from our account balance first
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, &invest.bond_denom)?;
+    let supply = total_supply_read(&deps.storage).load()?;
+    let free = (unreserved(balance.amount, &supply) - owed_rewards(deps)?).unwrap_or_default();
+    let (keys, weights) = delegator_weights(deps)?;
+    let can_distribute = weights.iter().any(|weight| !weight.is_zero());
+    let split = match split_rewards(&invest, &supply, free, can_distribute) {
+        Some(split) => split,
+        // if it is below the minimum, we do a no-op (do not revert other state from withdrawal)
+        None => return Ok(HandleResponse::default()),
+    };
+    if invest.distribute_rewards {
+        let res = distribute_rewards(deps, env, &keys, &weights, split.distributed)?;
+        return Ok(res);
+    }
+
+    // evacuated stake coming back never left supply.bonded
+    total_supply(&mut deps.storage).update(|mut supply| -> StdResult<_> {
+        supply.evacuating = (supply.evacuating - split.returning)?;
+        supply.bonded += split.compounded;
+        Ok(supply)
+    })?;
+
+    // reinvest_rewards keeps the withdrawn rewards off the reward index, so these claims
+    // are the only credit for the distributed part
+    if let Some(shares) = split_proportionally(split.distributed, &weights) {
+        for (key, share) in keys.iter().zip(shares) {
+            add_claim(&mut deps.storage, key, share, env.block.height)?;
+        }
     }
-    Ok(delegations)
+
+    // and bond them to the validator
+    let to_bond = coin(split.bonded().u128(), &balance.denom);
+    let mut messages = vec![];
+    if !to_bond.amount.is_zero() {
+        let delegate = delegate_msg(&deps.querier, invest.validator, to_bond.clone())?;
+        messages.push(delegate);
+    }
+    let mut res = HandleResponse {
+        messages,
+        attributes: vec![
+            attr("action", "reinvest"),
+            attr("bonded", to_bond.amount),
+            attr("distributed", split.distributed),
+            attr("denom", &to_bond.denom),
+        ],
+        data: None,
+    };
+    res.attributes.extend(supply_attributes(&deps.storage)?);
+    Ok(res)
 }
 
-fn query_delegation<S: Storage, A: Api, Q: Querier>(
+// RewardSplit is what _bond_all_tokens does with the free balance: evacuated stake
+// coming back and the compounded rewards are bonded, the distributed rewards go to the
+// delegators' claims
+struct RewardSplit {
+    returning: Uint128,
+    compounded: Uint128,
+    distributed: Uint128,
+}
+
+impl RewardSplit {
+    fn bonded(&self) -> Uint128 {
+        self.returning + self.compounded
+    }
+}
+
+// split_rewards works out how _bond_all_tokens uses `free`, the balance left once claims,
+// fees and owed rewards are reserved. It is None for a no-op: below min_withdrawal or
+// reinvest_min_ratio, or with distribute_rewards and no stake to split by. Otherwise
+// the compound_ratio split falls back to compounding everything without stake
+fn split_rewards(
+    invest: &InvestmentInfo,
+    supply: &Supply,
+    free: Uint128,
+    can_distribute: bool,
+) -> Option<RewardSplit> {
+    if invest.distribute_rewards {
+        if free.is_zero() || free < invest.min_withdrawal || !can_distribute {
+            return None;
+        }
+        return Some(RewardSplit {
+            returning: Uint128::zero(),
+            compounded: Uint128::zero(),
+            distributed: free,
+        });
+    }
+
+    if free < invest.min_withdrawal {
+        return None;
+    }
+    if let Some(ratio) = invest.reinvest_min_ratio {
+        if free < ratio * supply.bonded {
+            return None;
+        }
+    }
+    let returning = free.min(supply.evacuating);
+    let reward = (free - returning).ok()?;
+    let distributed = if can_distribute {
+        (reward - invest.compound_ratio * reward).ok()?
+    } else {
+        Uint128::zero()
+    };
+    Some(RewardSplit {
+        returning,
+        compounded: (reward - distributed).ok()?,
+        distributed,
+    })
+}
+
+/// distribute_rewards is the _bond_all_tokens path when rewards are paid out rather than
+/// compounded: the free balance is split across delegators' claims by bonded amount
+fn distribute_rewards<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    keys: &[CanonicalAddr],
+    weights: &[Uint128],
+    reward: Uint128,
+) -> StdResult<HandleResponse> {
+    let shares = match split_proportionally(reward, weights) {
+        Some(shares) => shares,
+        None => return Ok(HandleResponse::default()),
+    };
+
+    for (key, share) in keys.iter().zip(shares) {
+        add_claim(&mut deps.storage, key, share, env.block.height)?;
+    }
+
+    let mut attributes = vec![
+        attr("action", "distribute_rewards"),
+        attr("distributed", reward),
+    ];
+    attributes.extend(supply_attributes(&deps.storage)?);
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes,
+        data: None,
+    })
+}
+
+// delegator_weights lists every delegator with their bonded amount, by which rewards
+// are split when they are distributed
+fn delegator_weights<S: Storage, A: Api, Q: Querier>(
     deps: &Extern<S, A, Q>,
-    address: HumanAddr,
-) -> StdResult<DelegateResponse> {
-    let address_raw = deps.api.canonical_address(&address)?;
-    let delegation = delegations_read(&deps.storage)
-        .may_load(address_raw.as_slice())
+) -> StdResult<(Vec<CanonicalAddr>, Vec<Uint128>)> {
+    let delegator_list = delegators_read(&deps.storage)
+        .may_load()?
         .unwrap_or_default();
-    Ok(delegation.unwrap())
+    let mut keys = Vec::with_capacity(delegator_list.len());
+    let mut weights = Vec::with_capacity(delegator_list.len());
+    for delegator in delegator_list.iter() {
+        let key = deps.api.canonical_address(delegator)?;
+        weights.push(delegations_read(&deps.storage).load(key.as_slice())?.amount);
+        keys.push(key);
+    }
+    Ok((keys, weights))
 }
 
-fn query_all_delegators<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-) -> StdResult<Vec<HumanAddr>> {
-    delegators_read(&deps.storage).load()
+// total_claims sums all claim entries of the address
+fn total_claims<S: ReadonlyStorage>(storage: &S, key: &CanonicalAddr) -> StdResult<Uint128> {
+    Ok(claims_read(storage)
+        .may_load(key.as_slice())?
+        .unwrap_or_default()
+        .iter()
+        .map(|claim| claim.amount)
+        .sum())
 }
 
-pub fn query_token_info<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-) -> StdResult<TokenInfoResponse> {
-    token_info_read(&deps.storage).load()
+// add_claim credits `amount` to the address' claims, reserving it in the supply so it
+// isn't reinvested, and restarts the claim's expiry window
+fn add_claim<S: Storage>(
+    storage: &mut S,
+    key: &CanonicalAddr,
+    amount: Uint128,
+    height: u64,
+) -> StdResult<()> {
+    claims(storage).update(key.as_slice(), |entries| -> StdResult<_> {
+        let mut entries = entries.unwrap_or_default();
+        entries.push(Claim {
+            amount,
+            release_height: height,
+        });
+        Ok(entries)
+    })?;
+    claim_heights(storage).save(key.as_slice(), &height)?;
+    total_supply(storage).update(|mut supply| -> StdResult<_> {
+        supply.claims += amount;
+        Ok(supply)
+    })?;
+    Ok(())
 }
 
-pub fn query_balance<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
+// release_claims removes the address' claim entries that matured by `height`, oldest
+// first and at most MAX_CLAIM_ENTRIES, and returns their sum, which the caller must
+// pay out
+fn release_claims<S: Storage>(
+    storage: &mut S,
+    key: &CanonicalAddr,
+    height: u64,
+) -> StdResult<Uint128> {
+    let mut matured = claims_read(storage)
+        .may_load(key.as_slice())?
+        .unwrap_or_default();
+    matured.sort_by_key(|claim| claim.release_height);
+    let released = matured
+        .iter()
+        .take_while(|claim| claim.release_height <= height)
+        .count()
+        .min(MAX_CLAIM_ENTRIES);
+    let pending = matured.split_off(released);
+    let amount: Uint128 = matured.iter().map(|claim| claim.amount).sum();
+    if amount.is_zero() {
+        return Ok(amount);
+    }
+
+    if pending.is_empty() {
+        claims(storage).remove(key.as_slice());
+        claim_heights(storage).remove(key.as_slice());
+    } else {
+        claims(storage).save(key.as_slice(), &pending)?;
+    }
+    total_supply(storage).update(|mut supply| -> StdResult<_> {
+        supply.claims = (supply.claims - amount)?;
+        Ok(supply)
+    })?;
+    Ok(amount)
+}
+
+/// claim_for releases the matured claims of `address` and sends them to it. The payout
+/// always goes to the claim's owner, whoever triggers it
+pub fn claim_for<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
     address: HumanAddr,
-) -> StdResult<BalanceResponse> {
+) -> StdResult<HandleResponse> {
     let address_raw = deps.api.canonical_address(&address)?;
-    let balance = balances_read(&deps.storage)
+    let amount = release_claims(&mut deps.storage, &address_raw, env.block.height)?;
+    if amount.is_zero() {
+        return Err(StdError::generic_err("No matured claims"));
+    }
+
+    let bond_denom = invest_info_read(&deps.storage).load()?.bond_denom;
+    let mut attributes = vec![
+        attr("action", "claim_for"),
+        attr("address", address.clone()),
+        attr("amount", amount),
+    ];
+    attributes.extend(supply_attributes(&deps.storage)?);
+    Ok(HandleResponse {
+        messages: vec![BankMsg::Send {
+            from_address: env.contract.address,
+            to_address: address,
+            amount: vec![coin(amount.u128(), &bond_denom)],
+        }
+        .into()],
+        attributes,
+        data: None,
+    })
+}
+
+/// expire_claims returns an abandoned claim to the pool: it is delegated again and
+/// counted as bonded, which raises the value of every outstanding derivative token
+pub fn expire_claims<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    address: HumanAddr,
+) -> Result<HandleResponse, StakingError> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    if deps.api.canonical_address(&info.sender)? != invest.owner {
+        return Err(Unauthorized {}.build());
+    }
+
+    let address_raw = deps.api.canonical_address(&address)?;
+    let amount = total_claims(&deps.storage, &address_raw)?;
+    if amount.is_zero() {
+        return Err(StdError::generic_err("No claims to expire").into());
+    }
+    let claimed_at = claim_heights_read(&deps.storage)
         .may_load(address_raw.as_slice())?
         .unwrap_or_default();
-    Ok(BalanceResponse { balance })
+    if env.block.height < claimed_at + invest.blocks_for_duration(CLAIM_EXPIRY_SECONDS) {
+        return Err(StdError::generic_err("Claim has not expired yet").into());
+    }
+
+    claims(&mut deps.storage).remove(address_raw.as_slice());
+    claim_heights(&mut deps.storage).remove(address_raw.as_slice());
+    total_supply(&mut deps.storage).update(|mut supply| -> StdResult<_> {
+        supply.claims = (supply.claims - amount)?;
+        supply.bonded += amount;
+        Ok(supply)
+    })?;
+
+    let mut attributes = vec![
+        attr("action", "expire_claims"),
+        attr("address", address),
+        attr("reclaimed", amount),
+    ];
+    attributes.extend(supply_attributes(&deps.storage)?);
+    let delegate = coin(amount.u128(), &invest.bond_denom);
+    Ok(HandleResponse {
+        messages: vec![delegate_msg(&deps.querier, invest.validator, delegate)?],
+        attributes,
+        data: None,
+    })
+}
+
+/// evacuate_validator undelegates everything the contract has bonded to `from`. The stake
+/// keeps counting as bonded while it unbonds, and the delegation records on `from` are
+/// moved to the configured validator where _bond_all_tokens will put it back
+pub fn evacuate_validator<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    from: HumanAddr,
+) -> Result<HandleResponse, StakingError> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    if deps.api.canonical_address(&info.sender)? != invest.owner {
+        return Err(Unauthorized {}.build());
+    }
+    if from == invest.validator {
+        return Err(StdError::generic_err("Cannot evacuate the configured validator").into());
+    }
+
+    let amount = deps
+        .querier
+        .query_delegation(env.contract.address, from.clone())?
+        .map(|d| d.amount)
+        .ok_or_else(|| StdError::generic_err(format!("No delegation to {}", from)))?;
+
+    total_supply(&mut deps.storage).update(|mut supply| -> StdResult<_> {
+        supply.evacuating += amount.amount;
+        Ok(supply)
+    })?;
+
+    let delegator_list = delegators_read(&deps.storage)
+        .may_load()?
+        .unwrap_or_default();
+    for delegator in delegator_list.iter() {
+        let key = deps.api.canonical_address(delegator)?;
+        let mut delegate_info = delegations(&mut deps.storage).load(key.as_slice())?;
+        if delegate_info.validator == from {
+            delegate_info.validator = invest.validator.clone();
+            delegations(&mut deps.storage).save(key.as_slice(), &delegate_info)?;
+        }
+    }
+
+    Ok(HandleResponse {
+        messages: vec![StakingMsg::Undelegate {
+            validator: from.clone(),
+            amount: amount.clone(),
+        }
+        .into()],
+        attributes: vec![
+            attr("action", "evacuate_validator"),
+            attr("from", from),
+            attr("amount", amount.amount),
+        ],
+        data: None,
+    })
+}
+
+/// rebalance redelegates stake from validators above their target weight to the ones below
+/// it, moving as little as possible. Moves below min_withdrawal are skipped as dust.
+/// It only moves stake towards the configured targets, so anyone can call it
+pub fn rebalance<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    _info: MessageInfo,
+) -> StdResult<HandleResponse> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    let bonds: Vec<(HumanAddr, Uint128)> = deps
+        .querier
+        .query_all_delegations(&env.contract.address)?
+        .into_iter()
+        .filter(|d| d.amount.denom == invest.bond_denom)
+        .map(|d| (d.validator, d.amount.amount))
+        .collect();
+    let total: Uint128 = bonds.iter().map(|(_, amount)| *amount).sum();
+
+    let targets = query_target_validators(deps)?.validators;
+    let target_of = |validator: &HumanAddr| -> Uint128 {
+        targets
+            .iter()
+            .zip(weighted_shares(total, &targets))
+            .find(|((addr, _), _)| addr == validator)
+            .map(|(_, share)| share)
+            .unwrap_or_default()
+    };
+    let current_of = |validator: &HumanAddr| -> Uint128 {
+        bonds
+            .iter()
+            .find(|(addr, _)| addr == validator)
+            .map(|(_, amount)| *amount)
+            .unwrap_or_default()
+    };
+
+    // validators outside the target set have a target of zero
+    let mut surplus: Vec<(HumanAddr, Uint128)> = bonds
+        .iter()
+        .filter_map(|(validator, amount)| {
+            (*amount - target_of(validator))
+                .ok()
+                .filter(|excess| !excess.is_zero())
+                .map(|excess| (validator.clone(), excess))
+        })
+        .collect();
+    let mut deficit: Vec<(HumanAddr, Uint128)> = targets
+        .iter()
+        .filter_map(|(validator, _)| {
+            (target_of(validator) - current_of(validator))
+                .ok()
+                .filter(|missing| !missing.is_zero())
+                .map(|missing| (validator.clone(), missing))
+        })
+        .collect();
+
+    let mut messages = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < surplus.len() && j < deficit.len() {
+        let amount = surplus[i].1.min(deficit[j].1);
+        if amount >= invest.min_withdrawal {
+            messages.push(
+                StakingMsg::Redelegate {
+                    src_validator: surplus[i].0.clone(),
+                    dst_validator: deficit[j].0.clone(),
+                    amount: coin(amount.u128(), &invest.bond_denom),
+                }
+                .into(),
+            );
+        }
+        surplus[i].1 = (surplus[i].1 - amount)?;
+        deficit[j].1 = (deficit[j].1 - amount)?;
+        if surplus[i].1.is_zero() {
+            i += 1;
+        }
+        if deficit[j].1.is_zero() {
+            j += 1;
+        }
+    }
+
+    Ok(HandleResponse {
+        attributes: vec![
+            attr("action", "rebalance"),
+            attr("redelegations", messages.len()),
+        ],
+        messages,
+        data: None,
+    })
+}
+
+/// split_proportionally divides `total` by `weights`, handing the rounding remainder to
+/// the largest weight so the shares always add up to `total`.
+/// Returns None if there is no weight to split by
+fn split_proportionally(total: Uint128, weights: &[Uint128]) -> Option<Vec<Uint128>> {
+    let weight_sum = weights
+        .iter()
+        .fold(Uint128::zero(), |acc, weight| acc + *weight);
+    if weight_sum.is_zero() {
+        return None;
+    }
+
+    let mut shares: Vec<Uint128> = weights
+        .iter()
+        .map(|weight| total.multiply_ratio(*weight, weight_sum))
+        .collect();
+    let assigned = shares
+        .iter()
+        .fold(Uint128::zero(), |acc, share| acc + *share);
+    let remainder = (total - assigned).unwrap_or_default();
+    let mut largest = 0;
+    for (i, weight) in weights.iter().enumerate() {
+        if *weight > weights[largest] {
+            largest = i;
+        }
+    }
+    shares[largest] += remainder;
+    Some(shares)
 }
 
-pub fn query_claims<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-    address: HumanAddr,
-) -> StdResult<ClaimsResponse> {
-    let address_raw = deps.api.canonical_address(&address)?;
-    let claims = claims_read(&deps.storage)
-        .may_load(address_raw.as_slice())?
-        .unwrap_or_default();
-    Ok(ClaimsResponse { claims })
-}
+pub fn set_validator_powers<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    info: MessageInfo,
+    powers: Vec<ValidatorPower>,
+) -> Result<HandleResponse, StakingError> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    if deps.api.canonical_address(&info.sender)? != invest.owner {
+        return Err(Unauthorized {}.build());
+    }
+
+    let mut bucket = validator_powers(&mut deps.storage);
+    for p in powers.iter() {
+        bucket.save(p.validator.as_bytes(), &p.power)?;
+    }
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "set_validator_powers"),
+            attr("count", powers.len()),
+        ],
+        data: None,
+    })
+}
+
+pub fn update_token_info<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    info: MessageInfo,
+    name: Option<String>,
+    symbol: Option<String>,
+) -> Result<HandleResponse, StakingError> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    if deps.api.canonical_address(&info.sender)? != invest.owner {
+        return Err(Unauthorized {}.build());
+    }
+
+    let mut token = token_info_read(&deps.storage).load()?;
+    if let Some(name) = name {
+        validate_name(&name)?;
+        token.name = name;
+    }
+    if let Some(symbol) = symbol {
+        validate_symbol(&symbol)?;
+        token.symbol = symbol;
+    }
+    token_info(&mut deps.storage).save(&token)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "update_token_info"),
+            attr("name", &token.name),
+            attr("symbol", &token.symbol),
+        ],
+        data: None,
+    })
+}
+
+pub fn set_reward<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    info: MessageInfo,
+    address: HumanAddr,
+    amount: Uint128,
+) -> Result<HandleResponse, StakingError> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    if deps.api.canonical_address(&info.sender)? != invest.owner {
+        return Err(Unauthorized {}.build());
+    }
+
+    let key = deps.api.canonical_address(&address)?;
+    let mut previous = Uint128::zero();
+    delegations(&mut deps.storage).update(key.as_slice(), |delegate_info| -> StdResult<_> {
+        let mut new_delegate_info =
+            delegate_info.ok_or_else(|| StdError::not_found("Delegation"))?;
+        previous = new_delegate_info.undelegate_reward;
+        new_delegate_info.undelegate_reward = amount;
+        Ok(new_delegate_info)
+    })?;
+
+    // the previous value is logged so every correction can be audited
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "set_reward"),
+            attr("delegator", address),
+            attr("previous", previous),
+            attr("amount", amount),
+        ],
+        data: None,
+    })
+}
+
+/// migrate_delegator hands everything `from` holds over to `to`. Rewards are settled
+/// first so both delegations are at the same reward index when they are merged. A
+/// pending unbond must complete before its delegator can be migrated
+pub fn migrate_delegator<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    from: HumanAddr,
+    to: HumanAddr,
+) -> Result<HandleResponse, StakingError> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    if deps.api.canonical_address(&info.sender)? != invest.owner {
+        return Err(Unauthorized {}.build());
+    }
+    let from_raw = deps.api.canonical_address(&from)?;
+    let to_raw = validate_address(&deps.api, &to, "to")?;
+    if from_raw == to_raw {
+        return Err(StdError::generic_err("Cannot migrate a delegator to itself").into());
+    }
+
+    let _ = claim(deps, env.clone(), from.clone());
+    let _ = claim(deps, env.clone(), to.clone());
+    let from_info = delegations(&mut deps.storage).may_load(from_raw.as_slice())?;
+    let to_info = delegations(&mut deps.storage).may_load(to_raw.as_slice())?;
+    if from_info.iter().chain(&to_info).any(|d| d.unbond_flag) {
+        return Err(StdError::generic_err("Cannot migrate during a pending unbond").into());
+    }
+    let mut delegated = Uint128::zero();
+    if let Some(from_info) = from_info {
+        delegated = from_info.amount;
+        let merged = match to_info {
+            Some(mut to_info) => {
+                to_info.amount = checked_add(to_info.amount, from_info.amount, "Delegation")?;
+                to_info.undelegate_reward += from_info.undelegate_reward;
+                to_info.cost_basis += from_info.cost_basis;
+                to_info.last_delegate_height = to_info
+                    .last_delegate_height
+                    .max(from_info.last_delegate_height);
+                to_info.first_bond_height =
+                    to_info.first_bond_height.min(from_info.first_bond_height);
+                to_info.last_bond_height = to_info.last_bond_height.max(from_info.last_bond_height);
+                to_info
+            }
+            None => DelegateInfo {
+                delegator: to.clone(),
+                ..from_info
+            },
+        };
+        delegations(&mut deps.storage).save(to_raw.as_slice(), &merged)?;
+        delegations(&mut deps.storage).remove(from_raw.as_slice());
+
+        let mut delegator_list = delegators_read(&deps.storage).load()?;
+        delegator_list.retain(|d| d != &from);
+        if !delegator_list.contains(&to) {
+            delegator_list.push(to.clone());
+        }
+        delegators(&mut deps.storage).save(&delegator_list)?;
+        delegator_count(&mut deps.storage).save(&(delegator_list.len() as u64))?;
+    }
+
+    let balance = balances_read(&deps.storage)
+        .may_load(from_raw.as_slice())?
+        .unwrap_or_default();
+    if !balance.is_zero() {
+        balances(&mut deps.storage).remove(from_raw.as_slice());
+        balances(&mut deps.storage).update(to_raw.as_slice(), |b| -> StdResult<_> {
+            Ok(b.unwrap_or_default() + balance)
+        })?;
+        snapshot_balance(&mut deps.storage, &from_raw, env.block.height)?;
+        snapshot_balance(&mut deps.storage, &to_raw, env.block.height)?;
+    }
+
+    let moved = claims_read(&deps.storage)
+        .may_load(from_raw.as_slice())?
+        .unwrap_or_default();
+    let claimed: Uint128 = moved.iter().map(|claim| claim.amount).sum();
+    if !moved.is_empty() {
+        let mut entries = claims_read(&deps.storage)
+            .may_load(to_raw.as_slice())?
+            .unwrap_or_default();
+        entries.extend(moved);
+        entries.sort_by_key(|claim| claim.release_height);
+        claims(&mut deps.storage).save(to_raw.as_slice(), &entries)?;
+        claims(&mut deps.storage).remove(from_raw.as_slice());
+
+        let from_height = claim_heights_read(&deps.storage).may_load(from_raw.as_slice())?;
+        let to_height = claim_heights_read(&deps.storage).may_load(to_raw.as_slice())?;
+        if let Some(height) = from_height.max(to_height) {
+            claim_heights(&mut deps.storage).save(to_raw.as_slice(), &height)?;
+        }
+        claim_heights(&mut deps.storage).remove(from_raw.as_slice());
+    }
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "migrate_delegator"),
+            attr("from", from),
+            attr("to", to),
+            attr("delegation", delegated),
+            attr("balance", balance),
+            attr("claims", claimed),
+        ],
+        data: None,
+    })
+}
+
+pub fn set_deny_list<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    info: MessageInfo,
+    validators: Vec<HumanAddr>,
+) -> Result<HandleResponse, StakingError> {
+    let mut invest = invest_info_read(&deps.storage).load()?;
+    if deps.api.canonical_address(&info.sender)? != invest.owner {
+        return Err(Unauthorized {}.build());
+    }
+
+    invest.deny_list = validators;
+    invest_info(&mut deps.storage).save(&invest)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "set_deny_list"),
+            attr("count", invest.deny_list.len()),
+        ],
+        data: None,
+    })
+}
+
+/// set_withdraw_address points the contract's rewards at `address`. A staking withdraw
+/// with a recipient makes the chain set the contract's withdraw address and then pay
+/// that validator's pending rewards to it, so everything pending leaves at once and all
+/// later withdrawals follow it until it is pointed back at the contract. While it is
+/// set, Reinvest and ReinvestAll only withdraw, as no reward reaches the contract
+pub fn set_withdraw_address<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    info: MessageInfo,
+    address: HumanAddr,
+) -> Result<HandleResponse, StakingError> {
+    let mut invest = invest_info_read(&deps.storage).load()?;
+    if deps.api.canonical_address(&info.sender)? != invest.owner {
+        return Err(Unauthorized {}.build());
+    }
+    validate_address(&deps.api, &address, "address")?;
+
+    invest.withdraw_address = if address == env.contract.address {
+        None
+    } else {
+        Some(address.clone())
+    };
+    invest_info(&mut deps.storage).save(&invest)?;
+
+    let messages = deps
+        .querier
+        .query_all_delegations(&env.contract.address)?
+        .into_iter()
+        .map(|d| {
+            StakingMsg::Withdraw {
+                validator: d.validator,
+                recipient: Some(address.clone()),
+            }
+            .into()
+        })
+        .collect();
+
+    Ok(HandleResponse {
+        messages,
+        attributes: vec![
+            attr("action", "set_withdraw_address"),
+            attr("address", address),
+        ],
+        data: None,
+    })
+}
+
+pub fn set_min_withdrawal<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    _env: Env,
+    info: MessageInfo,
+    min_withdrawal: Uint128,
+) -> Result<HandleResponse, StakingError> {
+    let mut invest = invest_info_read(&deps.storage).load()?;
+    if deps.api.canonical_address(&info.sender)? != invest.owner {
+        return Err(Unauthorized {}.build());
+    }
+    if min_withdrawal.is_zero() {
+        return Err(StdError::generic_err("min_withdrawal must be nonzero").into());
+    }
+
+    let previous = invest.min_withdrawal;
+    invest.min_withdrawal = min_withdrawal;
+    invest_info(&mut deps.storage).save(&invest)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "set_min_withdrawal"),
+            attr("previous", previous),
+            attr("min_withdrawal", min_withdrawal),
+        ],
+        data: None,
+    })
+}
+
+// validate_text checks a token name or symbol has between min and max characters
+fn validate_text(value: &str, field: &str, min: usize, max: usize) -> StdResult<()> {
+    let len = value.chars().count();
+    if len < min || len > max {
+        return Err(StdError::generic_err(format!(
+            "Token {} must be {} to {} characters",
+            field, min, max
+        )));
+    }
+    Ok(())
+}
+
+// validate_name applies the CW20 rules for a token name: 3 to 50 characters, none of
+// them control characters
+fn validate_name(name: &str) -> StdResult<()> {
+    validate_text(name, "name", 3, 50)?;
+    if name.chars().any(char::is_control) {
+        return Err(StdError::generic_err(
+            "Token name must not contain control characters",
+        ));
+    }
+    Ok(())
+}
+
+// validate_symbol applies the CW20 rules for a token symbol: 3 to 12 characters of
+// [a-zA-Z-]
+fn validate_symbol(symbol: &str) -> StdResult<()> {
+    validate_text(symbol, "symbol", 3, 12)?;
+    if !symbol.chars().all(|c| c.is_ascii_alphabetic() || c == '-') {
+        return Err(StdError::generic_err(
+            "Token symbol must only contain letters and '-'",
+        ));
+    }
+    Ok(())
+}
+
+fn select_validator<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+) -> StdResult<Validator> {
+    let invest = invest_info_read(&deps.storage).may_load()?;
+    let (min_commission, max_commission) = invest
+        .map(|invest| (invest.min_commission, invest.max_commission))
+        .unwrap_or((Decimal::zero(), Decimal::one()));
+    rank_validators(deps)?.into_iter().next().ok_or_else(|| {
+        StdError::generic_err(format!(
+            "No validator outside the deny list with a commission between {} and {}",
+            min_commission, max_commission
+        ))
+    })
+}
+
+// rank_validators orders the validators in the commission band by the selection
+// strategy, best first.
+// LowestCommission sorts by commission, then by max_change_rate. HighestVotingPower puts
+// validators with a reported power first (highest first, commission breaks ties) and
+// ranks the rest by commission, so without any power data it matches LowestCommission
+fn rank_validators<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<Vec<Validator>> {
+    let invest = invest_info_read(&deps.storage).may_load()?;
+    let strategy = invest
+        .as_ref()
+        .map(|invest| invest.selection_strategy)
+        .unwrap_or(SelectionStrategy::LowestCommission);
+    let deny_list = invest
+        .as_ref()
+        .map(|invest| invest.deny_list.clone())
+        .unwrap_or_default();
+    let (min_commission, max_commission) = invest
+        .map(|invest| (invest.min_commission, invest.max_commission))
+        .unwrap_or((Decimal::zero(), Decimal::one()));
+
+    // only validators within the commission band and not denied are candidates
+    let mut validators: Vec<Validator> = deps
+        .querier
+        .query_validators()?
+        .into_iter()
+        .filter(|v| v.commission >= min_commission && v.commission <= max_commission)
+        .filter(|v| !deny_list.contains(&v.address))
+        .collect();
+    validators.sort_by(|a, b| {
+        a.commission
+            .cmp(&b.commission)
+            .then(a.max_change_rate.cmp(&b.max_change_rate))
+    });
+
+    if strategy == SelectionStrategy::HighestVotingPower {
+        let powers = validator_powers_read(&deps.storage);
+        let mut ranked = Vec::with_capacity(validators.len());
+        for v in validators.into_iter() {
+            ranked.push((powers.may_load(v.address.as_bytes())?, v));
+        }
+        // the sort is stable, so equal powers keep the commission order
+        ranked.sort_by(|(a, _), (b, _)| match (a, b) {
+            (Some(a), Some(b)) => b.cmp(a),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+        validators = ranked.into_iter().map(|(_, v)| v).collect();
+    }
+    Ok(validators)
+}
+
+/// unbond settles a delegation whose unbond request the sweep reached. Its stake was
+/// undelegated and claimed when the unbond was requested, so only the rewards it earned
+/// until then are left, and they are added to its claims
+fn unbond<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    delegator: HumanAddr,
+) -> StdResult<HandleResponse> {
+    let _ = claim(deps, env.clone(), delegator.clone());
+
+    let key = deps.api.canonical_address(&delegator)?;
+    let mut delegate_info = delegations(&mut deps.storage).load(key.as_slice())?;
+    let reward = delegate_info.undelegate_reward;
+    delegate_info.unbond_flag = false;
+    delegate_info.amount = Uint128::zero();
+    delegate_info.undelegate_reward = Uint128::zero();
+    delegate_info.cost_basis = Uint128::zero();
+    delegations(&mut deps.storage).save(key.as_slice(), &delegate_info)?;
+    pending_unbonds(&mut deps.storage)
+        .update(|count| -> StdResult<_> { Ok(count.saturating_sub(1)) })?;
+
+    if !reward.is_zero() {
+        add_claim(&mut deps.storage, &key, reward, env.block.height)?;
+    }
+
+    Ok(HandleResponse {
+        messages: vec![],
+        attributes: vec![
+            attr("action", "unbond"),
+            attr("delegator", delegator),
+            attr("reward", reward),
+        ],
+        data: None,
+    })
+}
+
+fn send_tokens(
+    from_address: HumanAddr,
+    to_address: HumanAddr,
+    amount: Vec<Coin>,
+    action: &str,
+) -> StdResult<HandleResponse> {
+    // an empty send is rejected by some chains, so send nothing at all instead
+    let amount: Vec<Coin> = amount.into_iter().filter(|c| !c.amount.is_zero()).collect();
+    if amount.is_empty() {
+        return Ok(HandleResponse::default());
+    }
+
+    let attributes = vec![attr("action", action), attr("to", to_address.clone())];
+
+    let r = HandleResponse {
+        messages: vec![CosmosMsg::Bank(BankMsg::Send {
+            from_address,
+            to_address,
+            amount,
+        })],
+        attributes,
+        data: None,
+    };
+    Ok(r)
+}
+
+/// is_expired sweeps up to `limit` delegators whose last delegation is older than
+/// EXPIRY_SECONDS, unbonding the ones that asked for it and reinvesting the rest
+/// (or accruing their rewards as claims, if they opted out of compounding). Each sweep
+/// resumes after the last delegator the previous one looked at and wraps around the
+/// list once, so delegators that stay expired don't hold up the ones behind them
+fn is_expired<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    limit: Option<u32>,
+) -> StdResult<HandleResponse> {
+    let limit = limit.unwrap_or(DEFAULT_SWEEP_LIMIT).min(MAX_SWEEP_LIMIT) as usize;
+    let delegator_list = query_all_delegators(deps)?;
+    let block_height = env.block.height;
+    let expiry = invest_info_read(&deps.storage)
+        .load()?
+        .blocks_for_duration(EXPIRY_SECONDS);
+    let start = match sweep_cursor_read(&deps.storage).may_load()? {
+        Some(cursor) => delegator_list
+            .iter()
+            .position(|d| d == &cursor)
+            .map_or(0, |i| i + 1),
+        None => 0,
+    };
+    let len = delegator_list.len();
+    let mut processed = 0;
+    // the per-delegator messages are all carried by the sweep's response, and the
+    // first failure aborts the sweep rather than being dropped
+    let mut messages = vec![];
+    let mut last = None;
+    for address in delegator_list.into_iter().cycle().skip(start).take(len) {
+        if processed >= limit {
+            break;
+        }
+        last = Some(address.clone());
+        let delegation = query_delegation(deps, address.clone()).unwrap();
+        // an emptied delegation has nothing to unbond, reinvest or accrue
+        let idle = delegation.amount.is_zero()
+            && delegation.undelegate_reward.is_zero()
+            && !delegation.unbond_flag;
+        if !idle && block_height.saturating_sub(delegation.last_delegate_height) > expiry {
+            processed += 1;
+            let res = if delegation.unbond_flag == true {
+                unbond(deps, env.clone(), address)?
+            } else if delegation.auto_reinvest {
+                reinvest(deps, env.clone(), address)?
+            } else {
+                accrue_rewards(deps, env.clone(), address)?
+            };
+            messages.extend(res.messages);
+        };
+    }
+    if let Some(last) = last {
+        sweep_cursor(&mut deps.storage).save(&last)?;
+    }
+
+    let mut attributes = vec![
+        attr("action", "process_expired"),
+        attr("processed", processed),
+    ];
+    attributes.extend(supply_attributes(&deps.storage)?);
+    Ok(HandleResponse {
+        messages,
+        attributes,
+        data: None,
+    })
+}
+
+/// migrate upgrades the storage of an existing instance, step by step from the stored
+/// version. Instances from before versioning are version 1
+pub fn migrate<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    _info: MessageInfo,
+    _msg: MigrateMsg,
+) -> StdResult<MigrateResponse> {
+    let version = contract_version_read(&deps.storage)
+        .may_load()?
+        .unwrap_or(1);
+    if version > CONTRACT_VERSION {
+        return Err(StdError::generic_err(format!(
+            "Cannot migrate from newer version {}",
+            version
+        )));
+    }
+
+    let mut attributes = vec![attr("action", "migrate"), attr("from_version", version)];
+    migrate_settings(deps)?;
+    if version < 2 {
+        let migrated = migrate_claims(&mut deps.storage, env.block.height)?;
+        attributes.push(attr("migrated_claims", migrated));
+    }
+    if version < 3 {
+        let migrated = migrate_first_bond_heights(&mut deps.storage)?;
+        attributes.push(attr("migrated_delegations", migrated));
+    }
+    if version < 4 {
+        let pending = delegations(&mut deps.storage)
+            .range(None, None, Order::Ascending)
+            .filter(|item| item.as_ref().map_or(true, |(_, d)| d.unbond_flag))
+            .collect::<StdResult<Vec<_>>>()?
+            .len() as u64;
+        pending_unbonds(&mut deps.storage).save(&pending)?;
+        attributes.push(attr("pending_unbonds", pending));
+    }
+    if version < 5 {
+        // the history starts with the current delegations, earlier ones are unknown
+        let current = deps.querier.query_all_delegations(&env.contract.address)?;
+        for delegation in current.iter() {
+            let key = delegation.validator.as_str().as_bytes();
+            validator_history(&mut deps.storage).save(key, &env.block.height)?;
+        }
+        attributes.push(attr("validator_history", current.len()));
+    }
+    // version 6 added InvestmentResponse::schema_version, the state is unchanged
+    if version < 7 {
+        let migrated = migrate_last_bond_heights(&mut deps.storage)?;
+        attributes.push(attr("migrated_bond_heights", migrated));
+    }
+    contract_version(&mut deps.storage).save(&CONTRACT_VERSION)?;
+
+    Ok(MigrateResponse {
+        messages: vec![],
+        attributes,
+        data: None,
+    })
+}
+
+// migrate_settings stores the settings and supply in their current shape, which is
+// idempotent, so it runs from any version. Settings added since version 1 load as their
+// defaults except for the ones that depend on others, which are filled in here, and the
+// singletons added since are created
+fn migrate_settings<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>) -> StdResult<()> {
+    let mut invest = invest_info_read(&deps.storage).load()?;
+    if invest.validators.is_empty() {
+        invest.validators = vec![invest.validator.clone()];
+    }
+    if invest.fee_recipients.is_empty() {
+        let owner = deps.api.human_address(&invest.owner)?;
+        invest.fee_recipients = vec![(owner, Decimal::one())];
+    }
+    invest_info(&mut deps.storage).save(&invest)?;
+    let supply = total_supply_read(&deps.storage).load()?;
+    total_supply(&mut deps.storage).save(&supply)?;
+
+    let delegator_list = delegators_read(&deps.storage)
+        .may_load()?
+        .unwrap_or_default();
+    delegators(&mut deps.storage).save(&delegator_list)?;
+    delegator_count(&mut deps.storage).save(&(delegator_list.len() as u64))?;
+    if fees_earned_read(&deps.storage).may_load()?.is_none() {
+        fees_earned(&mut deps.storage).save(&Uint128::zero())?;
+    }
+
+    // queries read delegations as DelegateResponse, which has no defaults
+    let records = delegations(&mut deps.storage)
+        .range(None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut bucket = delegations(&mut deps.storage);
+    for (key, delegate_info) in records.into_iter() {
+        bucket.save(&key, &delegate_info)?;
+    }
+    Ok(())
+}
+
+// migrate_claims rewrites version 1 claims, a bare amount per address, as a single
+// Claim that is payable right away
+fn migrate_claims<S: Storage>(storage: &mut S, height: u64) -> StdResult<usize> {
+    let legacy = legacy_claims_read(storage)
+        .range(None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut bucket = claims(storage);
+    for (key, amount) in legacy.iter() {
+        let claim = Claim {
+            amount: *amount,
+            release_height: height,
+        };
+        bucket.save(key, &vec![claim])?;
+    }
+    Ok(legacy.len())
+}
+
+// migrate_first_bond_heights starts the holding time of delegations from before version 3
+// at their last delegation, the earliest height still known for them
+fn migrate_first_bond_heights<S: Storage>(storage: &mut S) -> StdResult<usize> {
+    let records = delegations(storage)
+        .range(None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut bucket = delegations(storage);
+    let mut migrated = 0;
+    for (key, mut delegate_info) in records.into_iter() {
+        if delegate_info.first_bond_height == 0 {
+            delegate_info.first_bond_height = delegate_info.last_delegate_height;
+            bucket.save(&key, &delegate_info)?;
+            migrated += 1;
+        }
+    }
+    Ok(migrated)
+}
+
+// migrate_last_bond_heights starts the minimum hold of delegations from before version 7
+// at their last delegation, which is no earlier than their last bond
+fn migrate_last_bond_heights<S: Storage>(storage: &mut S) -> StdResult<usize> {
+    let records = delegations(storage)
+        .range(None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut bucket = delegations(storage);
+    let mut migrated = 0;
+    for (key, mut delegate_info) in records.into_iter() {
+        if delegate_info.last_bond_height == 0 {
+            delegate_info.last_bond_height = delegate_info.last_delegate_height;
+            bucket.save(&key, &delegate_info)?;
+            migrated += 1;
+        }
+    }
+    Ok(migrated)
+}
+
+/// sudo can only be invoked by the chain itself (e.g. from a governance-configured
+/// block hook), so it runs privileged maintenance without any sender checks.
+/// Note: the cosmwasm 0.11 entry point macros don't export sudo yet
+pub fn sudo<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    msg: SudoMsg,
+) -> Result<HandleResponse, StakingError> {
+    let height = env.block.height;
+    let res = match msg {
+        SudoMsg::ProcessExpired { limit } => is_expired(deps, env, limit)?,
+        SudoMsg::ProcessClaims { limit } => process_claims(deps, env, limit)?,
+    };
+    record_validators(&mut deps.storage, &res.messages, height)?;
+    Ok(res)
+}
+
+/// process_claims pays out the matured claims of up to `limit` addresses
+fn process_claims<S: Storage, A: Api, Q: Querier>(
+    deps: &mut Extern<S, A, Q>,
+    env: Env,
+    limit: Option<u32>,
+) -> StdResult<HandleResponse> {
+    let limit = limit.unwrap_or(DEFAULT_SWEEP_LIMIT).min(MAX_SWEEP_LIMIT) as usize;
+    let height = env.block.height;
+    let matured: Vec<CanonicalAddr> = claims_read(&deps.storage)
+        .range(None, None, Order::Ascending)
+        .filter_map(|item| match item {
+            Ok((key, entries)) if entries.iter().any(|c| c.release_height <= height) => {
+                Some(Ok(CanonicalAddr::from(key)))
+            }
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+        .take(limit)
+        .collect::<StdResult<_>>()?;
+
+    let bond_denom = invest_info_read(&deps.storage).load()?.bond_denom;
+    let mut messages = vec![];
+    for key in matured.iter() {
+        let amount = release_claims(&mut deps.storage, key, height)?;
+        messages.push(
+            BankMsg::Send {
+                from_address: env.contract.address.clone(),
+                to_address: deps.api.human_address(key)?,
+                amount: vec![coin(amount.u128(), &bond_denom)],
+            }
+            .into(),
+        );
+    }
+
+    let mut attributes = vec![
+        attr("action", "process_claims"),
+        attr("processed", matured.len()),
+    ];
+    attributes.extend(supply_attributes(&deps.storage)?);
+    Ok(HandleResponse {
+        messages,
+        attributes,
+        data: None,
+    })
+}
+
+pub fn query<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+    msg: QueryMsg,
+) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::TokenInfo {} => to_binary(&query_token_info(deps)?),
+        QueryMsg::Investment {} => to_binary(&query_investment(deps)?),
+        QueryMsg::Balance { address } => to_binary(&query_balance(deps, address)?),
+        QueryMsg::BalanceAt { address, height } => {
+            to_binary(&query_balance_at(deps, address, height)?)
+        }
+        QueryMsg::Claims { address } => to_binary(&query_claims(deps, address)?),
+        QueryMsg::UnbondSchedule { address } => to_binary(&query_unbond_schedule(deps, address)?),
+        QueryMsg::Unbonding { address } => to_binary(&query_unbonding(deps, env, address)?),
+        QueryMsg::ClaimsBreakdown {} => to_binary(&query_claims_breakdown(deps, env)?),
+        QueryMsg::Validators {} => to_binary(&query_validators(deps)?),
+        QueryMsg::ReinvestPreview {} => to_binary(&query_reinvest_preview(deps, env)?),
+        QueryMsg::Allowance { owner, spender } => {
+            to_binary(&query_allowance(deps, owner, spender)?)
+        }
+        QueryMsg::AllAllowances {
+            owner,
+            start_after,
+            limit,
+        } => to_binary(&query_all_allowances(deps, owner, start_after, limit)?),
+        QueryMsg::UnrealizedGain { address } => to_binary(&query_unrealized_gain(deps, address)?),
+        QueryMsg::BlendedCommission {} => to_binary(&query_blended_commission(deps, env)?),
+        QueryMsg::NextExpiry { address } => to_binary(&query_next_expiry(deps, env, address)?),
+        QueryMsg::ExpiredCount {} => to_binary(&query_expired_count(deps, env)?),
+        QueryMsg::AllDelegations { start_after, limit } => {
+            to_binary(&query_paged_delegations(deps, start_after, limit)?)
+        }
+        QueryMsg::IsDelegator { address } => to_binary(&query_is_delegator(deps, address)?),
+        QueryMsg::EffectiveRate {} => to_binary(&query_effective_rate(deps, env)?),
+        QueryMsg::BackedRate {} => to_binary(&query_backed_rate(deps)?),
+        QueryMsg::MinMeaningfulBond {} => to_binary(&query_min_meaningful_bond(deps)?),
+        QueryMsg::Account { address } => to_binary(&query_account(deps, address)?),
+        QueryMsg::Tvl {} => to_binary(&query_tvl(deps, env)?),
+        QueryMsg::BalanceSheet {} => to_binary(&query_balance_sheet(deps, env)?),
+        QueryMsg::UnbondStatus { address } => to_binary(&query_unbond_status(deps, address)?),
+        QueryMsg::ValidatorRewards {} => to_binary(&query_validator_rewards(deps, env)?),
+        QueryMsg::Redeemable { address } => to_binary(&query_redeemable(deps, env, address)?),
+        QueryMsg::FeesEarned {} => to_binary(&query_fees_earned(deps)?),
+        QueryMsg::OwnerFees {} => to_binary(&query_owner_fees(deps)?),
+        QueryMsg::TargetValidators {} => to_binary(&query_target_validators(deps)?),
+        QueryMsg::DelegatorsByValidator {
+            validator,
+            start_after,
+            limit,
+        } => to_binary(&query_delegators_by_validator(
+            deps,
+            validator,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::ContractBalance {} => to_binary(&query_contract_balance(deps, env)?),
+        QueryMsg::ValidatorStats {} => to_binary(&query_validator_stats(deps, env)?),
+        QueryMsg::ValidatorActive {} => to_binary(&query_validator_active(deps)?),
+        QueryMsg::ValidatorHistory {} => to_binary(&query_validator_history(deps)?),
+        QueryMsg::RankedValidators { limit } => to_binary(&query_ranked_validators(deps, limit)?),
+        QueryMsg::CanBond {} => to_binary(&query_can_bond(deps)?),
+        QueryMsg::DelegatorCount {} => to_binary(&query_delegator_count(deps)?),
+        QueryMsg::PendingUnbondCount {} => to_binary(&query_pending_unbond_count(deps)?),
+        QueryMsg::ExitTaxPreview { address, amount } => {
+            to_binary(&query_exit_tax_preview(deps, env, address, amount)?)
+        }
+    }
+}
+
+fn query_delegation<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+) -> StdResult<DelegateResponse> {
+    let address_raw = deps.api.canonical_address(&address)?;
+    let delegation = delegations_read(&deps.storage)
+        .may_load(address_raw.as_slice())
+        .unwrap_or_default();
+    Ok(delegation.unwrap())
+}
+
+fn query_all_delegators<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<Vec<HumanAddr>> {
+    delegators_read(&deps.storage).load()
+}
+
+pub fn query_token_info<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<TokenInfoResponse> {
+    token_info_read(&deps.storage).load()
+}
+
+pub fn query_balance<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+) -> StdResult<BalanceResponse> {
+    let address_raw = deps.api.canonical_address(&address)?;
+    let balance = balances_read(&deps.storage)
+        .may_load(address_raw.as_slice())?
+        .unwrap_or_default();
+    Ok(BalanceResponse { balance })
+}
+
+pub fn query_balance_at<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+    height: u64,
+) -> StdResult<BalanceResponse> {
+    if invest_info_read(&deps.storage)
+        .load()?
+        .snapshot_retention
+        .is_none()
+    {
+        return Err(StdError::generic_err("Balance snapshots are not enabled"));
+    }
+    let address_raw = deps.api.canonical_address(&address)?;
+    // the latest snapshot at or before `height`; none means nothing was held yet
+    let end = height.saturating_add(1).to_be_bytes();
+    let balance = balance_snapshots_read(&deps.storage, &address_raw)
+        .range(None, Some(&end), Order::Descending)
+        .next()
+        .transpose()?
+        .map(|(_, balance)| balance)
+        .unwrap_or_default();
+    Ok(BalanceResponse { balance })
+}
+
+pub fn query_allowance<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    owner: HumanAddr,
+    spender: HumanAddr,
+) -> StdResult<AllowanceResponse> {
+    let owner_raw = deps.api.canonical_address(&owner)?;
+    let spender_raw = deps.api.canonical_address(&spender)?;
+    let allowance = allowances_read(&deps.storage, &owner_raw)
+        .may_load(spender_raw.as_slice())?
+        .unwrap_or_default();
+    Ok(allowance)
+}
+
+pub fn query_all_allowances<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    owner: HumanAddr,
+    start_after: Option<HumanAddr>,
+    limit: Option<u32>,
+) -> StdResult<AllAllowancesResponse> {
+    let owner_raw = deps.api.canonical_address(&owner)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = calc_range_start(&deps.api, start_after)?;
+
+    let allowances = allowances_read(&deps.storage, &owner_raw)
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (spender_raw, allow) = item?;
+            Ok(AllowanceInfo {
+                spender: deps.api.human_address(&CanonicalAddr::from(spender_raw))?,
+                allowance: allow.allowance,
+                expires: allow.expires,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(AllAllowancesResponse { allowances })
+}
+
+pub fn query_paged_delegations<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    start_after: Option<HumanAddr>,
+    limit: Option<u32>,
+) -> StdResult<AllDelegationsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = calc_range_start(&deps.api, start_after)?;
+
+    let delegations = delegations_read(&deps.storage)
+        .range(start.as_deref(), None, Order::Ascending)
+        .take(limit)
+        .map(|item| Ok(item?.1))
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(AllDelegationsResponse { delegations })
+}
+
+// query_delegators_by_validator pages through the delegation records on `validator`,
+// e.g. to find who is exposed to a validator about to be jailed
+pub fn query_delegators_by_validator<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    validator: HumanAddr,
+    start_after: Option<HumanAddr>,
+    limit: Option<u32>,
+) -> StdResult<AllDelegationsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = calc_range_start(&deps.api, start_after)?;
+
+    let delegations = delegations_read(&deps.storage)
+        .range(start.as_deref(), None, Order::Ascending)
+        .filter(|item| match item {
+            Ok((_, delegation)) => delegation.validator == validator,
+            Err(_) => true,
+        })
+        .take(limit)
+        .map(|item| Ok(item?.1))
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(AllDelegationsResponse { delegations })
+}
+
+// calc_range_start turns the last address of the previous page into an exclusive range start
+fn calc_range_start<A: Api>(api: &A, start_after: Option<HumanAddr>) -> StdResult<Option<Vec<u8>>> {
+    match start_after {
+        Some(human) => {
+            let mut v: Vec<u8> = api.canonical_address(&human)?.into();
+            v.push(0);
+            Ok(Some(v))
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn query_claims<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+) -> StdResult<ClaimsResponse> {
+    let address_raw = deps.api.canonical_address(&address)?;
+    let claims = total_claims(&deps.storage, &address_raw)?;
+    let invest = invest_info_read(&deps.storage).load()?;
+    Ok(ClaimsResponse {
+        claims,
+        coin: coin(claims.u128(), invest.bond_denom),
+    })
+}
+
+pub fn query_unbond_schedule<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+) -> StdResult<UnbondScheduleResponse> {
+    let address_raw = deps.api.canonical_address(&address)?;
+    let mut schedule: Vec<(Uint128, u64)> = claims_read(&deps.storage)
+        .may_load(address_raw.as_slice())?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|claim| (claim.amount, claim.release_height))
+        .collect();
+    schedule.sort_by_key(|(_, release_height)| *release_height);
+    Ok(UnbondScheduleResponse { schedule })
+}
+
+pub fn query_unbonding<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+    address: HumanAddr,
+) -> StdResult<UnbondingResponse> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    let address_raw = deps.api.canonical_address(&address)?;
+    let unbonding: Uint128 = claims_read(&deps.storage)
+        .may_load(address_raw.as_slice())?
+        .unwrap_or_default()
+        .iter()
+        .filter(|claim| claim.release_height > env.block.height)
+        .map(|claim| claim.amount)
+        .sum();
+    Ok(UnbondingResponse {
+        unbonding: coin(unbonding.u128(), invest.bond_denom),
+    })
+}
+
+pub fn query_claims_breakdown<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<ClaimsBreakdownResponse> {
+    let mut matured = Uint128::zero();
+    let mut pending = Uint128::zero();
+    for item in claims_read(&deps.storage).range(None, None, Order::Ascending) {
+        let (_, entries) = item?;
+        for claim in entries {
+            if claim.release_height <= env.block.height {
+                matured += claim.amount;
+            } else {
+                pending += claim.amount;
+            }
+        }
+    }
+    Ok(ClaimsBreakdownResponse { matured, pending })
+}
+
+pub fn query_investment<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<InvestmentResponse> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    let supply = supply_or_default(&deps.storage)?;
+
+    let res = InvestmentResponse {
+        owner: deps.api.human_address(&invest.owner)?,
+        exit_tax: invest.exit_tax,
+        validator: invest.validator,
+        validators: invest.validators,
+        min_withdrawal: invest.min_withdrawal,
+        selection_strategy: invest.selection_strategy,
+        max_delegators: invest.max_delegators,
+        distribute_rewards: invest.distribute_rewards,
+        compound_ratio: invest.compound_ratio,
+        reinvest_min_ratio: invest.reinvest_min_ratio,
+        display_denom: invest.display_denom,
+        display_exponent: invest.display_exponent,
+        fee_recipients: invest.fee_recipients,
+        min_age_before_redelegate: invest.min_age_before_redelegate,
+        min_hold_blocks: invest.min_hold_blocks,
+        blocks_per_year: invest.blocks_per_year,
+        tax_tiers: invest.tax_tiers,
+        auto_callback: invest.auto_callback,
+        min_commission: invest.min_commission,
+        max_commission: invest.max_commission,
+        max_validator_share: invest.max_validator_share,
+        instant_unbond_fee: invest.instant_unbond_fee,
+        reward_fee: invest.reward_fee,
+        snapshot_retention: invest.snapshot_retention,
+        deny_list: invest.deny_list,
+        withdraw_address: invest.withdraw_address,
+        bond_stray: invest.bond_stray,
+        init_height: invest.init_height,
+        init_time: invest.init_time,
+        schema_version: CONTRACT_VERSION,
+        token_supply: supply.issued,
+        staked_tokens: coin(supply.bonded.u128(), &invest.bond_denom),
+        nominal_value: if supply.issued.is_zero() {
+            FALLBACK_RATIO
+        } else {
+            Decimal::from_ratio(supply.bonded, supply.issued)
+        },
+    };
+    Ok(res)
+}
+
+pub fn query_reinvest_preview<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<ReinvestPreviewResponse> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    let mut supply = supply_or_default(&deps.storage)?;
+    let contract_addr = env.contract.address;
+
+    let rewards = pending_rewards(&deps.querier, &contract_addr, &invest.bond_denom)?;
+    let balance = deps
+        .querier
+        .query_balance(&contract_addr, &invest.bond_denom)?;
+
+    // mirror reinvest_rewards and _bond_all_tokens: the fee is set aside from the
+    // withdrawn rewards, and the callback splits the balance after withdrawal, minus
+    // pending claims, fees and owed rewards. Rewards paid to a withdraw address never
+    // reach the callback
+    let total_rewards: Uint128 = rewards.iter().map(|(_, amount)| *amount).sum();
+    supply.fees += total_rewards * invest.reward_fee;
+    let free = (unreserved(balance.amount + total_rewards, &supply) - owed_rewards(deps)?)
+        .unwrap_or_default();
+    let (_, weights) = delegator_weights(deps)?;
+    let can_distribute = weights.iter().any(|weight| !weight.is_zero());
+    let split = match invest.withdraw_address {
+        Some(_) => None,
+        None => split_rewards(&invest, &supply, free, can_distribute),
+    };
+    let (to_bond, to_distribute) = split
+        .as_ref()
+        .map_or((Uint128::zero(), Uint128::zero()), |split| {
+            (split.bonded(), split.distributed)
+        });
+    let withdraw_from = if to_bond.is_zero() && to_distribute.is_zero() {
+        vec![]
+    } else {
+        rewards
+            .into_iter()
+            .map(|(validator, _)| validator)
+            .collect()
+    };
+
+    Ok(ReinvestPreviewResponse {
+        withdraw_from,
+        to_bond: coin(to_bond.u128(), &invest.bond_denom),
+        to_distribute: coin(to_distribute.u128(), &invest.bond_denom),
+        validator: invest.validator,
+    })
+}
+
+pub fn query_unrealized_gain<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+) -> StdResult<UnrealizedGainResponse> {
+    let address_raw = deps.api.canonical_address(&address)?;
+    let balance = balances_read(&deps.storage)
+        .may_load(address_raw.as_slice())?
+        .unwrap_or_default();
+    let cost_basis = delegations_read(&deps.storage)
+        .may_load(address_raw.as_slice())?
+        .map(|d| d.cost_basis)
+        .unwrap_or_default();
+
+    // value the balance at the current nominal_value
+    let supply = supply_or_default(&deps.storage)?;
+    let value = if supply.issued.is_zero() {
+        FALLBACK_RATIO * balance
+    } else {
+        balance.multiply_ratio(supply.bonded, supply.issued)
+    };
+    let gain = (value - cost_basis).unwrap_or_default();
+
+    Ok(UnrealizedGainResponse {
+        value,
+        cost_basis,
+        gain,
+    })
+}
+
+// exit_tax_rate is what unbonding at `height` would charge the address, as in
+// reserve_unbond. Addresses without a delegation pay the flat exit_tax
+fn exit_tax_rate<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    invest: &InvestmentInfo,
+    address_raw: &CanonicalAddr,
+    height: u64,
+) -> StdResult<Decimal> {
+    let rate = match delegations_read(&deps.storage).may_load(address_raw.as_slice())? {
+        Some(delegation) => {
+            invest.exit_tax_for(height.saturating_sub(delegation.first_bond_height))
+        }
+        None => invest.exit_tax,
+    };
+    Ok(rate)
+}
+
+pub fn query_redeemable<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+    address: HumanAddr,
+) -> StdResult<RedeemableResponse> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    let supply = supply_or_default(&deps.storage)?;
+    let address_raw = deps.api.canonical_address(&address)?;
+    let balance = balances_read(&deps.storage)
+        .may_load(address_raw.as_slice())?
+        .unwrap_or_default();
+
+    let rate = exit_tax_rate(deps, &invest, &address_raw, env.block.height)?;
+    let value = native_value(balance, &supply);
+    let net = (value - exit_tax_on(value, rate))?;
+    Ok(RedeemableResponse {
+        redeemable: coin(net.u128(), invest.bond_denom),
+    })
+}
+
+pub fn query_exit_tax_preview<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+    address: HumanAddr,
+    amount: Uint128,
+) -> StdResult<ExitTaxPreviewResponse> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    let supply = supply_or_default(&deps.storage)?;
+    let address_raw = deps.api.canonical_address(&address)?;
+
+    let rate = exit_tax_rate(deps, &invest, &address_raw, env.block.height)?;
+    let tax_derivative = exit_tax_on(amount, rate);
+    Ok(ExitTaxPreviewResponse {
+        gross_native: native_value(amount, &supply),
+        tax_derivative,
+        net_native: native_value((amount - tax_derivative)?, &supply),
+    })
+}
+
+pub fn query_fees_earned<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<FeesEarnedResponse> {
+    let fees_earned = fees_earned_read(&deps.storage)
+        .may_load()?
+        .unwrap_or_default();
+    Ok(FeesEarnedResponse { fees_earned })
+}
+
+/// the configured validators are weighted equally
+pub fn query_target_validators<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<TargetValidatorsResponse> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    // the validators share the stake equally. 1/n is rounded down, so the last one takes
+    // the remainder and the weights add up to exactly 1
+    let count = invest.validators.len() as u128;
+    let last = count.saturating_sub(1) as usize;
+    let share = DECIMAL_FRACTIONAL / count.max(1);
+    let remainder = DECIMAL_FRACTIONAL - share * count;
+    let validators = invest
+        .validators
+        .into_iter()
+        .enumerate()
+        .map(|(i, validator)| {
+            let atomics = if i == last { share + remainder } else { share };
+            (validator, Decimal::from_ratio(atomics, DECIMAL_FRACTIONAL))
+        })
+        .collect();
+    Ok(TargetValidatorsResponse { validators })
+}
+
+/// the owner's balance is split into fee income, as tracked by fees_earned, and the rest.
+/// Tokens the owner moved away count against the fees first
+pub fn query_owner_fees<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<OwnerFeesResponse> {
+    let owner = invest_info_read(&deps.storage).load()?.owner;
+    let balance = balances_read(&deps.storage)
+        .may_load(owner.as_slice())?
+        .unwrap_or_default();
+    let earned = fees_earned_read(&deps.storage)
+        .may_load()?
+        .unwrap_or_default();
+    let fees = earned.min(balance);
+    Ok(OwnerFeesResponse {
+        fees,
+        bonds: (balance - fees)?,
+    })
+}
+
+pub fn query_validator_rewards<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<ValidatorRewardsResponse> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    let rewards = validator_rewards(&deps.querier, &env.contract.address, &invest.bond_denom)?
+        .into_iter()
+        .map(|(validator, reward)| (validator, coin(reward.u128(), &invest.bond_denom)))
+        .collect();
+    Ok(ValidatorRewardsResponse { rewards })
+}
+
+pub fn query_unbond_status<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+) -> StdResult<UnbondStatusResponse> {
+    let address_raw = deps.api.canonical_address(&address)?;
+    let delegation = delegations_read(&deps.storage)
+        .may_load(address_raw.as_slice())?
+        .ok_or_else(|| StdError::not_found("Delegation"))?;
+    let expiry = invest_info_read(&deps.storage)
+        .load()?
+        .blocks_for_duration(EXPIRY_SECONDS);
+
+    Ok(UnbondStatusResponse {
+        unbond_flag: delegation.unbond_flag,
+        eligible_at: delegation.last_delegate_height + expiry + 1,
+        amount: delegation.amount,
+    })
+}
+
+pub fn query_contract_balance<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<ContractBalanceResponse> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    let balance = deps
+        .querier
+        .query_balance(&env.contract.address, &invest.bond_denom)?;
+    Ok(ContractBalanceResponse { balance })
+}
+
+pub fn query_validator_active<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<ValidatorActiveResponse> {
+    let validator = invest_info_read(&deps.storage).load()?.validator;
+    let active = deps
+        .querier
+        .query_validators()?
+        .iter()
+        .any(|v| v.address == validator);
+    Ok(ValidatorActiveResponse { validator, active })
+}
+
+pub fn query_validator_history<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<ValidatorHistoryResponse> {
+    let validators = validator_history_read(&deps.storage)
+        .range(None, None, Order::Ascending)
+        .map(|item| {
+            let (key, since) = item?;
+            let validator = HumanAddr::from(String::from_utf8(key)?);
+            Ok(ValidatorHistoryEntry { validator, since })
+        })
+        .collect::<StdResult<_>>()?;
+    Ok(ValidatorHistoryResponse { validators })
+}
+
+pub fn query_validator_stats<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<ValidatorStatsResponse> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    let contract = env.contract.address;
+    let active = deps
+        .querier
+        .query_all_delegations(&contract)?
+        .iter()
+        .filter(|d| !d.amount.amount.is_zero())
+        .count();
+    Ok(ValidatorStatsResponse {
+        configured: invest.validators.len() as u32,
+        active: active as u32,
+        total_bonded: get_bonded(&deps.querier, &contract, &invest.bond_denom)?,
+    })
+}
+
+pub fn query_tvl<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<TvlResponse> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    let contract_addr = env.contract.address;
+
+    let bonded = get_bonded(&deps.querier, &contract_addr, &invest.bond_denom)?;
+    let balance = deps
+        .querier
+        .query_balance(&contract_addr, &invest.bond_denom)?
+        .amount;
+    let pending: Uint128 = pending_rewards(&deps.querier, &contract_addr, &invest.bond_denom)?
+        .into_iter()
+        .map(|(_, reward)| reward)
+        .sum();
+
+    // the part of the balance held for claims and fees is already owed out
+    let free = unreserved(balance, &supply_or_default(&deps.storage)?);
+    let reserved = (balance - free)?;
+    Ok(TvlResponse {
+        tvl: coin((bonded + free + pending).u128(), &invest.bond_denom),
+        reserved: coin(reserved.u128(), &invest.bond_denom),
+    })
+}
+
+pub fn query_balance_sheet<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<BalanceSheetResponse> {
+    let tvl = query_tvl(deps, env)?;
+    let assets = tvl.tvl.amount + tvl.reserved.amount;
+    let supply = supply_or_default(&deps.storage)?;
+    let derivative_value = if supply.issued.is_zero() {
+        Uint128::zero()
+    } else {
+        native_value(supply.issued, &supply)
+    };
+    let obligations = derivative_value + supply.claims + supply.fees;
+    let coverage = if obligations.is_zero() {
+        Decimal::one()
+    } else {
+        Decimal::from_ratio(assets, obligations)
+    };
+    Ok(BalanceSheetResponse {
+        assets,
+        obligations,
+        coverage,
+    })
+}
+
+pub fn query_account<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+) -> StdResult<AccountResponse> {
+    let address_raw = deps.api.canonical_address(&address)?;
+    let balance = balances_read(&deps.storage)
+        .may_load(address_raw.as_slice())?
+        .unwrap_or_default();
+    let claims = total_claims(&deps.storage, &address_raw)?;
+    let delegation = delegations_read(&deps.storage).may_load(address_raw.as_slice())?;
+    Ok(AccountResponse {
+        balance,
+        claims,
+        delegation,
+    })
+}
+
+pub fn query_effective_rate<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<EffectiveRateResponse> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    let supply = supply_or_default(&deps.storage)?;
+    if supply.issued.is_zero() {
+        return Ok(EffectiveRateResponse {
+            raw: FALLBACK_RATIO,
+            effective: FALLBACK_RATIO,
+        });
+    }
+
+    // the reward fee is set aside when the rewards are withdrawn, only the rest ends up
+    // backing the derivative
+    let pending: Uint128 =
+        pending_rewards(&deps.querier, &env.contract.address, &invest.bond_denom)?
+            .into_iter()
+            .map(|(_, reward)| reward)
+            .sum();
+    let backing = (pending - pending * invest.reward_fee)?;
+    Ok(EffectiveRateResponse {
+        raw: Decimal::from_ratio(supply.bonded, supply.issued),
+        effective: Decimal::from_ratio(supply.bonded + backing, supply.issued),
+    })
+}
+
+pub fn query_backed_rate<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<BackedRateResponse> {
+    let supply = supply_or_default(&deps.storage)?;
+    if supply.issued.is_zero() {
+        return Ok(BackedRateResponse {
+            nominal: FALLBACK_RATIO,
+            backed: FALLBACK_RATIO,
+        });
+    }
+
+    // reserve_unbond takes the native value of a claim out of bonded as it burns the
+    // tokens, so bonded is already what backs the remaining ones
+    Ok(BackedRateResponse {
+        nominal: Decimal::from_ratio(supply.bonded, supply.issued),
+        backed: Decimal::from_ratio(supply.bonded, supply.issued),
+    })
+}
+
+/// query_min_meaningful_bond finds the smallest amount for which bond's
+/// amount * issued / bonded rounds down to at least one token
+pub fn query_min_meaningful_bond<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<MinMeaningfulBondResponse> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    let supply = supply_or_default(&deps.storage)?;
+    let amount = if supply.issued.is_zero() || supply.bonded.is_zero() {
+        // FALLBACK_RATIO mints one token per native token
+        Uint128(1)
+    } else {
+        let (bonded, issued) = (supply.bonded.u128(), supply.issued.u128());
+        Uint128(bonded.div_ceil(issued))
+    };
+    Ok(MinMeaningfulBondResponse {
+        minimum: coin(amount.u128(), &invest.bond_denom),
+    })
+}
+
+/// query_can_bond checks the conditions under which a new bond would fail: no validator
+/// to delegate to, or no room for another delegator
+pub fn query_can_bond<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<CanBondResponse> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    let reason = if rank_validators(deps)?.is_empty() {
+        Some(format!(
+            "No validator outside the deny list with a commission between {} and {}",
+            invest.min_commission, invest.max_commission
+        ))
+    } else {
+        let count = delegator_count_read(&deps.storage)
+            .may_load()?
+            .unwrap_or_default();
+        match invest.max_delegators {
+            Some(max) if count >= max as u64 => {
+                Some(format!("Cannot register more than {} delegators", max))
+            }
+            _ => None,
+        }
+    };
+    Ok(CanBondResponse {
+        can_bond: reason.is_none(),
+        reason,
+    })
+}
+
+pub fn query_delegator_count<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<DelegatorCountResponse> {
+    let count = delegator_count_read(&deps.storage)
+        .may_load()?
+        .unwrap_or_default();
+    Ok(DelegatorCountResponse { count })
+}
+
+pub fn query_pending_unbond_count<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<PendingUnbondCountResponse> {
+    let count = pending_unbonds_read(&deps.storage)
+        .may_load()?
+        .unwrap_or_default();
+    Ok(PendingUnbondCountResponse { count })
+}
+
+pub fn query_is_delegator<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    address: HumanAddr,
+) -> StdResult<IsDelegatorResponse> {
+    let is_delegator = delegators_read(&deps.storage)
+        .may_load()?
+        .unwrap_or_default()
+        .contains(&address);
+    Ok(IsDelegatorResponse { is_delegator })
+}
+
+pub fn query_next_expiry<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+    address: HumanAddr,
+) -> StdResult<NextExpiryResponse> {
+    let address_raw = deps.api.canonical_address(&address)?;
+    let delegation = delegations_read(&deps.storage)
+        .may_load(address_raw.as_slice())?
+        .ok_or_else(|| StdError::not_found("Delegation"))?;
+
+    let expiry = invest_info_read(&deps.storage)
+        .load()?
+        .blocks_for_duration(EXPIRY_SECONDS);
+    let expires_at = delegation.last_delegate_height + expiry;
+    Ok(NextExpiryResponse {
+        expires_at,
+        eligible: env.block.height > expires_at,
+    })
+}
+
+pub fn query_expired_count<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<ExpiredCountResponse> {
+    let expiry = invest_info_read(&deps.storage)
+        .load()?
+        .blocks_for_duration(EXPIRY_SECONDS);
+    // same condition as the sweep in is_expired
+    let mut count = 0;
+    for item in delegations_read(&deps.storage).range(None, None, Order::Ascending) {
+        let (_, delegation) = item?;
+        let last = delegation.last_delegate_height;
+        if env.block.height.saturating_sub(last) > expiry {
+            count += 1;
+        }
+    }
+    Ok(ExpiredCountResponse { count })
+}
+
+/// only bond_denom delegations count. Stake left on a validator that dropped out of the
+/// set pays no commission it could report, so it is skipped as well
+pub fn query_blended_commission<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    env: Env,
+) -> StdResult<BlendedCommissionResponse> {
+    let invest = invest_info_read(&deps.storage).load()?;
+    let validators = query_validators(deps)?;
+    let bonds = deps.querier.query_all_delegations(&env.contract.address)?;
+
+    // the weighted sum is kept in Decimal atomics, so only the final ratio is rounded
+    let mut weighted: u128 = 0;
+    let mut total = Uint128::zero();
+    for bond in bonds.iter().filter(|b| b.amount.denom == invest.bond_denom) {
+        let validator = match validators.iter().find(|v| v.address == bond.validator) {
+            Some(validator) => validator,
+            None => continue,
+        };
+        let atomics = validator.commission * Uint128(DECIMAL_FRACTIONAL);
+        weighted = atomics
+            .u128()
+            .checked_mul(bond.amount.amount.u128())
+            .and_then(|product| weighted.checked_add(product))
+            .ok_or_else(|| StdError::generic_err("Blended commission overflows"))?;
+        total += bond.amount.amount;
+    }
+
+    let commission = if total.is_zero() {
+        Decimal::zero()
+    } else {
+        Decimal::from_ratio(weighted / total.u128(), DECIMAL_FRACTIONAL)
+    };
+    Ok(BlendedCommissionResponse { commission })
+}
+
+pub fn query_ranked_validators<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+    limit: Option<u32>,
+) -> StdResult<RankedValidatorsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let mut validators = rank_validators(deps)?;
+    validators.truncate(limit);
+    Ok(RankedValidatorsResponse { validators })
+}
+
+fn query_validators<S: Storage, A: Api, Q: Querier>(
+    deps: &Extern<S, A, Q>,
+) -> StdResult<Vec<Validator>> {
+    let request = StakingQuery::Validators {}.into();
+    let res: ValidatorsResponse = deps.querier.query(&QueryRequest::Staking(request))?;
+    Ok(res.validators)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_info, MockQuerier, MOCK_CONTRACT_ADDR,
+    };
+    use cosmwasm_std::{
+        coins, Coin, CosmosMsg, Decimal, FullDelegation, ReadonlyStorage, Validator,
+    };
+    use cosmwasm_storage::{Bucket, Singleton};
+    use serde::{Deserialize, Serialize};
+    use std::str::FromStr;
+
+    use crate::state::{KEY_INVESTMENT, KEY_TOTAL_SUPPLY, PREFIX_CLAIMS, PREFIX_DELEGATIONS};
+
+    // the default block time turns the durations into these windows
+    const EXPIRY_BLOCKS: u64 = 25_920;
+    const CLAIM_EXPIRY_BLOCKS: u64 = 6_307_200;
+    const UNBONDING_BLOCKS: u64 = 362_880;
+
+    fn sample_validator<U: Into<HumanAddr>>(addr: U) -> Validator {
+        Validator {
+            address: addr.into(),
+            commission: Decimal::percent(3),
+            max_commission: Decimal::percent(10),
+            max_change_rate: Decimal::percent(1),
+        }
+    }
+
+    fn custom_sample_validator<U: Into<HumanAddr>>(
+        addr: U,
+        comm: u64,
+        max_comm: u64,
+        rate: u64,
+    ) -> Validator {
+        Validator {
+            address: addr.into(),
+            commission: Decimal::percent(comm),
+            max_commission: Decimal::percent(max_comm),
+            max_change_rate: Decimal::percent(rate),
+        }
+    }
+
+    fn sample_delegation<U: Into<HumanAddr>>(addr: U, amount: Coin) -> FullDelegation {
+        let can_redelegate = amount.clone();
+        FullDelegation {
+            validator: addr.into(),
+            delegator: HumanAddr::from(MOCK_CONTRACT_ADDR),
+            amount,
+            can_redelegate,
+            accumulated_rewards: Vec::new(),
+        }
+    }
+
+    fn sample_delegation_with_rewards<U: Into<HumanAddr>>(
+        addr: U,
+        amount: Coin,
+        rewards: Coin,
+    ) -> FullDelegation {
+        FullDelegation {
+            accumulated_rewards: vec![rewards],
+            ..sample_delegation(addr, amount)
+        }
+    }
+
+    fn set_validator(querier: &mut MockQuerier) {
+        querier.update_staking("ustake", &[sample_validator(DEFAULT_VALIDATOR)], &[]);
+    }
+
+    fn set_delegation(querier: &mut MockQuerier, amount: u128, denom: &str) {
+        querier.update_staking(
+            "ustake",
+            &[sample_validator(DEFAULT_VALIDATOR)],
+            &[sample_delegation(DEFAULT_VALIDATOR, coin(amount, denom))],
+        );
+    }
+
+    const DEFAULT_VALIDATOR: &str = "default-validator";
+
+    // stores a delegation record for the address and registers it as a delegator
+    fn seed_delegation<S: Storage, A: Api, Q: Querier>(
+        deps: &mut Extern<S, A, Q>,
+        addr: &str,
+        validator: &str,
+        amount: u128,
+        height: u64,
+    ) {
+        let delegator = HumanAddr::from(addr);
+        let key = deps.api.canonical_address(&delegator).unwrap();
+        let info = DelegateInfo {
+            delegator: delegator.clone(),
+            validator: HumanAddr::from(validator),
+            amount: Uint128(amount),
+            last_delegate_height: height,
+            ..DelegateInfo::default()
+        };
+        delegations(&mut deps.storage)
+            .save(key.as_slice(), &info)
+            .unwrap();
+        let mut list = delegators_read(&deps.storage)
+            .may_load()
+            .unwrap()
+            .unwrap_or_default();
+        list.push(delegator);
+        delegators(&mut deps.storage).save(&list).unwrap();
+    }
+
+    fn seed_reward<S: Storage, A: Api, Q: Querier>(
+        deps: &mut Extern<S, A, Q>,
+        addr: &str,
+        amount: u128,
+    ) {
+        let key = deps.api.canonical_address(&HumanAddr::from(addr)).unwrap();
+        delegations(&mut deps.storage)
+            .update(key.as_slice(), |d| -> StdResult<_> {
+                let mut d = d.unwrap();
+                d.undelegate_reward = Uint128(amount);
+                Ok(d)
+            })
+            .unwrap();
+    }
+
+    fn default_init(tax_percent: u64, min_withdrawal: u128) -> InitMsg {
+        InitMsg {
+            name: "Cool Derivative".to_string(),
+            symbol: "DRV".to_string(),
+            decimals: 9,
+            validator: HumanAddr::from(DEFAULT_VALIDATOR),
+            exit_tax: Decimal::percent(tax_percent),
+            min_withdrawal: Uint128(min_withdrawal),
+            selection_strategy: None,
+            max_delegators: None,
+            distribute_rewards: false,
+            reinvest_min_ratio: None,
+            display_denom: None,
+            display_exponent: None,
+            fee_recipients: None,
+            validators: None,
+            min_age_before_redelegate: None,
+            auto_callback: None,
+            min_commission: None,
+            max_commission: None,
+            blocks_per_year: None,
+            tax_tiers: None,
+            min_hold_blocks: None,
+            max_validator_share: None,
+            instant_unbond_fee: None,
+            reward_fee: None,
+            snapshot_retention: None,
+            deny_list: None,
+            bond_stray: None,
+            compound_ratio: None,
+        }
+    }
+
+    fn get_balance<S: Storage, A: Api, Q: Querier, U: Into<HumanAddr>>(
+        deps: &Extern<S, A, Q>,
+        addr: U,
+    ) -> Uint128 {
+        query_balance(&deps, addr.into()).unwrap().balance
+    }
+
+    fn get_claims<S: Storage, A: Api, Q: Querier, U: Into<HumanAddr>>(
+        deps: &Extern<S, A, Q>,
+        addr: U,
+    ) -> Uint128 {
+        query_claims(&deps, addr.into()).unwrap().claims
+    }
+
+    #[test]
+    fn initialization_with_missing_validator() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier
+            .update_staking("ustake", &[sample_validator("john")], &[]);
+
+        let creator = HumanAddr::from("creator");
+        let msg = InitMsg {
+            name: "Cool Derivative".to_string(),
+            symbol: "DRV".to_string(),
+            decimals: 9,
+            validator: HumanAddr::from("my-validator"),
+            exit_tax: Decimal::percent(2),
+            min_withdrawal: Uint128(50),
+            selection_strategy: None,
+            max_delegators: None,
+            distribute_rewards: false,
+            reinvest_min_ratio: None,
+            display_denom: None,
+            display_exponent: None,
+            fee_recipients: None,
+            validators: None,
+            min_age_before_redelegate: None,
+            auto_callback: None,
+            min_commission: None,
+            max_commission: None,
+            blocks_per_year: None,
+            tax_tiers: None,
+            min_hold_blocks: None,
+            max_validator_share: None,
+            instant_unbond_fee: None,
+            reward_fee: None,
+            snapshot_retention: None,
+            deny_list: None,
+            bond_stray: None,
+            compound_ratio: None,
+        };
+        let info = mock_info(&creator, &[]);
+
+        // make sure we can init with this
+        let res = init(&mut deps, mock_env(), info, msg.clone());
+        match res.unwrap_err() {
+            StdError::GenericErr { msg, .. } => {
+                assert_eq!(msg, "my-validator is not in the current validator set")
+            }
+            _ => panic!("expected unregistered validator error"),
+        }
+    }
+
+    #[test]
+    fn proper_initialization() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier.update_staking(
+            "ustake",
+            &[
+                sample_validator("john"),
+                sample_validator("mary"),
+                sample_validator("my-validator"),
+            ],
+            &[],
+        );
+
+        let creator = HumanAddr::from("creator");
+        let msg = InitMsg {
+            name: "Cool Derivative".to_string(),
+            symbol: "DRV".to_string(),
+            decimals: 0,
+            validator: HumanAddr::from("my-validator"),
+            exit_tax: Decimal::percent(2),
+            min_withdrawal: Uint128(50),
+            selection_strategy: None,
+            max_delegators: None,
+            distribute_rewards: false,
+            reinvest_min_ratio: None,
+            display_denom: None,
+            display_exponent: None,
+            fee_recipients: None,
+            validators: None,
+            min_age_before_redelegate: None,
+            auto_callback: None,
+            min_commission: None,
+            max_commission: None,
+            blocks_per_year: None,
+            tax_tiers: None,
+            min_hold_blocks: None,
+            max_validator_share: None,
+            instant_unbond_fee: None,
+            reward_fee: None,
+            snapshot_retention: None,
+            deny_list: None,
+            bond_stray: None,
+            compound_ratio: None,
+        };
+        let info = mock_info(&creator, &[]);
+
+        // make sure we can init with this
+        let res = init(&mut deps, mock_env(), info, msg.clone()).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // token info is proper
+        let token = query_token_info(&deps).unwrap();
+        assert_eq!(&token.name, &msg.name);
+        assert_eq!(&token.symbol, &msg.symbol);
+        assert_eq!(token.decimals, msg.decimals);
+
+        // no balance
+        assert_eq!(get_balance(&deps, &creator), Uint128(0));
+        // no claims
+        assert_eq!(get_claims(&deps, &creator), Uint128(0));
+
+        // investment info correct
+        let invest = query_investment(&deps).unwrap();
+        assert_eq!(&invest.owner, &creator);
+        assert_eq!(&invest.validator, &msg.validator);
+        assert_eq!(invest.exit_tax, msg.exit_tax);
+        assert_eq!(invest.min_withdrawal, msg.min_withdrawal);
+
+        assert_eq!(invest.token_supply, Uint128(0));
+        assert_eq!(invest.staked_tokens, coin(0, "ustake"));
+        assert_eq!(invest.nominal_value, Decimal::one());
+        assert_eq!(invest.schema_version, 7);
+    }
+
+    #[test]
+    fn init_records_block_height() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+
+        let creator = HumanAddr::from("creator");
+        let info = mock_info(&creator, &[]);
+        let mut env = mock_env();
+        env.block.height = 4_321;
+        init(&mut deps, env.clone(), info, default_init(2, 50)).unwrap();
+
+        let invest = query_investment(&deps).unwrap();
+        assert_eq!(invest.init_height, 4_321);
+        assert_eq!(invest.init_time, env.block.time);
+    }
+
+    #[test]
+    fn bonding_issues_tokens() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+
+        let creator = HumanAddr::from("creator");
+        let init_msg = default_init(2, 50);
+        let info = mock_info(&creator, &[]);
+
+        // make sure we can init with this
+        let res = init(&mut deps, mock_env(), info, init_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // let's bond some tokens now
+        let bob = HumanAddr::from("bob");
+        let bond_msg = HandleMsg::Bond { validator: None };
+        let info = mock_info(&bob, &[coin(10, "random"), coin(1000, "ustake")]);
+
+        // try to bond and make sure we trigger delegation
+        let res = handle(&mut deps, mock_env(), info, bond_msg).unwrap();
+        assert_eq!(1, res.messages.len());
+        let delegate = &res.messages[0];
+        match delegate {
+            CosmosMsg::Staking(StakingMsg::Delegate { validator, amount }) => {
+                assert_eq!(validator.as_str(), DEFAULT_VALIDATOR);
+                assert_eq!(amount, &coin(1000, "ustake"));
+            }
+            _ => panic!("Unexpected message: {:?}", delegate),
+        }
+
+        // bob got 1000 DRV for 1000 stake at a 1.0 ratio
+        assert_eq!(get_balance(&deps, &bob), Uint128(1000));
+
+        // investment info correct (updated supply)
+        let invest = query_investment(&deps).unwrap();
+        assert_eq!(invest.token_supply, Uint128(1000));
+        assert_eq!(invest.staked_tokens, coin(1000, "ustake"));
+        assert_eq!(invest.nominal_value, Decimal::one());
+    }
+
+    #[test]
+    fn rebonding_changes_pricing() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+
+        let creator = HumanAddr::from("creator");
+        let init_msg = default_init(2, 50);
+        let info = mock_info(&creator, &[]);
+
+        // make sure we can init with this
+        let res = init(&mut deps, mock_env(), info, init_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // let's bond some tokens now
+        let bob = HumanAddr::from("bob");
+        let bond_msg = HandleMsg::Bond { validator: None };
+        let info = mock_info(&bob, &[coin(10, "random"), coin(1000, "ustake")]);
+        let res = handle(&mut deps, mock_env(), info, bond_msg).unwrap();
+        assert_eq!(1, res.messages.len());
+
+        // update the querier with new bond
+        set_delegation(&mut deps.querier, 1000, "ustake");
+
+        // fake a reinvestment (this must be sent by the contract itself)
+        let rebond_msg = HandleMsg::_BondAllTokens {};
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(500, "ustake"));
+        let _ = handle(&mut deps, mock_env(), info, rebond_msg).unwrap();
+
+        // update the querier with new bond
+        set_delegation(&mut deps.querier, 1500, "ustake");
+
+        // we should now see 1000 issues and 1500 bonded (and a price of 1.5)
+        let invest = query_investment(&deps).unwrap();
+        assert_eq!(invest.token_supply, Uint128(1000));
+        assert_eq!(invest.staked_tokens, coin(1500, "ustake"));
+        let ratio = Decimal::from_str("1.5").unwrap();
+        assert_eq!(invest.nominal_value, ratio);
+
+        // we bond some other tokens and get a different issuance price (maintaining the ratio)
+        let alice = HumanAddr::from("alice");
+        let bond_msg = HandleMsg::Bond { validator: None };
+        let info = mock_info(&alice, &[coin(3000, "ustake")]);
+        let res = handle(&mut deps, mock_env(), info, bond_msg).unwrap();
+        assert_eq!(1, res.messages.len());
+
+        // update the querier with new bond
+        set_delegation(&mut deps.querier, 3000, "ustake");
+
+        // alice should have gotten 2000 DRV for the 3000 stake, keeping the ratio at 1.5
+        assert_eq!(get_balance(&deps, &alice), Uint128(2000));
+
+        let invest = query_investment(&deps).unwrap();
+        assert_eq!(invest.token_supply, Uint128(3000));
+        assert_eq!(invest.staked_tokens, coin(4500, "ustake"));
+        assert_eq!(invest.nominal_value, ratio);
+    }
+
+    #[test]
+    fn bonding_fails_with_wrong_denom() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+
+        let creator = HumanAddr::from("creator");
+        let init_msg = default_init(2, 50);
+        let info = mock_info(&creator, &[]);
+
+        // make sure we can init with this
+        let res = init(&mut deps, mock_env(), info, init_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // let's bond some tokens now
+        let bob = HumanAddr::from("bob");
+        let bond_msg = HandleMsg::Bond { validator: None };
+        let info = mock_info(&bob, &[coin(500, "photon")]);
+
+        // try to bond and make sure we trigger delegation
+        let res = handle(&mut deps, mock_env(), info, bond_msg);
+        match res.unwrap_err() {
+            StakingError::Std {
+                original: StdError::GenericErr { msg, .. },
+            } => assert_eq!(msg, "No ustake tokens sent"),
+            err => panic!("Unexpected error: {:?}", err),
+        };
+    }
+
+    #[test]
+    fn unbonding_maintains_price_ratio() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+
+        let creator = HumanAddr::from("creator");
+        let init_msg = default_init(10, 50);
+        let info = mock_info(&creator, &[]);
+
+        // make sure we can init with this
+        let res = init(&mut deps, mock_env(), info, init_msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // let's bond some tokens now
+        let bob = HumanAddr::from("bob");
+        let bond_msg = HandleMsg::Bond { validator: None };
+        let info = mock_info(&bob, &[coin(10, "random"), coin(1000, "ustake")]);
+        let res = handle(&mut deps, mock_env(), info, bond_msg).unwrap();
+        assert_eq!(1, res.messages.len());
+
+        // update the querier with new bond
+        set_delegation(&mut deps.querier, 1000, "ustake");
+
+        // fake a reinvestment (this must be sent by the contract itself)
+        // after this, we see 1000 issues and 1500 bonded (and a price of 1.5)
+        let rebond_msg = HandleMsg::_BondAllTokens {};
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(500, "ustake"));
+        let _ = handle(&mut deps, mock_env(), info, rebond_msg).unwrap();
+
+        // update the querier with new bond, lower balance
+        set_delegation(&mut deps.querier, 1500, "ustake");
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, vec![]);
+
+        // creator now tries to unbond these tokens - this must fail
+        let unbond_msg = HandleMsg::Unbond {
+            amount: Some(Uint128(600)),
+        };
+        let info = mock_info(&creator, &[]);
+        let res = handle(&mut deps, mock_env(), info, unbond_msg);
+        match res.unwrap_err() {
+            StakingError::Std {
+                original: StdError::Underflow { .. },
+            } => {}
+            err => panic!("Unexpected error: {:?}", err),
+        }
+
+        // bob unbonds 600 tokens at 10% tax...
+        // 60 are taken and send to the owner
+        // 540 are unbonded in exchange for 540 * 1.5 = 810 native tokens
+        let unbond_msg = HandleMsg::Unbond {
+            amount: Some(Uint128(600)),
+        };
+        let owner_cut = Uint128(60);
+        let bobs_claim = Uint128(810);
+        let bobs_balance = Uint128(400);
+        let info = mock_info(&bob, &[]);
+        let res = handle(&mut deps, mock_env(), info, unbond_msg).unwrap();
+        assert_eq!(1, res.messages.len());
+        let delegate = &res.messages[0];
+        match delegate {
+            CosmosMsg::Staking(StakingMsg::Undelegate { validator, amount }) => {
+                assert_eq!(validator.as_str(), DEFAULT_VALIDATOR);
+                assert_eq!(amount, &coin(bobs_claim.u128(), "ustake"));
+            }
+            _ => panic!("Unexpected message: {:?}", delegate),
+        }
+
+        // update the querier with new bond, lower balance
+        set_delegation(&mut deps.querier, 690, "ustake");
+
+        // check balances
+        assert_eq!(get_balance(&deps, &bob), bobs_balance);
+        assert_eq!(get_balance(&deps, &creator), owner_cut);
+        // proper claims
+        assert_eq!(get_claims(&deps, &bob), bobs_claim);
+
+        // supplies updated, ratio the same (1.5)
+        let ratio = Decimal::from_str("1.5").unwrap();
+
+        let invest = query_investment(&deps).unwrap();
+        assert_eq!(invest.token_supply, bobs_balance + owner_cut);
+        assert_eq!(invest.staked_tokens, coin(690, "ustake")); // 1500 - 810
+        assert_eq!(invest.nominal_value, ratio);
+    }
+
+    #[test]
+    fn unbond_claims_taxed_value_in_bond_denom() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(10, 50)).unwrap();
+
+        // same pool as unbonding_maintains_price_ratio: 1000 issued at a ratio of 1.5
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(500, "ustake"));
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap();
+        set_delegation(&mut deps.querier, 1500, "ustake");
+        deps.querier.update_balance(MOCK_CONTRACT_ADDR, vec![]);
+
+        // all 1000 at 10%: 900 are burned for 1350 native tokens
+        let info = mock_info("bob", &[]);
+        let msg = HandleMsg::Unbond { amount: None };
+        let res = handle(&mut deps, mock_env(), info, msg).unwrap();
+        let undelegate: CosmosMsg = StakingMsg::Undelegate {
+            validator: HumanAddr::from(DEFAULT_VALIDATOR),
+            amount: coin(1350, "ustake"),
+        }
+        .into();
+        assert_eq!(res.messages, vec![undelegate]);
+        assert_eq!(get_claims(&deps, "bob"), Uint128(1350));
+        set_delegation(&mut deps.querier, 150, "ustake");
+
+        // the sweep only settles the delegation, the claim was made with the request
+        let mut env = mock_env();
+        env.block.height += EXPIRY_BLOCKS + 1;
+        let msg = HandleMsg::ProcessExpired { limit: None };
+        let res = handle(&mut deps, env.clone(), mock_info("anyone", &[]), msg).unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!(get_claims(&deps, "bob"), Uint128(1350));
+
+        // and it pays out in bond_denom once the stake is released
+        let err = handle(&mut deps, env, mock_info("bob", &[]), HandleMsg::Claim {});
+        assert!(err.unwrap_err().to_string().contains("No matured claims"));
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(1350, "ustake"));
+        let mut env = mock_env();
+        env.block.height += UNBONDING_BLOCKS;
+        let res = handle(&mut deps, env, mock_info("bob", &[]), HandleMsg::Claim {}).unwrap();
+        let payout: CosmosMsg = BankMsg::Send {
+            from_address: HumanAddr::from(MOCK_CONTRACT_ADDR),
+            to_address: HumanAddr::from("bob"),
+            amount: coins(1350, "ustake"),
+        }
+        .into();
+        assert_eq!(res.messages, vec![payout]);
+    }
+
+    #[test]
+    fn exit_tax_preview_splits_tax_and_payout() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(10, 50)).unwrap();
+
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(500, "ustake"));
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap();
+
+        // same figures as unbonding_maintains_price_ratio: 600 at 10% tax and ratio 1.5
+        let bob = HumanAddr::from("bob");
+        let preview = query_exit_tax_preview(&deps, mock_env(), bob, Uint128(600)).unwrap();
+        assert_eq!(
+            preview,
+            ExitTaxPreviewResponse {
+                gross_native: Uint128(900),
+                tax_derivative: Uint128(60),
+                net_native: Uint128(810),
+            }
+        );
+    }
+
+    #[test]
+    fn exit_tax_follows_holding_time() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let mut msg = default_init(0, 50);
+        msg.tax_tiers = Some(vec![
+            (1000, Decimal::percent(2)),
+            (100, Decimal::percent(5)),
+        ]);
+        let info = mock_info("creator", &[]);
+        let err = init(&mut deps, mock_env(), info, msg.clone()).unwrap_err();
+        assert!(err.to_string().contains("sorted"));
+
+        msg.tax_tiers = Some(vec![
+            (100, Decimal::percent(5)),
+            (1000, Decimal::percent(2)),
+        ]);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+
+        for (i, name) in ["alice", "bob", "carl"].iter().enumerate() {
+            let info = mock_info(*name, &[coin(1000, "ustake")]);
+            let msg = HandleMsg::Bond { validator: None };
+            handle(&mut deps, mock_env(), info, msg).unwrap();
+            set_delegation(&mut deps.querier, 1000 * (i as u128 + 1), "ustake");
+        }
+
+        // under 100 blocks, under 1000 blocks, and past every tier. The previews
+        // charge what unbonding does
+        for (name, held, tax) in [("alice", 50, 50), ("bob", 500, 20), ("carl", 5000, 0)].iter() {
+            let mut env = mock_env();
+            env.block.height += held;
+            let addr = HumanAddr::from(*name);
+            let preview =
+                query_exit_tax_preview(&deps, env.clone(), addr.clone(), Uint128(1000)).unwrap();
+            assert_eq!(preview.tax_derivative, Uint128(*tax), "{}", name);
+            let res = query_redeemable(&deps, env.clone(), addr).unwrap();
+            assert_eq!(res.redeemable, coin(1000 - *tax, "ustake"), "{}", name);
+            let info = mock_info(*name, &[]);
+            let msg = HandleMsg::Unbond { amount: None };
+            let res = handle(&mut deps, env, info, msg).unwrap();
+            assert!(res.attributes.contains(&attr("exit_tax", *tax)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn pending_unbonds_are_counted() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        for (i, name) in ["alice", "bob", "carl"].iter().enumerate() {
+            let info = mock_info(*name, &[coin(1000, "ustake")]);
+            let msg = HandleMsg::Bond { validator: None };
+            handle(&mut deps, mock_env(), info, msg).unwrap();
+            set_delegation(&mut deps.querier, 1000 * (i as u128 + 1), "ustake");
+        }
+
+        // a second request from the same delegator isn't counted twice
+        for name in ["alice", "bob", "bob"].iter() {
+            let info = mock_info(*name, &[]);
+            let msg = HandleMsg::Unbond { amount: None };
+            handle(&mut deps, mock_env(), info, msg).unwrap();
+        }
+        assert_eq!(query_pending_unbond_count(&deps).unwrap().count, 2);
+
+        // the sweep settles them
+        let mut env = mock_env();
+        env.block.height += EXPIRY_BLOCKS + 1;
+        let msg = HandleMsg::ProcessExpired { limit: None };
+        handle(&mut deps, env, mock_info("anyone", &[]), msg).unwrap();
+        assert_eq!(query_pending_unbond_count(&deps).unwrap().count, 0);
+    }
+
+    #[test]
+    fn unbond_waits_for_min_hold() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let mut msg = default_init(2, 50);
+        msg.min_hold_blocks = Some(100);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+
+        let info = mock_info("bob", &[]);
+        let msg = HandleMsg::Unbond { amount: None };
+        let err = handle(&mut deps, mock_env(), info, msg).unwrap_err();
+        assert!(err.to_string().contains("another 100 blocks"), "{}", err);
+
+        let mut env = mock_env();
+        env.block.height += 99;
+        let info = mock_info("bob", &[]);
+        let msg = HandleMsg::Unbond { amount: None };
+        handle(&mut deps, env.clone(), info, msg).unwrap_err();
+
+        // a sweep reinvesting the delegation doesn't restart the hold
+        reinvest(&mut deps, env.clone(), HumanAddr::from("bob")).unwrap();
+        let bob = query_delegation(&deps, HumanAddr::from("bob")).unwrap();
+        assert_eq!(bob.last_delegate_height, env.block.height);
+        assert_eq!(bob.last_bond_height, mock_env().block.height);
+
+        env.block.height += 1;
+        let info = mock_info("bob", &[]);
+        let msg = HandleMsg::Unbond { amount: None };
+        handle(&mut deps, env, info, msg).unwrap();
+    }
+
+    #[test]
+    fn bond_spills_over_validator_cap() {
+        let mut deps = mock_dependencies(&[]);
+        let validators = [
+            custom_sample_validator(DEFAULT_VALIDATOR, 1, 10, 1),
+            custom_sample_validator("second", 2, 10, 1),
+        ];
+        deps.querier.update_staking("ustake", &validators, &[]);
+        let mut msg = default_init(2, 50);
+        msg.max_validator_share = Some(Decimal::percent(50));
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+
+        // 600 on the best validator and 400 on the second
+        total_supply(&mut deps.storage)
+            .update(|mut supply| -> StdResult<_> {
+                supply.issued = Uint128(1000);
+                supply.bonded = Uint128(1000);
+                Ok(supply)
+            })
+            .unwrap();
+        let delegations = [
+            sample_delegation(DEFAULT_VALIDATOR, coin(600, "ustake")),
+            sample_delegation("second", coin(400, "ustake")),
+        ];
+        deps.querier
+            .update_staking("ustake", &validators, &delegations);
+
+        // the best validator may only grow to 1000 of the 2000 total
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        let res = handle(&mut deps, mock_env(), info, msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![
+                StakingMsg::Delegate {
+                    validator: HumanAddr::from(DEFAULT_VALIDATOR),
+                    amount: coin(400, "ustake"),
+                }
+                .into(),
+                StakingMsg::Delegate {
+                    validator: HumanAddr::from("second"),
+                    amount: coin(600, "ustake"),
+                }
+                .into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn instant_unbond_pays_out_at_fee() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let mut msg = default_init(2, 50);
+        msg.instant_unbond_fee = Some(Decimal::percent(10));
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+
+        // without the liquidity it is a normal unbond request
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(500, "ustake"));
+        let info = mock_info("bob", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::InstantUnbond {}).unwrap();
+        let paid = |msg: &CosmosMsg| matches!(msg, CosmosMsg::Bank(_));
+        assert!(!res.messages.iter().any(paid));
+        let delegation = query_delegation(&deps, HumanAddr::from("bob")).unwrap();
+        assert!(delegation.unbond_flag);
+
+        let info = mock_info("alice", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(2000, "ustake"));
+        // the 2% exit tax leaves 980 to burn, of which 98 stay in the pool
+        let info = mock_info("alice", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::InstantUnbond {}).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![
+                CosmosMsg::Bank(BankMsg::Send {
+                    from_address: HumanAddr::from(MOCK_CONTRACT_ADDR),
+                    to_address: HumanAddr::from("alice"),
+                    amount: coins(882, "ustake"),
+                }),
+                StakingMsg::Undelegate {
+                    validator: HumanAddr::from(DEFAULT_VALIDATOR),
+                    amount: coin(980, "ustake"),
+                }
+                .into(),
+            ]
+        );
+        assert!(res.attributes.contains(&attr("fee", 98)));
+        assert!(res.attributes.contains(&attr("exit_tax", 20)));
+        // on top of the 20 from bob's delayed unbond
+        assert_eq!(get_balance(&deps, "creator"), Uint128(40));
+        let delegation = query_delegation(&deps, HumanAddr::from("alice")).unwrap();
+        assert_eq!(delegation.amount, Uint128(0));
+    }
+
+    #[test]
+    fn instant_unbond_burns_the_tokens() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let mut msg = default_init(2, 50);
+        msg.instant_unbond_fee = Some(Decimal::percent(10));
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+
+        // 2000 issued for 3000 bonded, a ratio of 1.5
+        for name in ["alice", "bob"].iter() {
+            let info = mock_info(*name, &[coin(1000, "ustake")]);
+            let msg = HandleMsg::Bond { validator: None };
+            handle(&mut deps, mock_env(), info, msg).unwrap();
+        }
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(1000, "ustake"));
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap();
+        set_delegation(&mut deps.querier, 3000, "ustake");
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(2000, "ustake"));
+
+        // bob's 1000 less the 2% exit tax are worth 1470, of which 147 stay in the pool
+        let info = mock_info("bob", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::InstantUnbond {}).unwrap();
+        let payout: CosmosMsg = BankMsg::Send {
+            from_address: HumanAddr::from(MOCK_CONTRACT_ADDR),
+            to_address: HumanAddr::from("bob"),
+            amount: coins(1323, "ustake"),
+        }
+        .into();
+        let undelegate: CosmosMsg = StakingMsg::Undelegate {
+            validator: HumanAddr::from(DEFAULT_VALIDATOR),
+            amount: coin(1470, "ustake"),
+        }
+        .into();
+        assert_eq!(res.messages, vec![payout, undelegate]);
+        assert!(res.attributes.contains(&attr("fee", 147)));
+
+        // the tax stays issued with the fee recipient
+        assert_eq!(get_balance(&deps, "bob"), Uint128(0));
+        assert_eq!(get_balance(&deps, "creator"), Uint128(20));
+        let supply = total_supply_read(&deps.storage).load().unwrap();
+        assert_eq!(supply.issued, Uint128(1020));
+        assert_eq!(supply.bonded, Uint128(1530));
+    }
+
+    #[test]
+    fn instant_unbond_reserves_owed_rewards() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let mut msg = default_init(2, 50);
+        msg.instant_unbond_fee = Some(Decimal::percent(10));
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+        for name in ["alice", "bob"].iter() {
+            let info = mock_info(*name, &[coin(1000, "ustake")]);
+            let msg = HandleMsg::Bond { validator: None };
+            handle(&mut deps, mock_env(), info, msg).unwrap();
+        }
+        set_delegation(&mut deps.querier, 2000, "ustake");
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(1000, "ustake"));
+
+        // 200 of the 1000 held are alice's rewards, too few are left for bob's 882
+        seed_reward(&mut deps, "alice", 200);
+        let info = mock_info("bob", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::InstantUnbond {}).unwrap();
+        let paid = |msg: &CosmosMsg| matches!(msg, CosmosMsg::Bank(_));
+        assert!(!res.messages.iter().any(paid));
+        let delegation = query_delegation(&deps, HumanAddr::from("bob")).unwrap();
+        assert!(delegation.unbond_flag);
+        let alice = query_delegation(&deps, HumanAddr::from("alice")).unwrap();
+        assert_eq!(alice.undelegate_reward, Uint128(200));
+    }
+
+    #[test]
+    fn balance_at_reads_snapshots() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let mut msg = default_init(2, 50);
+        msg.snapshot_retention = Some(3);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+
+        let height = mock_env().block.height;
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        for (blocks, amount) in [(10, 300), (20, 100), (30, 100)].iter() {
+            let mut env = mock_env();
+            env.block.height += blocks;
+            let msg = HandleMsg::Transfer {
+                recipient: HumanAddr::from("alice"),
+                amount: Uint128(*amount),
+            };
+            handle(&mut deps, env, mock_info("bob", &[]), msg).unwrap();
+        }
+
+        let balance_at = |deps: &Extern<_, _, _>, addr: &str, height| {
+            query_balance_at(deps, HumanAddr::from(addr), height)
+                .unwrap()
+                .balance
+        };
+        assert_eq!(balance_at(&deps, "bob", height + 15), Uint128(700));
+        assert_eq!(balance_at(&deps, "bob", height + 20), Uint128(600));
+        assert_eq!(balance_at(&deps, "bob", height + 100), Uint128(500));
+        assert_eq!(balance_at(&deps, "alice", height + 15), Uint128(300));
+        assert_eq!(balance_at(&deps, "alice", height + 5), Uint128(0));
+
+        // only the latest 3 of bob's 4 snapshots are kept
+        let bob_raw = deps.api.canonical_address(&HumanAddr::from("bob")).unwrap();
+        let kept = balance_snapshots_read(&deps.storage, &bob_raw)
+            .range(None, None, Order::Ascending)
+            .count();
+        assert_eq!(kept, 3);
+    }
+
+    #[test]
+    fn bond_attributes_carry_denom() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        let res = handle(&mut deps, mock_env(), info, msg).unwrap();
+        assert!(res.attributes.contains(&attr("bonded", 1000)));
+        assert!(res.attributes.contains(&attr("denom", "ustake")));
+        set_delegation(&mut deps.querier, 1000, "ustake");
+
+        // the exit tax is taken in derivative tokens
+        let info = mock_info("bob", &[]);
+        let msg = HandleMsg::Unbond { amount: None };
+        let res = handle(&mut deps, mock_env(), info, msg).unwrap();
+        assert!(res.attributes.contains(&attr("exit_tax", 20)));
+        assert!(res.attributes.contains(&attr("denom", "DRV")));
+    }
+
+    #[test]
+    fn bond_emits_supply_attributes() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        // a pool that earned 50% with a pending claim
+        total_supply(&mut deps.storage)
+            .update(|mut supply| -> StdResult<_> {
+                supply.issued = Uint128(1000);
+                supply.bonded = Uint128(1500);
+                supply.claims = Uint128(40);
+                Ok(supply)
+            })
+            .unwrap();
+        set_delegation(&mut deps.querier, 1500, "ustake");
+
+        let info = mock_info("bob", &[coin(300, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        let res = handle(&mut deps, mock_env(), info, msg).unwrap();
+        assert!(res.attributes.contains(&attr("issued", 1200)));
+        assert!(res.attributes.contains(&attr("bonded", 1800)));
+        assert!(res.attributes.contains(&attr("claims", 40)));
+    }
+
+    #[test]
+    fn can_bond_explains_why_not() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let mut msg = default_init(2, 50);
+        msg.max_delegators = Some(1);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+
+        let res = query_can_bond(&deps).unwrap();
+        assert_eq!(
+            res,
+            CanBondResponse {
+                can_bond: true,
+                reason: None,
+            }
+        );
+
+        // the only delegator slot is taken
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        let res = query_can_bond(&deps).unwrap();
+        assert!(!res.can_bond);
+        assert_eq!(
+            res.reason,
+            Some("Cannot register more than 1 delegators".to_string())
+        );
+
+        // no validator left to delegate to
+        deps.querier.update_staking("ustake", &[], &[]);
+        let res = query_can_bond(&deps).unwrap();
+        assert!(!res.can_bond);
+        assert!(res.reason.unwrap().starts_with("No validator"));
+    }
+
+    #[test]
+    fn delegator_count_after_bonds() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        assert_eq!(query_delegator_count(&deps).unwrap().count, 0);
+
+        for addr in &["bob", "alice", "bob"] {
+            let info = mock_info(*addr, &[coin(1000, "ustake")]);
+            let msg = HandleMsg::Bond { validator: None };
+            handle(&mut deps, mock_env(), info, msg).unwrap();
+        }
+
+        // bonding again doesn't count twice
+        assert_eq!(query_delegator_count(&deps).unwrap().count, 2);
+    }
+
+    #[test]
+    fn reinvest_without_auto_callback() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier.update_staking(
+            "ustake",
+            &[sample_validator(DEFAULT_VALIDATOR)],
+            &[sample_delegation_with_rewards(
+                DEFAULT_VALIDATOR,
+                coin(1000, "ustake"),
+                coin(100, "ustake"),
+            )],
+        );
+        let mut msg = default_init(2, 50);
+        msg.auto_callback = Some(false);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+
+        // only the withdrawal, no self-callback
+        let info = mock_info("anyone", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::Reinvest {}).unwrap();
+        assert_eq!(1, res.messages.len());
+        match &res.messages[0] {
+            CosmosMsg::Staking(StakingMsg::Withdraw { .. }) => {}
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+
+        // the owner bonds the rewards in a second step, others still can't
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(100, "ustake"));
+        let info = mock_info("anyone", &[]);
+        handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap_err();
+        let info = mock_info("creator", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap();
+        assert_eq!(1, res.messages.len());
+    }
+
+    #[test]
+    fn send_moves_balance_and_calls_receiver() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+
+        let creator = HumanAddr::from("creator");
+        let info = mock_info(&creator, &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let bob = HumanAddr::from("bob");
+        let bob_raw = deps.api.canonical_address(&bob).unwrap();
+        balances(&mut deps.storage)
+            .save(bob_raw.as_slice(), &Uint128(1000))
+            .unwrap();
+
+        let vault = HumanAddr::from("vault-contract");
+        let payload = Binary::from(br#"{"deposit":{}}"#.to_vec());
+        let send_msg = HandleMsg::Send {
+            contract: vault.clone(),
+            amount: Uint128(400),
+            msg: Some(payload.clone()),
+        };
+        let res = handle(&mut deps, mock_env(), mock_info(&bob, &[]), send_msg).unwrap();
+
+        assert_eq!(get_balance(&deps, &bob), Uint128(600));
+        assert_eq!(get_balance(&deps, &vault), Uint128(400));
+
+        assert_eq!(1, res.messages.len());
+        let expected = Cw20ReceiveMsg {
+            sender: bob.clone(),
+            amount: Uint128(400),
+            msg: Some(payload),
+        };
+        match &res.messages[0] {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr,
+                msg,
+                send,
+            }) => {
+                assert_eq!(contract_addr, &vault);
+                assert_eq!(msg, &expected.into_binary().unwrap());
+                assert!(send.is_empty());
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+
+        // cannot send more than the balance
+        let send_msg = HandleMsg::Send {
+            contract: vault,
+            amount: Uint128(601),
+            msg: None,
+        };
+        let res = handle(&mut deps, mock_env(), mock_info(&bob, &[]), send_msg);
+        match res.unwrap_err() {
+            StakingError::Std {
+                original: StdError::Underflow { .. },
+            } => {}
+            err => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn reinvest_waits_for_min_age() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier.update_staking(
+            "ustake",
+            &[
+                custom_sample_validator(DEFAULT_VALIDATOR, 3, 10, 1),
+                custom_sample_validator("cheap", 1, 10, 1),
+            ],
+            &[],
+        );
+        let mut msg = default_init(2, 50);
+        msg.min_age_before_redelegate = Some(100);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+
+        let height = mock_env().block.height;
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 1000, height - 10);
+        seed_reward(&mut deps, "bob", 100);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(1100, "ustake"));
+
+        // the stake is too young, so it only compounds in place
+        let bob = HumanAddr::from("bob");
+        let res = reinvest(&mut deps, mock_env(), bob.clone()).unwrap();
+        assert_eq!(1, res.messages.len());
+        match &res.messages[0] {
+            CosmosMsg::Staking(StakingMsg::Delegate { validator, .. }) => {
+                assert_eq!(validator.as_str(), DEFAULT_VALIDATOR)
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+
+        // one block short of the threshold still stays put
+        let mut env = mock_env();
+        env.block.height = height + 99;
+        seed_reward(&mut deps, "bob", 100);
+        let res = reinvest(&mut deps, env.clone(), bob.clone()).unwrap();
+        assert_eq!(1, res.messages.len());
+
+        // compounding resets the age, so the threshold counts from the last delegation
+        env.block.height = height + 99 + 100;
+        seed_reward(&mut deps, "bob", 100);
+        let res = reinvest(&mut deps, env, bob).unwrap();
+        assert_eq!(2, res.messages.len());
+        match &res.messages[1] {
+            CosmosMsg::Staking(StakingMsg::Redelegate { dst_validator, .. }) => {
+                assert_eq!(dst_validator.as_str(), "cheap")
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn reinvest_skips_redelegate_during_cooldown() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier.update_staking(
+            "ustake",
+            &[
+                custom_sample_validator(DEFAULT_VALIDATOR, 3, 10, 1),
+                custom_sample_validator("cheap", 1, 10, 1),
+            ],
+            &[],
+        );
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info.clone(), default_init(2, 50)).unwrap();
+
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 1000, 12_000);
+        seed_reward(&mut deps, "bob", 100);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(1100, "ustake"));
+
+        // first reinvest moves the stake to the cheaper validator
+        let bob = HumanAddr::from("bob");
+        let res = reinvest(&mut deps, mock_env(), bob.clone()).unwrap();
+        assert_eq!(2, res.messages.len());
+        match &res.messages[0] {
+            CosmosMsg::Staking(StakingMsg::Delegate { amount, .. }) => {
+                assert_eq!(amount, &coin(100, "ustake"));
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+        match &res.messages[1] {
+            CosmosMsg::Staking(StakingMsg::Redelegate {
+                src_validator,
+                dst_validator,
+                amount,
+            }) => {
+                assert_eq!(src_validator.as_str(), DEFAULT_VALIDATOR);
+                assert_eq!(dst_validator.as_str(), "cheap");
+                assert_eq!(amount, &coin(1000, "ustake"));
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+
+        // an even cheaper validator shows up shortly after
+        deps.querier.update_staking(
+            "ustake",
+            &[
+                custom_sample_validator(DEFAULT_VALIDATOR, 3, 10, 1),
+                custom_sample_validator("cheap", 1, 10, 1),
+                custom_sample_validator("cheaper", 0, 10, 1),
+            ],
+            &[],
+        );
+        let mut env = mock_env();
+        env.block.height += 10;
+        seed_reward(&mut deps, "bob", 100);
+        let res = reinvest(&mut deps, env, bob.clone()).unwrap();
+        assert_eq!(1, res.messages.len());
+        match &res.messages[0] {
+            CosmosMsg::Staking(StakingMsg::Delegate { validator, .. }) => {
+                assert_eq!(validator.as_str(), "cheap")
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+        assert_eq!(
+            query_delegation(&deps, bob).unwrap().validator.as_str(),
+            "cheap"
+        );
+    }
+
+    #[test]
+    fn reinvest_preview_matches_reinvest() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier.update_staking(
+            "ustake",
+            &[
+                sample_validator(DEFAULT_VALIDATOR),
+                sample_validator("other"),
+            ],
+            &[
+                sample_delegation_with_rewards(
+                    DEFAULT_VALIDATOR,
+                    coin(1000, "ustake"),
+                    coin(40, "ustake"),
+                ),
+                sample_delegation("other", coin(500, "ustake")),
+            ],
+        );
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(30, "ustake"));
+
+        let preview = query_reinvest_preview(&deps, mock_env()).unwrap();
+        assert_eq!(
+            preview.withdraw_from,
+            vec![HumanAddr::from(DEFAULT_VALIDATOR)]
+        );
+        assert_eq!(preview.to_bond, coin(70, "ustake"));
+        assert_eq!(preview.validator.as_str(), DEFAULT_VALIDATOR);
+
+        // reinvest withdraws from the same validators and calls back into itself
+        let info = mock_info("keeper", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::Reinvest {}).unwrap();
+        assert_eq!(2, res.messages.len());
+        match &res.messages[0] {
+            CosmosMsg::Staking(StakingMsg::Withdraw { validator, .. }) => {
+                assert_eq!(validator, &preview.withdraw_from[0])
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+        match &res.messages[1] {
+            CosmosMsg::Wasm(WasmMsg::Execute { contract_addr, .. }) => {
+                assert_eq!(contract_addr.as_str(), MOCK_CONTRACT_ADDR)
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+
+        // once the rewards land, the callback bonds exactly the previewed amount
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(70, "ustake"));
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap();
+        match &res.messages[0] {
+            CosmosMsg::Staking(StakingMsg::Delegate { validator, amount }) => {
+                assert_eq!(validator, &preview.validator);
+                assert_eq!(amount, &preview.to_bond);
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+    }
+
+    #[test]
+    fn reinvest_preview_follows_the_split() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        for delegator in ["alice", "bob"].iter() {
+            let info = mock_info(*delegator, &coins(1000, "ustake"));
+            let msg = HandleMsg::Bond { validator: None };
+            handle(&mut deps, mock_env(), info, msg).unwrap();
+        }
+        deps.querier.update_staking(
+            "ustake",
+            &[sample_validator(DEFAULT_VALIDATOR)],
+            &[sample_delegation_with_rewards(
+                DEFAULT_VALIDATOR,
+                coin(2000, "ustake"),
+                coin(100, "ustake"),
+            )],
+        );
+        let set = |deps: &mut Extern<_, _, _>, f: &dyn Fn(&mut InvestmentInfo)| {
+            invest_info(&mut deps.storage)
+                .update(|mut invest| -> StdResult<_> {
+                    f(&mut invest);
+                    Ok(invest)
+                })
+                .unwrap();
+        };
+
+        // a relative threshold above the rewards makes the callback a no-op
+        set(&mut deps, &|invest| {
+            invest.reinvest_min_ratio = Some(Decimal::percent(10))
+        });
+        let preview = query_reinvest_preview(&deps, mock_env()).unwrap();
+        assert!(preview.withdraw_from.is_empty());
+        assert_eq!(preview.to_bond, coin(0, "ustake"));
+
+        // paying rewards out bonds nothing
+        set(&mut deps, &|invest| {
+            invest.reinvest_min_ratio = None;
+            invest.distribute_rewards = true;
+        });
+        let preview = query_reinvest_preview(&deps, mock_env()).unwrap();
+        assert_eq!(preview.to_bond, coin(0, "ustake"));
+        assert_eq!(preview.to_distribute, coin(100, "ustake"));
+
+        // half compounded, half distributed, as the callback does
+        set(&mut deps, &|invest| {
+            invest.distribute_rewards = false;
+            invest.compound_ratio = Decimal::percent(50);
+        });
+        let preview = query_reinvest_preview(&deps, mock_env()).unwrap();
+        assert_eq!(preview.to_bond, coin(50, "ustake"));
+        assert_eq!(preview.to_distribute, coin(50, "ustake"));
+
+        let info = mock_info("keeper", &[]);
+        handle(&mut deps, mock_env(), info, HandleMsg::Reinvest {}).unwrap();
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(100, "ustake"));
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap();
+        match &res.messages[0] {
+            CosmosMsg::Staking(StakingMsg::Delegate { amount, .. }) => {
+                assert_eq!(amount, &preview.to_bond)
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+        assert!(res.attributes.contains(&attr("distributed", 50)));
+    }
+
+    #[test]
+    fn reinvest_preview_empty_below_threshold() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier.update_staking(
+            "ustake",
+            &[sample_validator(DEFAULT_VALIDATOR)],
+            &[sample_delegation_with_rewards(
+                DEFAULT_VALIDATOR,
+                coin(1000, "ustake"),
+                coin(20, "ustake"),
+            )],
+        );
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let preview = query_reinvest_preview(&deps, mock_env()).unwrap();
+        assert!(preview.withdraw_from.is_empty());
+        assert_eq!(preview.to_bond, coin(0, "ustake"));
+    }
+
+    #[test]
+    fn migrate_rewrites_legacy_claims() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        // an instance from before versioning, with a bare amount as claim
+        contract_version(&mut deps.storage).remove();
+        let bob = HumanAddr::from("bob");
+        let bob_raw = deps.api.canonical_address(&bob).unwrap();
+        Bucket::<_, Uint128>::new(&mut deps.storage, PREFIX_CLAIMS)
+            .save(bob_raw.as_slice(), &Uint128(250))
+            .unwrap();
+        claims_read(&deps.storage)
+            .load(bob_raw.as_slice())
+            .unwrap_err();
+
+        let mut env = mock_env();
+        env.block.height = 20_000;
+        let info = mock_info("creator", &[]);
+        let res = migrate(&mut deps, env.clone(), info.clone(), MigrateMsg {}).unwrap();
+        assert!(res.attributes.contains(&attr("migrated_claims", 1)));
+
+        // readable in the new format and payable from the migration block
+        let entries = claims_read(&deps.storage).load(bob_raw.as_slice()).unwrap();
+        assert_eq!(
+            entries,
+            vec![Claim {
+                amount: Uint128(250),
+                release_height: 20_000,
+            }]
+        );
+        assert!(entries[0].release_height <= env.block.height);
+        assert_eq!(get_claims(&deps, &bob), Uint128(250));
+
+        // the version guard makes a second run a no-op
+        let res = migrate(&mut deps, env, info, MigrateMsg {}).unwrap();
+        assert!(!res.attributes.iter().any(|a| a.key == "migrated_claims"));
+    }
+
+    #[test]
+    fn migrate_upgrades_version_1_state() {
+        #[derive(Serialize, Deserialize)]
+        struct V1Investment {
+            owner: CanonicalAddr,
+            bond_denom: String,
+            exit_tax: Decimal,
+            validator: HumanAddr,
+            min_withdrawal: Uint128,
+            init_height: u64,
+            init_time: u64,
+        }
+        #[derive(Serialize, Deserialize)]
+        struct V1Supply {
+            issued: Uint128,
+            bonded: Uint128,
+            claims: Uint128,
+        }
+        #[derive(Serialize, Deserialize)]
+        struct V1Delegation {
+            delegator: HumanAddr,
+            validator: HumanAddr,
+            amount: Uint128,
+            last_delegate_height: u64,
+            unbond_flag: bool,
+            undelegate_reward: Uint128,
+            last_redelegate_height: Option<u64>,
+        }
+
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        // replace the state with what a version 1 instance holding bob's bond stored
+        contract_version(&mut deps.storage).remove();
+        let creator_raw = deps
+            .api
+            .canonical_address(&HumanAddr::from("creator"))
+            .unwrap();
+        Singleton::new(&mut deps.storage, KEY_INVESTMENT)
+            .save(&V1Investment {
+                owner: creator_raw,
+                bond_denom: "ustake".to_string(),
+                exit_tax: Decimal::percent(2),
+                validator: HumanAddr::from(DEFAULT_VALIDATOR),
+                min_withdrawal: Uint128(50),
+                init_height: 12_000,
+                init_time: 1_571_797_419,
+            })
+            .unwrap();
+        Singleton::new(&mut deps.storage, KEY_TOTAL_SUPPLY)
+            .save(&V1Supply {
+                issued: Uint128(1000),
+                bonded: Uint128(1000),
+                claims: Uint128(0),
+            })
+            .unwrap();
+        let bob = HumanAddr::from("bob");
+        let bob_raw = deps.api.canonical_address(&bob).unwrap();
+        Bucket::new(&mut deps.storage, PREFIX_DELEGATIONS)
+            .save(
+                bob_raw.as_slice(),
+                &V1Delegation {
+                    delegator: bob.clone(),
+                    validator: HumanAddr::from(DEFAULT_VALIDATOR),
+                    amount: Uint128(1000),
+                    last_delegate_height: 12_100,
+                    unbond_flag: false,
+                    undelegate_reward: Uint128(0),
+                    last_redelegate_height: None,
+                },
+            )
+            .unwrap();
+        delegators(&mut deps.storage)
+            .save(&vec![bob.clone()])
+            .unwrap();
+        balances(&mut deps.storage)
+            .save(bob_raw.as_slice(), &Uint128(1000))
+            .unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+        delegations_read(&deps.storage)
+            .load(bob_raw.as_slice())
+            .unwrap_err();
+
+        let info = mock_info("creator", &[]);
+        let res = migrate(&mut deps, mock_env(), info, MigrateMsg {}).unwrap();
+        assert!(res.attributes.contains(&attr("from_version", 1)));
+
+        let invest = query_investment(&deps).unwrap();
+        assert_eq!(invest.validators, vec![HumanAddr::from(DEFAULT_VALIDATOR)]);
+        assert_eq!(invest.exit_tax, Decimal::percent(2));
+        assert_eq!(invest.nominal_value, Decimal::one());
+        let stored = invest_info_read(&deps.storage).load().unwrap();
+        assert_eq!(
+            stored.fee_recipients,
+            vec![(HumanAddr::from("creator"), Decimal::one())]
+        );
+        assert_eq!(stored.blocks_per_year, DEFAULT_BLOCKS_PER_YEAR);
+        let delegation = delegations_read(&deps.storage)
+            .load(bob_raw.as_slice())
+            .unwrap();
+        assert_eq!(delegation.first_bond_height, 12_100);
+        assert!(delegation.auto_reinvest);
+
+        // and the upgraded instance takes new bonds
+        let info = mock_info("carl", &[coin(500, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        assert_eq!(get_balance(&deps, &HumanAddr::from("carl")), Uint128(500));
+        assert_eq!(query_investment(&deps).unwrap().token_supply, Uint128(1500));
+    }
+
+    #[test]
+    fn first_bond_height_is_kept() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+
+        let mut env = mock_env();
+        env.block.height += 500;
+        let info = mock_info("bob", &[coin(500, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, env.clone(), info, msg).unwrap();
+
+        let bob_raw = deps.api.canonical_address(&HumanAddr::from("bob")).unwrap();
+        let delegation = delegations_read(&deps.storage)
+            .load(bob_raw.as_slice())
+            .unwrap();
+        assert_eq!(delegation.first_bond_height, mock_env().block.height);
+        assert_eq!(delegation.last_delegate_height, env.block.height);
+
+        // records from before version 3 start at their last delegation
+        seed_delegation(&mut deps, "carl", DEFAULT_VALIDATOR, 100, 12_000);
+        contract_version(&mut deps.storage).save(&2).unwrap();
+        let info = mock_info("creator", &[]);
+        let res = migrate(&mut deps, mock_env(), info, MigrateMsg {}).unwrap();
+        assert!(res.attributes.contains(&attr("migrated_delegations", 1)));
+        let carl = HumanAddr::from("carl");
+        let carl_raw = deps.api.canonical_address(&carl).unwrap();
+        let delegation = delegations_read(&deps.storage)
+            .load(carl_raw.as_slice())
+            .unwrap();
+        assert_eq!(delegation.first_bond_height, 12_000);
+        assert_eq!(delegation.last_bond_height, 12_000);
+    }
+
+    #[test]
+    fn windows_follow_blocks_per_year() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        let invest = invest_info_read(&deps.storage).load().unwrap();
+        assert_eq!(invest.blocks_for_duration(EXPIRY_SECONDS), EXPIRY_BLOCKS);
+        assert_eq!(
+            invest.blocks_for_duration(CLAIM_EXPIRY_SECONDS),
+            CLAIM_EXPIRY_BLOCKS
+        );
+
+        // a 6s block time gives 100_800 blocks for 7 days
+        let mut msg = default_init(2, 50);
+        msg.blocks_per_year = Some(5_256_000);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+        let invest = invest_info_read(&deps.storage).load().unwrap();
+        assert_eq!(invest.blocks_for_duration(7 * 24 * 60 * 60), 100_800);
+
+        // and the expiry sweep picks that up
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 0, 100);
+        let res = query_next_expiry(&deps, mock_env(), HumanAddr::from("bob")).unwrap();
+        assert_eq!(res.expires_at, 100 + 21_600);
+    }
+
+    #[test]
+    fn sudo_processes_expired_batch() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        seed_delegation(&mut deps, "alice", DEFAULT_VALIDATOR, 100, 100);
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 100, 100);
+        seed_delegation(&mut deps, "carl", DEFAULT_VALIDATOR, 100, 100);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(1000, "ustake"));
+
+        let mut env = mock_env();
+        env.block.height = 100 + EXPIRY_BLOCKS + 1;
+        let res = sudo(
+            &mut deps,
+            env.clone(),
+            SudoMsg::ProcessExpired { limit: Some(2) },
+        )
+        .unwrap();
+        assert_eq!(res.attributes[1], attr("processed", 2));
+
+        // the first two were reinvested, the third waits for the next block
+        let height_of = |deps: &Extern<_, _, _>, addr: &str| {
+            query_delegation(deps, HumanAddr::from(addr))
+                .unwrap()
+                .last_delegate_height
+        };
+        assert_eq!(height_of(&deps, "alice"), env.block.height);
+        assert_eq!(height_of(&deps, "bob"), env.block.height);
+        assert_eq!(height_of(&deps, "carl"), 100);
+
+        let res = sudo(&mut deps, env, SudoMsg::ProcessExpired { limit: Some(2) }).unwrap();
+        assert_eq!(res.attributes[1], attr("processed", 1));
+    }
+
+    #[test]
+    fn sweep_resumes_after_last_delegator() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        // idle delegators ahead of the funded ones don't take up the batch
+        for idle in ["idle1", "idle2", "idle3"].iter() {
+            seed_delegation(&mut deps, idle, DEFAULT_VALIDATOR, 0, 100);
+        }
+        for funded in ["dave", "erin", "frank"].iter() {
+            seed_delegation(&mut deps, funded, DEFAULT_VALIDATOR, 100, 100);
+        }
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(1000, "ustake"));
+        let height_of = |deps: &Extern<_, _, _>, addr: &str| {
+            query_delegation(deps, HumanAddr::from(addr))
+                .unwrap()
+                .last_delegate_height
+        };
+
+        let mut env = mock_env();
+        env.block.height = 100 + EXPIRY_BLOCKS + 1;
+        let first = env.block.height;
+        let msg = SudoMsg::ProcessExpired { limit: Some(2) };
+        let res = sudo(&mut deps, env.clone(), msg.clone()).unwrap();
+        assert_eq!(res.attributes[1], attr("processed", 2));
+        assert_eq!(height_of(&deps, "dave"), first);
+        assert_eq!(height_of(&deps, "erin"), first);
+        assert_eq!(height_of(&deps, "frank"), 100);
+        assert_eq!(height_of(&deps, "idle1"), 100);
+
+        // once everyone is expired again, the next sweep starts with frank rather than
+        // going over dave and erin again
+        env.block.height = first + EXPIRY_BLOCKS + 1;
+        let res = sudo(&mut deps, env.clone(), msg).unwrap();
+        assert_eq!(res.attributes[1], attr("processed", 2));
+        assert_eq!(height_of(&deps, "frank"), env.block.height);
+        assert_eq!(height_of(&deps, "dave"), env.block.height);
+        assert_eq!(height_of(&deps, "erin"), first);
+    }
+
+    #[test]
+    fn sudo_pays_only_matured_claims() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let height = mock_env().block.height;
+        let bob_raw = deps.api.canonical_address(&HumanAddr::from("bob")).unwrap();
+        let carl_raw = deps
+            .api
+            .canonical_address(&HumanAddr::from("carl"))
+            .unwrap();
+        add_claim(&mut deps.storage, &bob_raw, Uint128(40), height - 10).unwrap();
+        add_claim(&mut deps.storage, &bob_raw, Uint128(5), height + 10).unwrap();
+        add_claim(&mut deps.storage, &carl_raw, Uint128(70), height + 10).unwrap();
+
+        let msg = SudoMsg::ProcessClaims { limit: None };
+        let res = sudo(&mut deps, mock_env(), msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: HumanAddr::from(MOCK_CONTRACT_ADDR),
+                to_address: HumanAddr::from("bob"),
+                amount: coins(40, "ustake"),
+            })]
+        );
+
+        // the pending claims stay reserved
+        assert_eq!(get_claims(&deps, "bob"), Uint128(5));
+        assert_eq!(get_claims(&deps, "carl"), Uint128(70));
+        let supply = total_supply_read(&deps.storage).load().unwrap();
+        assert_eq!(supply.claims, Uint128(75));
+    }
+
+    #[test]
+    fn unbond_schedule_is_sorted() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let bob_raw = deps.api.canonical_address(&HumanAddr::from("bob")).unwrap();
+        add_claim(&mut deps.storage, &bob_raw, Uint128(5), 300).unwrap();
+        add_claim(&mut deps.storage, &bob_raw, Uint128(40), 200).unwrap();
+
+        let res = query_unbond_schedule(&deps, HumanAddr::from("bob")).unwrap();
+        assert_eq!(res.schedule, vec![(Uint128(40), 200), (Uint128(5), 300)]);
+
+        let res = query_unbond_schedule(&deps, HumanAddr::from("carl")).unwrap();
+        assert!(res.schedule.is_empty());
+    }
+
+    #[test]
+    fn claims_breakdown_splits_by_maturity() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let height = mock_env().block.height;
+        let bob_raw = deps.api.canonical_address(&HumanAddr::from("bob")).unwrap();
+        let cal_raw = deps.api.canonical_address(&HumanAddr::from("cal")).unwrap();
+        add_claim(&mut deps.storage, &bob_raw, Uint128(40), height - 10).unwrap();
+        add_claim(&mut deps.storage, &bob_raw, Uint128(5), height + 10).unwrap();
+        add_claim(&mut deps.storage, &cal_raw, Uint128(7), height).unwrap();
+        add_claim(&mut deps.storage, &cal_raw, Uint128(100), height + 1).unwrap();
+
+        let res = query_claims_breakdown(&deps, mock_env()).unwrap();
+        assert_eq!(
+            res,
+            ClaimsBreakdownResponse {
+                matured: Uint128(47),
+                pending: Uint128(105),
+            }
+        );
+        let supply = total_supply_read(&deps.storage).load().unwrap();
+        assert_eq!(res.matured + res.pending, supply.claims);
+    }
+
+    #[test]
+    fn unbonding_excludes_matured_claims() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(0, 50)).unwrap();
+
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+
+        // 400 unbonded now have matured by the time another 100 are unbonded
+        let height = mock_env().block.height;
+        let msg = HandleMsg::Unbond {
+            amount: Some(Uint128(400)),
+        };
+        handle(&mut deps, mock_env(), mock_info("bob", &[]), msg).unwrap();
+        set_delegation(&mut deps.querier, 600, "ustake");
+        let mut env = mock_env();
+        env.block.height += UNBONDING_BLOCKS;
+        let msg = HandleMsg::Unbond {
+            amount: Some(Uint128(100)),
+        };
+        handle(&mut deps, env.clone(), mock_info("bob", &[]), msg).unwrap();
+
+        let bob = HumanAddr::from("bob");
+        let res = query_unbonding(&deps, env.clone(), bob.clone()).unwrap();
+        assert_eq!(res.unbonding, coin(100, "ustake"));
+        let res = query_claims_breakdown(&deps, env.clone()).unwrap();
+        assert_eq!(res.matured, Uint128(400));
+        assert_eq!(res.pending, Uint128(100));
+        let res = query_unbond_schedule(&deps, bob).unwrap();
+        let first = height + UNBONDING_BLOCKS;
+        let second = first + UNBONDING_BLOCKS;
+        let schedule = vec![(Uint128(400), first), (Uint128(100), second)];
+        assert_eq!(res.schedule, schedule);
+    }
+
+    #[test]
+    fn claim_for_pays_the_owner() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let height = mock_env().block.height;
+        let bob_raw = deps.api.canonical_address(&HumanAddr::from("bob")).unwrap();
+        add_claim(&mut deps.storage, &bob_raw, Uint128(40), height - 10).unwrap();
+        add_claim(&mut deps.storage, &bob_raw, Uint128(5), height + 10).unwrap();
+
+        // a keeper triggers it, but the funds go to bob
+        let info = mock_info("keeper", &[]);
+        let msg = HandleMsg::ClaimFor {
+            address: HumanAddr::from("bob"),
+        };
+        let res = handle(&mut deps, mock_env(), info, msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: HumanAddr::from(MOCK_CONTRACT_ADDR),
+                to_address: HumanAddr::from("bob"),
+                amount: coins(40, "ustake"),
+            })]
+        );
+        assert_eq!(get_claims(&deps, "bob"), Uint128(5));
+
+        // nothing else has matured
+        let info = mock_info("keeper", &[]);
+        let msg = HandleMsg::ClaimFor {
+            address: HumanAddr::from("bob"),
+        };
+        let err = handle(&mut deps, mock_env(), info, msg).unwrap_err();
+        assert!(err.to_string().contains("No matured claims"));
+    }
+
+    #[test]
+    fn claim_sums_matured_entries() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let height = mock_env().block.height;
+        let bob_raw = deps.api.canonical_address(&HumanAddr::from("bob")).unwrap();
+        add_claim(&mut deps.storage, &bob_raw, Uint128(5), height + 10).unwrap();
+        add_claim(&mut deps.storage, &bob_raw, Uint128(10), height - 30).unwrap();
+        add_claim(&mut deps.storage, &bob_raw, Uint128(20), height - 20).unwrap();
+        add_claim(&mut deps.storage, &bob_raw, Uint128(30), height).unwrap();
+
+        let info = mock_info("bob", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::Claim {}).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: HumanAddr::from(MOCK_CONTRACT_ADDR),
+                to_address: HumanAddr::from("bob"),
+                amount: coins(60, "ustake"),
+            })]
+        );
+        assert_eq!(get_claims(&deps, "bob"), Uint128(5));
+
+        // past the limit, the oldest entries are released first
+        for i in 0..MAX_CLAIM_ENTRIES as u64 + 1 {
+            add_claim(&mut deps.storage, &bob_raw, Uint128(1), height - 1 - i).unwrap();
+        }
+        let info = mock_info("bob", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::Claim {}).unwrap();
+        assert!(res.attributes.contains(&attr("amount", MAX_CLAIM_ENTRIES)));
+        let entries = claims_read(&deps.storage).load(bob_raw.as_slice()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].release_height, height - 1);
+    }
+
+    #[test]
+    fn expired_count_matches_sweep() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        seed_delegation(&mut deps, "alice", DEFAULT_VALIDATOR, 100, 100);
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 100, 100);
+        seed_delegation(&mut deps, "carl", DEFAULT_VALIDATOR, 100, 5_000);
+
+        // one block before the expiry of the first two nothing is due
+        let mut env = mock_env();
+        env.block.height = 100 + EXPIRY_BLOCKS;
+        assert_eq!(query_expired_count(&deps, env.clone()).unwrap().count, 0);
+        env.block.height += 1;
+        assert_eq!(query_expired_count(&deps, env).unwrap().count, 2);
+    }
+
+    #[test]
+    fn sweep_returns_delegator_messages() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        seed_delegation(&mut deps, "alice", DEFAULT_VALIDATOR, 100, 100);
+        seed_reward(&mut deps, "alice", 10);
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 100, 100);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(10, "ustake"));
+        let bob_raw = deps.api.canonical_address(&HumanAddr::from("bob")).unwrap();
+        delegations(&mut deps.storage)
+            .update(bob_raw.as_slice(), |d| -> StdResult<_> {
+                let mut d = d.unwrap();
+                d.unbond_flag = true;
+                Ok(d)
+            })
+            .unwrap();
+
+        let mut env = mock_env();
+        env.block.height = 100 + EXPIRY_BLOCKS + 1;
+        let info = mock_info("anyone", &[]);
+        let msg = HandleMsg::ProcessExpired { limit: None };
+        let res = handle(&mut deps, env, info, msg).unwrap();
+
+        // alice's reinvest is carried by the sweep. Bob's stake was claimed when he
+        // asked to unbond, so settling him sends nothing
+        assert_eq!(1, res.messages.len());
+        match &res.messages[0] {
+            CosmosMsg::Staking(StakingMsg::Delegate { validator, .. }) => {
+                assert_eq!(validator.as_str(), DEFAULT_VALIDATOR)
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+        let delegation = query_delegation(&deps, HumanAddr::from("bob")).unwrap();
+        assert!(!delegation.unbond_flag);
+    }
+
+    #[test]
+    fn unbond_skips_zero_payout() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 0, 100);
+
+        let res = unbond(&mut deps, mock_env(), HumanAddr::from("bob")).unwrap();
+        assert!(res.messages.is_empty());
+        assert_eq!(get_claims(&deps, "bob"), Uint128(0));
+    }
+
+    #[test]
+    fn sweep_accrues_claims_when_auto_reinvest_disabled() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        seed_delegation(&mut deps, "alice", DEFAULT_VALIDATOR, 100, 100);
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 100, 100);
+
+        let msg = HandleMsg::SetAutoReinvest { enabled: false };
+        handle(&mut deps, mock_env(), mock_info("alice", &[]), msg).unwrap();
+        let alice = query_delegation(&deps, HumanAddr::from("alice")).unwrap();
+        assert_eq!(alice.auto_reinvest, false);
+
+        // only registered delegators can opt out
+        let msg = HandleMsg::SetAutoReinvest { enabled: false };
+        handle(&mut deps, mock_env(), mock_info("carl", &[]), msg).unwrap_err();
+
+        // bob's half of the rewards is compounded, alice's stays owed to her
+        deps.querier.update_staking(
+            "ustake",
+            &[sample_validator(DEFAULT_VALIDATOR)],
+            &[sample_delegation_with_rewards(
+                DEFAULT_VALIDATOR,
+                coin(200, "ustake"),
+                coin(100, "ustake"),
+            )],
+        );
+        let info = mock_info("anyone", &[]);
+        handle(&mut deps, mock_env(), info, HandleMsg::ReinvestAll {}).unwrap();
+
+        // the opt-out path emits nothing and leaves the reward as a claim
+        let mut env = mock_env();
+        env.block.height = 100 + EXPIRY_BLOCKS + 1;
+        let res = accrue_rewards(&mut deps, env.clone(), HumanAddr::from("alice")).unwrap();
+        assert_eq!(res.messages.len(), 0);
+        assert_eq!(get_claims(&deps, &HumanAddr::from("alice")), Uint128(50));
+
+        let alice = query_delegation(&deps, HumanAddr::from("alice")).unwrap();
+        assert_eq!(alice.last_delegate_height, env.block.height);
+        assert_eq!(alice.undelegate_reward, Uint128(0));
+
+        // the sweep only reinvests bob, alice is no longer expired
+        is_expired(&mut deps, env.clone(), None).unwrap();
+        let bob = query_delegation(&deps, HumanAddr::from("bob")).unwrap();
+        assert_eq!(bob.last_delegate_height, env.block.height);
+        assert_eq!(get_claims(&deps, &HumanAddr::from("bob")), Uint128(0));
+        assert_eq!(get_claims(&deps, &HumanAddr::from("alice")), Uint128(50));
+
+        // with nothing owed, a later sweep leaves alice's claims untouched
+        let alice_raw = deps
+            .api
+            .canonical_address(&HumanAddr::from("alice"))
+            .unwrap();
+        let mut later = env.clone();
+        later.block.height += EXPIRY_BLOCKS + 1;
+        accrue_rewards(&mut deps, later, HumanAddr::from("alice")).unwrap();
+        let entries = claims_read(&deps.storage)
+            .load(alice_raw.as_slice())
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        let touched = claim_heights_read(&deps.storage).load(alice_raw.as_slice());
+        assert_eq!(touched.unwrap(), env.block.height);
+    }
+
+    #[test]
+    fn blended_commission_weights_by_stake() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier.update_staking(
+            "ustake",
+            &[
+                custom_sample_validator("john", 2, 10, 1),
+                custom_sample_validator("mary", 4, 10, 1),
+            ],
+            &[
+                sample_delegation("john", coin(1000, "ustake")),
+                sample_delegation("mary", coin(1000, "ustake")),
+            ],
+        );
+        let mut msg = default_init(2, 50);
+        msg.validator = HumanAddr::from("john");
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+
+        let res = query_blended_commission(&deps, mock_env()).unwrap();
+        assert_eq!(res.commission, Decimal::percent(3));
+
+        // small stakes aren't rounded away one validator at a time
+        deps.querier.update_staking(
+            "ustake",
+            &[
+                custom_sample_validator("john", 2, 10, 1),
+                custom_sample_validator("mary", 4, 10, 1),
+            ],
+            &[
+                sample_delegation("john", coin(1, "ustake")),
+                sample_delegation("mary", coin(2, "ustake")),
+            ],
+        );
+        let res = query_blended_commission(&deps, mock_env()).unwrap();
+        assert_eq!(res.commission, Decimal::from_ratio(10u128, 300u128));
+
+        // stake on a validator that left the set and other denoms are left out
+        deps.querier.update_staking(
+            "ustake",
+            &[custom_sample_validator("john", 2, 10, 1)],
+            &[
+                sample_delegation("john", coin(1000, "ustake")),
+                sample_delegation("mary", coin(1000, "ustake")),
+                sample_delegation("john", coin(500, "uatom")),
+            ],
+        );
+        let res = query_blended_commission(&deps, mock_env()).unwrap();
+        assert_eq!(res.commission, Decimal::percent(2));
+
+        // nothing delegated means nothing paid
+        deps.querier
+            .update_staking("ustake", &[custom_sample_validator("john", 2, 10, 1)], &[]);
+        let res = query_blended_commission(&deps, mock_env()).unwrap();
+        assert_eq!(res.commission, Decimal::zero());
+    }
+
+    #[test]
+    fn bond_respects_max_delegators() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let mut msg = default_init(2, 50);
+        msg.max_delegators = Some(2);
+        init(&mut deps, mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        for addr in &["alice", "bob"] {
+            let info = mock_info(*addr, &[coin(100, "ustake")]);
+            let msg = HandleMsg::Bond { validator: None };
+            handle(&mut deps, mock_env(), info, msg).unwrap();
+        }
+
+        // the cap is reached, so a new address is turned away
+        let info = mock_info("carl", &[coin(100, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        let res = handle(&mut deps, mock_env(), info, msg);
+        match res.unwrap_err() {
+            StakingError::TooManyDelegators { max } => assert_eq!(max, 2),
+            err => panic!("Unexpected error: {:?}", err),
+        }
+        assert_eq!(get_balance(&deps, &HumanAddr::from("carl")), Uint128(0));
+
+        // while existing delegators can keep bonding
+        let info = mock_info("alice", &[coin(100, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        assert_eq!(get_balance(&deps, &HumanAddr::from("alice")), Uint128(200));
+    }
+
+    #[test]
+    fn distribute_rewards_splits_into_claims() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let mut msg = default_init(2, 50);
+        msg.distribute_rewards = true;
+        init(&mut deps, mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        seed_delegation(&mut deps, "alice", DEFAULT_VALIDATOR, 100, 100);
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 200, 100);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(301, "ustake"));
+
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap();
+        assert_eq!(res.messages.len(), 0);
+        assert_eq!(res.attributes[1], attr("distributed", Uint128(301)));
+
+        // bob has the larger stake and picks up the rounding remainder
+        assert_eq!(get_claims(&deps, &HumanAddr::from("alice")), Uint128(100));
+        assert_eq!(get_claims(&deps, &HumanAddr::from("bob")), Uint128(201));
+        let supply = total_supply_read(&deps.storage).load().unwrap();
+        assert_eq!(supply.claims, Uint128(301));
+        assert_eq!(supply.bonded, Uint128(0));
+
+        // the distributed tokens are reserved, so a second call is a no-op
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap();
+        assert_eq!(res, HandleResponse::default());
+    }
+
+    #[test]
+    fn compound_ratio_splits_rewards() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let mut msg = default_init(2, 50);
+        msg.compound_ratio = Some(Decimal::percent(101));
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap_err();
+        let mut msg = default_init(2, 50);
+        msg.compound_ratio = Some(Decimal::percent(50));
+        init(&mut deps, mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        seed_delegation(&mut deps, "alice", DEFAULT_VALIDATOR, 1000, 100);
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 1000, 100);
+        total_supply(&mut deps.storage)
+            .update(|mut supply| -> StdResult<_> {
+                supply.issued = Uint128(2000);
+                supply.bonded = Uint128(2000);
+                Ok(supply)
+            })
+            .unwrap();
+        deps.querier.update_staking(
+            "ustake",
+            &[sample_validator(DEFAULT_VALIDATOR)],
+            &[sample_delegation_with_rewards(
+                DEFAULT_VALIDATOR,
+                coin(2000, "ustake"),
+                coin(200, "ustake"),
+            )],
+        );
+        let info = mock_info("keeper", &[]);
+        handle(&mut deps, mock_env(), info, HandleMsg::Reinvest {}).unwrap();
+        set_delegation(&mut deps.querier, 2000, "ustake");
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(200, "ustake"));
+        let supply = total_supply_read(&deps.storage).load().unwrap();
+        assert_eq!(supply.reward_index, Decimal::zero());
+
+        // half of the 200 is bonded, the other half is split across the claims
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![StakingMsg::Delegate {
+                validator: HumanAddr::from(DEFAULT_VALIDATOR),
+                amount: coin(100, "ustake"),
+            }
+            .into()]
+        );
+        let supply = total_supply_read(&deps.storage).load().unwrap();
+        assert_eq!(supply.bonded, Uint128(2100));
+        assert_eq!(supply.claims, Uint128(100));
+        assert_eq!(get_claims(&deps, "alice"), Uint128(50));
+        assert_eq!(get_claims(&deps, "bob"), Uint128(50));
+
+        // and the sweep has nothing more to credit for them
+        let mut env = mock_env();
+        env.block.height = 100 + EXPIRY_BLOCKS + 1;
+        is_expired(&mut deps, env, None).unwrap();
+        assert_eq!(get_claims(&deps, "alice"), Uint128(50));
+        assert_eq!(get_claims(&deps, "bob"), Uint128(50));
+    }
+
+    #[test]
+    fn next_expiry_follows_delegate_height() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        seed_delegation(&mut deps, "alice", DEFAULT_VALIDATOR, 100, 100);
+
+        let alice = HumanAddr::from("alice");
+        let mut env = mock_env();
+        env.block.height = 100 + EXPIRY_BLOCKS;
+        let res = query_next_expiry(&deps, env.clone(), alice.clone()).unwrap();
+        assert_eq!(res.expires_at, 100 + EXPIRY_BLOCKS);
+        assert_eq!(res.eligible, false);
+
+        env.block.height += 1;
+        let res = query_next_expiry(&deps, env.clone(), alice).unwrap();
+        assert_eq!(res.eligible, true);
+
+        // unknown addresses have nothing to expire
+        query_next_expiry(&deps, env, HumanAddr::from("bob")).unwrap_err();
+    }
+
+    #[test]
+    fn bond_emits_only_its_delegation() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        // an expired delegator is waiting for the sweep
+        seed_delegation(&mut deps, "alice", DEFAULT_VALIDATOR, 100, 100);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(1000, "ustake"));
+
+        let mut env = mock_env();
+        env.block.height = 100 + EXPIRY_BLOCKS + 1;
+        let info = mock_info("bob", &[coin(500, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        let res = handle(&mut deps, env, info, msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![CosmosMsg::Staking(StakingMsg::Delegate {
+                validator: HumanAddr::from(DEFAULT_VALIDATOR),
+                amount: coin(500, "ustake"),
+            })]
+        );
+
+        // the sweep did not run as a side effect
+        let alice = query_delegation(&deps, HumanAddr::from("alice")).unwrap();
+        assert_eq!(alice.last_delegate_height, 100);
+        assert_eq!(alice.last_redelegate_height, None);
+    }
+
+    #[test]
+    fn bond_with_validator_override() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier.update_staking(
+            "ustake",
+            &[
+                custom_sample_validator("john", 1, 10, 1),
+                custom_sample_validator("mary", 5, 10, 1),
+            ],
+            &[],
+        );
+        let mut msg = default_init(2, 50);
+        msg.validator = HumanAddr::from("john");
+        init(&mut deps, mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        // mary isn't the automatic pick, but was asked for
+        let bob = HumanAddr::from("bob");
+        let msg = HandleMsg::Bond {
+            validator: Some(HumanAddr::from("mary")),
+        };
+        let info = mock_info(&bob, &[coin(500, "ustake")]);
+        let res = handle(&mut deps, mock_env(), info, msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![CosmosMsg::Staking(StakingMsg::Delegate {
+                validator: HumanAddr::from("mary"),
+                amount: coin(500, "ustake"),
+            })]
+        );
+        let delegation = query_delegation(&deps, bob).unwrap();
+        assert_eq!(delegation.validator, HumanAddr::from("mary"));
+
+        // validators outside the set are rejected
+        let msg = HandleMsg::Bond {
+            validator: Some(HumanAddr::from("jane")),
+        };
+        let info = mock_info("carl", &[coin(500, "ustake")]);
+        let res = handle(&mut deps, mock_env(), info, msg);
+        match res.unwrap_err() {
+            StakingError::Std {
+                original: StdError::GenericErr { msg, .. },
+            } => assert_eq!(msg, "jane is not in the current validator set"),
+            err => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn all_delegations_pages_through_delegators() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        for addr in &["alice", "bob", "carl"] {
+            let info = mock_info(*addr, &[coin(100, "ustake")]);
+            let msg = HandleMsg::Bond { validator: None };
+            handle(&mut deps, mock_env(), info, msg).unwrap();
+        }
+
+        let page = query_paged_delegations(&deps, None, Some(2)).unwrap();
+        assert_eq!(page.delegations.len(), 2);
+        let last = page.delegations[1].delegator.clone();
+        let rest = query_paged_delegations(&deps, Some(last), Some(2)).unwrap();
+        assert_eq!(rest.delegations.len(), 1);
+
+        let mut seen: Vec<String> = page
+            .delegations
+            .into_iter()
+            .chain(rest.delegations)
+            .map(|d| d.delegator.to_string())
+            .collect();
+        seen.sort();
+        assert_eq!(seen, vec!["alice", "bob", "carl"]);
+    }
+
+    #[test]
+    fn delegators_by_validator_filters() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        seed_delegation(&mut deps, "alice", DEFAULT_VALIDATOR, 100, 100);
+        seed_delegation(&mut deps, "bob", "jailed", 100, 100);
+        seed_delegation(&mut deps, "carl", DEFAULT_VALIDATOR, 100, 100);
+        seed_delegation(&mut deps, "dave", "jailed", 100, 100);
+
+        let jailed = HumanAddr::from("jailed");
+        let page = query_delegators_by_validator(&deps, jailed.clone(), None, Some(1)).unwrap();
+        assert_eq!(page.delegations.len(), 1);
+        let last = page.delegations[0].delegator.clone();
+        let rest = query_delegators_by_validator(&deps, jailed, Some(last), None).unwrap();
+        assert_eq!(rest.delegations.len(), 1);
+
+        let mut seen: Vec<String> = page
+            .delegations
+            .into_iter()
+            .chain(rest.delegations)
+            .map(|d| d.delegator.to_string())
+            .collect();
+        seen.sort();
+        assert_eq!(seen, vec!["bob", "dave"]);
+    }
+
+    #[test]
+    fn reinvest_waits_for_min_ratio() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let mut msg = default_init(2, 50);
+        msg.reinvest_min_ratio = Some(Decimal::percent(10));
+        init(&mut deps, mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+
+        // above min_withdrawal, but below 10% of the bonded tokens
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(60, "ustake"));
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap();
+        assert_eq!(res, HandleResponse::default());
+
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(150, "ustake"));
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        let supply = total_supply_read(&deps.storage).load().unwrap();
+        assert_eq!(supply.bonded, Uint128(1150));
+    }
+
+    #[test]
+    fn is_delegator_after_bond() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let info = mock_info("bob", &[coin(100, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+
+        let res = query_is_delegator(&deps, HumanAddr::from("bob")).unwrap();
+        assert_eq!(res.is_delegator, true);
+        let res = query_is_delegator(&deps, HumanAddr::from("stranger")).unwrap();
+        assert_eq!(res.is_delegator, false);
+    }
+
+    #[test]
+    fn claims_carry_bond_denom() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let bob = HumanAddr::from("bob");
+        let bob_raw = deps.api.canonical_address(&bob).unwrap();
+        add_claim(&mut deps.storage, &bob_raw, Uint128(250), 100).unwrap();
+
+        let res = query_claims(&deps, bob).unwrap();
+        assert_eq!(res.claims, Uint128(250));
+        assert_eq!(res.coin, coin(250, "ustake"));
+    }
+
+    #[test]
+    fn transfer_rejects_malformed_recipient() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let msg = HandleMsg::Transfer {
+            recipient: HumanAddr::from("x"),
+            amount: Uint128(10),
+        };
+        let res = handle(&mut deps, mock_env(), mock_info("bob", &[]), msg);
+        match res.unwrap_err() {
+            StakingError::Std {
+                original: StdError::GenericErr { msg, .. },
+            } => assert_eq!(msg, "Invalid recipient address: x"),
+            err => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn transfer_with_nonce_rejects_replay() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+
+        let transfer = |nonce| HandleMsg::TransferWithNonce {
+            recipient: HumanAddr::from("alice"),
+            amount: Uint128(10),
+            nonce,
+        };
+        let info = mock_info("bob", &[]);
+        let res = handle(&mut deps, mock_env(), info, transfer(0)).unwrap();
+        assert!(res.attributes.contains(&attr("nonce", 0)));
+
+        // the same nonce again is a replay, and nonces can't be skipped either
+        let info = mock_info("bob", &[]);
+        let err = handle(&mut deps, mock_env(), info, transfer(0)).unwrap_err();
+        assert!(err.to_string().contains("Invalid nonce 0, expected 1"));
+        let info = mock_info("bob", &[]);
+        handle(&mut deps, mock_env(), info, transfer(2)).unwrap_err();
+        assert_eq!(get_balance(&deps, "alice"), Uint128(10));
+
+        let info = mock_info("bob", &[]);
+        handle(&mut deps, mock_env(), info, transfer(1)).unwrap();
+        assert_eq!(get_balance(&deps, "alice"), Uint128(20));
+    }
+
+    #[test]
+    fn effective_rate_includes_pending_rewards() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        deps.querier.update_staking(
+            "ustake",
+            &[sample_validator(DEFAULT_VALIDATOR)],
+            &[sample_delegation_with_rewards(
+                DEFAULT_VALIDATOR,
+                coin(1000, "ustake"),
+                coin(100, "ustake"),
+            )],
+        );
+
+        let res = query_effective_rate(&deps, mock_env()).unwrap();
+        assert_eq!(res.raw, Decimal::one());
+        assert_eq!(res.effective, Decimal::permille(1100));
+        assert!(res.effective > res.raw);
+
+        // the reward fee never reaches the pool
+        invest_info(&mut deps.storage)
+            .update(|mut invest| -> StdResult<_> {
+                invest.reward_fee = Decimal::percent(10);
+                Ok(invest)
+            })
+            .unwrap();
+        let res = query_effective_rate(&deps, mock_env()).unwrap();
+        assert_eq!(res.effective, Decimal::permille(1090));
+    }
+
+    #[test]
+    fn backed_rate_leaves_out_unbonded_claims() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let res = query_backed_rate(&deps).unwrap();
+        assert_eq!(res.backed, FALLBACK_RATIO);
+
+        for delegator in ["alice", "bob"].iter() {
+            let info = mock_info(*delegator, &coins(1000, "ustake"));
+            let msg = HandleMsg::Bond { validator: None };
+            handle(&mut deps, mock_env(), info, msg).unwrap();
+        }
+        set_delegation(&mut deps.querier, 2000, "ustake");
+
+        // bob's claim of 588 left bonded when the tokens were burned, so it isn't set
+        // aside a second time
+        let msg = HandleMsg::Unbond {
+            amount: Some(Uint128(600)),
+        };
+        handle(&mut deps, mock_env(), mock_info("bob", &[]), msg).unwrap();
+        let supply = total_supply_read(&deps.storage).load().unwrap();
+        assert_eq!(supply.claims, Uint128(588));
+        assert_eq!(supply.bonded, Uint128(1412));
+        let res = query_backed_rate(&deps).unwrap();
+        assert_eq!(res.nominal, Decimal::one());
+        assert_eq!(res.backed, Decimal::one());
+        assert!(res.backed <= res.nominal);
+    }
+
+    #[test]
+    fn min_meaningful_bond_mints_one_token() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let res = query_min_meaningful_bond(&deps).unwrap();
+        assert_eq!(res.minimum, coin(1, "ustake"));
+
+        // ratio 1.5: 2 ustake mints 2 * 1000 / 1500 = 1 token, 1 ustake mints none
+        total_supply(&mut deps.storage)
+            .update(|mut supply| -> StdResult<_> {
+                supply.issued = Uint128(1000);
+                supply.bonded = Uint128(1500);
+                Ok(supply)
+            })
+            .unwrap();
+        let res = query_min_meaningful_bond(&deps).unwrap();
+        assert_eq!(res.minimum, coin(2, "ustake"));
+        let (issued, bonded) = (Uint128(1000), Uint128(1500));
+        let minted = checked_mint(res.minimum.amount, issued, bonded).unwrap();
+        assert_eq!(minted, Uint128(1));
+        let below = (res.minimum.amount - Uint128(1)).unwrap();
+        assert_eq!(checked_mint(below, issued, bonded).unwrap(), Uint128(0));
+    }
+
+    #[test]
+    fn reinvest_all_compounds_proportionally() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        seed_delegation(&mut deps, "alice", DEFAULT_VALIDATOR, 100, 100);
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 200, 100);
+        seed_delegation(&mut deps, "carl", DEFAULT_VALIDATOR, 300, 100);
+        deps.querier.update_staking(
+            "ustake",
+            &[sample_validator(DEFAULT_VALIDATOR)],
+            &[sample_delegation_with_rewards(
+                DEFAULT_VALIDATOR,
+                coin(600, "ustake"),
+                coin(60, "ustake"),
+            )],
+        );
+
+        let info = mock_info("keeper", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::ReinvestAll {}).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![
+                StakingMsg::Withdraw {
+                    validator: HumanAddr::from(DEFAULT_VALIDATOR),
+                    recipient: None,
+                }
+                .into(),
+                StakingMsg::Delegate {
+                    validator: HumanAddr::from(DEFAULT_VALIDATOR),
+                    amount: coin(60, "ustake"),
+                }
+                .into(),
+            ]
+        );
+
+        // the rewards are settled into the compound and minted to their owners
+        for (addr, amount) in &[("alice", 110), ("bob", 220), ("carl", 330)] {
+            let delegation = query_delegation(&deps, HumanAddr::from(*addr)).unwrap();
+            assert_eq!(delegation.amount, Uint128(*amount));
+            assert_eq!(delegation.undelegate_reward, Uint128(0));
+            let minted = Uint128(*amount / 11);
+            assert_eq!(get_balance(&deps, &HumanAddr::from(*addr)), minted);
+        }
+        let supply = total_supply_read(&deps.storage).load().unwrap();
+        assert_eq!(supply.bonded, Uint128(60));
+        assert_eq!(supply.issued, Uint128(60));
+    }
+
+    #[test]
+    fn reinvest_all_mints_without_diluting() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        for name in ["alice", "bob"].iter() {
+            let info = mock_info(*name, &[coin(1000, "ustake")]);
+            let msg = HandleMsg::Bond { validator: None };
+            handle(&mut deps, mock_env(), info, msg).unwrap();
+        }
+        let msg = HandleMsg::SetAutoReinvest { enabled: false };
+        handle(&mut deps, mock_env(), mock_info("bob", &[]), msg).unwrap();
+        deps.querier.update_staking(
+            "ustake",
+            &[sample_validator(DEFAULT_VALIDATOR)],
+            &[sample_delegation_with_rewards(
+                DEFAULT_VALIDATOR,
+                coin(2000, "ustake"),
+                coin(200, "ustake"),
+            )],
+        );
+
+        // alice's 100 is bonded for her alone, bob's 100 stays owed to him
+        let info = mock_info("keeper", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::ReinvestAll {}).unwrap();
+        assert!(res.attributes.contains(&attr("compounded", 100)));
+        assert_eq!(get_balance(&deps, &HumanAddr::from("alice")), Uint128(1100));
+        assert_eq!(get_balance(&deps, &HumanAddr::from("bob")), Uint128(1000));
+        let invest = query_investment(&deps).unwrap();
+        assert_eq!(invest.nominal_value, Decimal::one());
+        let bob = query_delegation(&deps, HumanAddr::from("bob")).unwrap();
+        assert_eq!(bob.undelegate_reward, Uint128(100));
+    }
+
+    #[test]
+    fn reinvested_rewards_are_claimed_once() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        for name in ["alice", "bob"].iter() {
+            let info = mock_info(*name, &[coin(1000, "ustake")]);
+            let msg = HandleMsg::Bond { validator: None };
+            handle(&mut deps, mock_env(), info, msg).unwrap();
+        }
+
+        // 200 of rewards are withdrawn and bonded by the callback
+        deps.querier.update_staking(
+            "ustake",
+            &[sample_validator(DEFAULT_VALIDATOR)],
+            &[sample_delegation_with_rewards(
+                DEFAULT_VALIDATOR,
+                coin(2000, "ustake"),
+                coin(200, "ustake"),
+            )],
+        );
+        let info = mock_info("keeper", &[]);
+        handle(&mut deps, mock_env(), info, HandleMsg::Reinvest {}).unwrap();
+        set_delegation(&mut deps.querier, 2000, "ustake");
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(200, "ustake"));
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap();
+        set_delegation(&mut deps.querier, 2200, "ustake");
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(0, "ustake"));
+
+        // both unbond everything and the sweep settles what else they are owed
+        let mut undelegated = 0;
+        for name in ["alice", "bob"].iter() {
+            let info = mock_info(*name, &[]);
+            let msg = HandleMsg::Unbond { amount: None };
+            let res = handle(&mut deps, mock_env(), info, msg).unwrap();
+            for msg in res.messages.iter() {
+                if let CosmosMsg::Staking(StakingMsg::Undelegate { amount, .. }) = msg {
+                    undelegated += amount.amount.u128();
+                }
+            }
+        }
+        let mut env = mock_env();
+        env.block.height += EXPIRY_BLOCKS + 1;
+        is_expired(&mut deps, env, None).unwrap();
+
+        // once the stake is released the contract can pay every claim
+        let claims = get_claims(&deps, "alice") + get_claims(&deps, "bob");
+        assert_eq!(claims, Uint128(2156));
+        assert!(claims.u128() <= undelegated);
+    }
+
+    #[test]
+    fn bond_stray_benefits_the_pool() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 1000, 100);
+        seed_reward(&mut deps, "bob", 30);
+        total_supply(&mut deps.storage)
+            .update(|mut supply| -> StdResult<_> {
+                supply.issued = Uint128(1000);
+                supply.bonded = Uint128(1000);
+                Ok(supply)
+            })
+            .unwrap();
+        // 30 is owed to bob, the other 70 was sent straight to the contract
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(100, "ustake"));
+
+        let info = mock_info("anyone", &[]);
+        let err = handle(&mut deps, mock_env(), info, HandleMsg::BondStray {}).unwrap_err();
+        assert!(err.to_string().contains("disabled"));
+
+        let msg = HandleMsg::SetBondStray { enabled: true };
+        let info = mock_info("bob", &[]);
+        match handle(&mut deps, mock_env(), info, msg.clone()).unwrap_err() {
+            StakingError::Unauthorized { .. } => {}
+            err => panic!("Unexpected error: {:?}", err),
+        }
+        let info = mock_info("creator", &[]);
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+
+        let info = mock_info("anyone", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::BondStray {}).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![StakingMsg::Delegate {
+                validator: HumanAddr::from(DEFAULT_VALIDATOR),
+                amount: coin(70, "ustake"),
+            }
+            .into()]
+        );
+        assert!(res.attributes.contains(&attr("bonded", 1070)));
+
+        // no tokens were minted, so the existing ones are worth more
+        let invest = query_investment(&deps).unwrap();
+        assert_eq!(invest.token_supply, Uint128(1000));
+        assert_eq!(invest.nominal_value, Decimal::permille(1070));
+    }
+
+    #[test]
+    fn reinvest_skips_self_redelegate() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 1000, 12_000);
+        seed_reward(&mut deps, "bob", 100);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(1100, "ustake"));
+
+        // the best validator is the one bob is already on
+        let bob = HumanAddr::from("bob");
+        let res = reinvest(&mut deps, mock_env(), bob.clone()).unwrap();
+        assert_eq!(1, res.messages.len());
+        match &res.messages[0] {
+            CosmosMsg::Staking(StakingMsg::Delegate { validator, .. }) => {
+                assert_eq!(validator.as_str(), DEFAULT_VALIDATOR)
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
+        }
+
+        // nothing moved, so no cooldown starts
+        let delegation = query_delegation(&deps, bob).unwrap();
+        assert_eq!(delegation.last_redelegate_height, None);
+    }
+
+    #[test]
+    fn expire_claims_after_grace_period() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let creator = HumanAddr::from("creator");
+        let info = mock_info(&creator, &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let bob = HumanAddr::from("bob");
+        let bob_raw = deps.api.canonical_address(&bob).unwrap();
+        add_claim(&mut deps.storage, &bob_raw, Uint128(40), 100).unwrap();
+
+        // still within the grace window
+        let mut env = mock_env();
+        env.block.height = 100 + CLAIM_EXPIRY_BLOCKS - 1;
+        let msg = HandleMsg::ExpireClaims {
+            address: bob.clone(),
+        };
+        let info = mock_info(&creator, &[]);
+        handle(&mut deps, env.clone(), info, msg.clone()).unwrap_err();
+
+        // only the owner can expire claims
+        env.block.height += 1;
+        let res = handle(&mut deps, env.clone(), mock_info("bob", &[]), msg.clone());
+        match res.unwrap_err() {
+            StakingError::Unauthorized { .. } => {}
+            err => panic!("Unexpected error: {:?}", err),
+        }
+
+        let res = handle(&mut deps, env, mock_info(&creator, &[]), msg).unwrap();
+        assert_eq!(res.attributes[2], attr("reclaimed", Uint128(40)));
+        assert_eq!(
+            res.messages,
+            vec![CosmosMsg::Staking(StakingMsg::Delegate {
+                validator: HumanAddr::from(DEFAULT_VALIDATOR),
+                amount: coin(40, "ustake"),
+            })]
+        );
+        assert_eq!(get_claims(&deps, &bob), Uint128(0));
+        let supply = total_supply_read(&deps.storage).load().unwrap();
+        assert_eq!(supply.claims, Uint128(0));
+        assert_eq!(supply.bonded, Uint128(40));
+    }
+
+    #[test]
+    fn account_bundles_position() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let bob = HumanAddr::from("bob");
+        let res = query_account(&deps, bob.clone()).unwrap();
+        assert_eq!(res.balance, Uint128(0));
+        assert_eq!(res.claims, Uint128(0));
+        assert_eq!(res.delegation, None);
+
+        let info = mock_info(&bob, &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        let bob_raw = deps.api.canonical_address(&bob).unwrap();
+        add_claim(&mut deps.storage, &bob_raw, Uint128(30), 100).unwrap();
+
+        let res = query_account(&deps, bob.clone()).unwrap();
+        assert_eq!(res.balance, Uint128(1000));
+        assert_eq!(res.claims, Uint128(30));
+        let delegation = res.delegation.unwrap();
+        assert_eq!(delegation.delegator, bob);
+        assert_eq!(delegation.amount, Uint128(1000));
+    }
+
+    #[test]
+    fn display_denom_round_trips() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let mut msg = default_init(2, 50);
+        msg.display_denom = Some("STAKE".to_string());
+        msg.display_exponent = Some(6);
+        init(&mut deps, mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let invest = query_investment(&deps).unwrap();
+        assert_eq!(invest.display_denom, Some("STAKE".to_string()));
+        assert_eq!(invest.display_exponent, Some(6));
+    }
+
+    #[test]
+    fn evacuate_validator_undelegates_and_rebonds() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier.update_staking(
+            "ustake",
+            &[
+                sample_validator(DEFAULT_VALIDATOR),
+                sample_validator("tombstoned"),
+            ],
+            &[sample_delegation("tombstoned", coin(1000, "ustake"))],
+        );
+        let creator = HumanAddr::from("creator");
+        let info = mock_info(&creator, &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        seed_delegation(&mut deps, "bob", "tombstoned", 1000, 100);
+        total_supply(&mut deps.storage)
+            .save(&Supply {
+                issued: Uint128(1000),
+                bonded: Uint128(1000),
+                ..Supply::default()
+            })
+            .unwrap();
+
+        let msg = HandleMsg::EvacuateValidator {
+            from: HumanAddr::from("tombstoned"),
+        };
+        let res = handle(&mut deps, mock_env(), mock_info("bob", &[]), msg.clone());
+        match res.unwrap_err() {
+            StakingError::Unauthorized { .. } => {}
+            err => panic!("Unexpected error: {:?}", err),
+        }
+
+        let res = handle(&mut deps, mock_env(), mock_info(&creator, &[]), msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![CosmosMsg::Staking(StakingMsg::Undelegate {
+                validator: HumanAddr::from("tombstoned"),
+                amount: coin(1000, "ustake"),
+            })]
+        );
+        let bob = query_delegation(&deps, HumanAddr::from("bob")).unwrap();
+        assert_eq!(bob.validator, HumanAddr::from(DEFAULT_VALIDATOR));
+
+        // once unbonded, the stake is bonded again without inflating supply.bonded
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(1000, "ustake"));
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        let supply = total_supply_read(&deps.storage).load().unwrap();
+        assert_eq!(supply.bonded, Uint128(1000));
+        assert_eq!(supply.evacuating, Uint128(0));
+    }
+
+    #[test]
+    fn reward_index_accrues_across_cycles() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        seed_delegation(&mut deps, "alice", DEFAULT_VALIDATOR, 100, 100);
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 300, 100);
+        let reward_of = |deps: &Extern<_, _, _>, addr: &str| {
+            query_delegation(deps, HumanAddr::from(addr))
+                .unwrap()
+                .undelegate_reward
+        };
+
+        // first cycle pays 0.1 per token, alice settles right away
+        bump_reward_index(&mut deps, Uint128(40)).unwrap();
+        claim(&mut deps, mock_env(), HumanAddr::from("alice")).unwrap();
+        assert_eq!(reward_of(&deps, "alice"), Uint128(10));
+
+        // second cycle pays 0.2 per token, alice only gets the new part
+        bump_reward_index(&mut deps, Uint128(80)).unwrap();
+        claim(&mut deps, mock_env(), HumanAddr::from("alice")).unwrap();
+        claim(&mut deps, mock_env(), HumanAddr::from("bob")).unwrap();
+        assert_eq!(reward_of(&deps, "alice"), Uint128(30));
+        assert_eq!(reward_of(&deps, "bob"), Uint128(90));
+
+        // settling twice doesn't pay twice
+        claim(&mut deps, mock_env(), HumanAddr::from("bob")).unwrap();
+        assert_eq!(reward_of(&deps, "bob"), Uint128(90));
+
+        // a new bond starts from the current index and earns nothing retroactively
+        let info = mock_info("carl", &[coin(100, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        claim(&mut deps, mock_env(), HumanAddr::from("carl")).unwrap();
+        assert_eq!(reward_of(&deps, "carl"), Uint128(0));
+    }
+
+    #[test]
+    fn balance_sheet_shows_slash() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        let res = query_balance_sheet(&deps, mock_env()).unwrap();
+        assert_eq!(res.coverage, Decimal::one());
+
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+        let res = query_balance_sheet(&deps, mock_env()).unwrap();
+        assert_eq!(res.assets, Uint128(1000));
+        assert_eq!(res.obligations, Uint128(1000));
+        assert!(res.coverage >= Decimal::one());
+
+        // a 10% slash leaves the pool short
+        set_delegation(&mut deps.querier, 900, "ustake");
+        let res = query_balance_sheet(&deps, mock_env()).unwrap();
+        assert_eq!(res.coverage, Decimal::percent(90));
+        assert!(res.coverage < Decimal::one());
+    }
+
+    #[test]
+    fn tvl_sums_bonded_balance_and_rewards() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        deps.querier.update_staking(
+            "ustake",
+            &[sample_validator(DEFAULT_VALIDATOR)],
+            &[sample_delegation_with_rewards(
+                DEFAULT_VALIDATOR,
+                coin(1000, "ustake"),
+                coin(25, "ustake"),
+            )],
+        );
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(75, "ustake"));
+
+        let res = query_tvl(&deps, mock_env()).unwrap();
+        assert_eq!(res.tvl, coin(1100, "ustake"));
+        assert_eq!(res.reserved, coin(0, "ustake"));
+
+        // claims and fees held in the balance are reported apart
+        total_supply(&mut deps.storage)
+            .update(|mut supply| -> StdResult<_> {
+                supply.claims = Uint128(40);
+                supply.fees = Uint128(10);
+                Ok(supply)
+            })
+            .unwrap();
+        let res = query_tvl(&deps, mock_env()).unwrap();
+        assert_eq!(res.tvl, coin(1050, "ustake"));
+        assert_eq!(res.reserved, coin(50, "ustake"));
+    }
+
+    #[test]
+    fn get_bonded_with_mixed_denoms() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier.update_staking(
+            "ustake",
+            &[
+                sample_validator(DEFAULT_VALIDATOR),
+                sample_validator("other"),
+            ],
+            &[
+                sample_delegation(DEFAULT_VALIDATOR, coin(1000, "ustake")),
+                sample_delegation("other", coin(300, "uatom")),
+            ],
+        );
+        let contract = HumanAddr::from(MOCK_CONTRACT_ADDR);
+
+        let bonded = get_bonded(&deps.querier, &contract, "ustake").unwrap();
+        assert_eq!(bonded, Uint128(1000));
+    }
+
+    #[test]
+    fn contract_balance_in_bond_denom() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        deps.querier.update_balance(
+            MOCK_CONTRACT_ADDR,
+            vec![coin(300, "ustake"), coin(7, "uatom")],
+        );
+        let res = query_contract_balance(&deps, mock_env()).unwrap();
+        assert_eq!(res.balance, coin(300, "ustake"));
+    }
+
+    #[test]
+    fn reinvest_skips_idle_delegator() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 0, 100);
+
+        let before: Vec<_> = deps.storage.range(None, None, Order::Ascending).collect();
+        let res = reinvest(&mut deps, mock_env(), HumanAddr::from("bob")).unwrap();
+        assert_eq!(res, HandleResponse::default());
+        let after: Vec<_> = deps.storage.range(None, None, Order::Ascending).collect();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn exit_tax_split_across_fee_recipients() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+
+        // weights must add up to 1
+        let mut msg = default_init(2, 50);
+        msg.fee_recipients = Some(vec![
+            (HumanAddr::from("treasury"), Decimal::percent(60)),
+            (HumanAddr::from("dev"), Decimal::percent(30)),
+        ]);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap_err();
+
+        let mut msg = default_init(2, 50);
+        msg.fee_recipients = Some(vec![
+            (HumanAddr::from("treasury"), Decimal::percent(60)),
+            (HumanAddr::from("dev"), Decimal::percent(40)),
+        ]);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+
+        let info = mock_info("bob", &[coin(5050, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        set_delegation(&mut deps.querier, 5050, "ustake");
+
+        // 2% of 5050 is 101, split 60.6 / 40.4 with the remainder to the treasury
+        let info = mock_info("bob", &[]);
+        let msg = HandleMsg::Unbond { amount: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        assert_eq!(
+            get_balance(&deps, &HumanAddr::from("treasury")),
+            Uint128(61)
+        );
+        assert_eq!(get_balance(&deps, &HumanAddr::from("dev")), Uint128(40));
+        // the rest of bob's tokens are burned
+        assert_eq!(get_balance(&deps, &HumanAddr::from("bob")), Uint128(0));
+
+        // asking again doesn't tax or burn again
+        let info = mock_info("bob", &[]);
+        let msg = HandleMsg::Unbond { amount: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        assert_eq!(
+            get_balance(&deps, &HumanAddr::from("treasury")),
+            Uint128(61)
+        );
+        assert_eq!(query_investment(&deps).unwrap().token_supply, Uint128(101));
+    }
+
+    #[test]
+    fn fees_earned_tracks_owner_cut() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let mut msg = default_init(2, 50);
+        msg.fee_recipients = Some(vec![
+            (HumanAddr::from("creator"), Decimal::percent(50)),
+            (HumanAddr::from("dev"), Decimal::percent(50)),
+        ]);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+        assert_eq!(query_fees_earned(&deps).unwrap().fees_earned, Uint128(0));
+
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+
+        // 2% of 1000 is 20, half of which goes to the owner
+        let info = mock_info("bob", &[]);
+        let msg = HandleMsg::Unbond { amount: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        assert_eq!(query_fees_earned(&deps).unwrap().fees_earned, Uint128(10));
+        assert_eq!(get_balance(&deps, &HumanAddr::from("dev")), Uint128(10));
+    }
+
+    #[test]
+    fn withdraw_fees_pays_treasury() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let mut msg = default_init(2, 50);
+        msg.fee_recipients = Some(vec![(HumanAddr::from("treasury"), Decimal::one())]);
+        msg.reward_fee = Some(Decimal::percent(10));
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+
+        // withdrawing 200 of rewards sets 10% aside and leaves the rest to the pool
+        deps.querier.update_staking(
+            "ustake",
+            &[sample_validator(DEFAULT_VALIDATOR)],
+            &[sample_delegation_with_rewards(
+                DEFAULT_VALIDATOR,
+                coin(1000, "ustake"),
+                coin(200, "ustake"),
+            )],
+        );
+        let info = mock_info("keeper", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::Reinvest {}).unwrap();
+        assert!(res.attributes.contains(&attr("fee", 20)));
+        let supply = total_supply_read(&deps.storage).load().unwrap();
+        assert_eq!(supply.fees, Uint128(20));
+
+        // the fee is never bonded with the rewards
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(200, "ustake"));
+        let invest = invest_info_read(&deps.storage).load().unwrap();
+        let contract = HumanAddr::from(MOCK_CONTRACT_ADDR);
+        let free = free_balance(&deps, &contract, &invest).unwrap();
+        assert_eq!(free, Uint128(180));
+
+        let info = mock_info("bob", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::WithdrawFees {});
+        match res.unwrap_err() {
+            StakingError::Unauthorized { .. } => {}
+            err => panic!("Unexpected error: {:?}", err),
+        }
+
+        let info = mock_info("creator", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::WithdrawFees {}).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: HumanAddr::from(MOCK_CONTRACT_ADDR),
+                to_address: HumanAddr::from("treasury"),
+                amount: coins(20, "ustake"),
+            })]
+        );
+        let supply = total_supply_read(&deps.storage).load().unwrap();
+        assert_eq!(supply.fees, Uint128(0));
+    }
+
+    #[test]
+    fn owner_fees_exclude_owner_bonds() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        for addr in &["creator", "bob"] {
+            let info = mock_info(*addr, &[coin(1000, "ustake")]);
+            let msg = HandleMsg::Bond { validator: None };
+            handle(&mut deps, mock_env(), info, msg).unwrap();
+        }
+        set_delegation(&mut deps.querier, 2000, "ustake");
+        let info = mock_info("bob", &[]);
+        let msg = HandleMsg::Unbond { amount: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+
+        // 2% of bob's 1000 went to the owner, next to its own 1000
+        let res = query_owner_fees(&deps).unwrap();
+        assert_eq!(res.fees, Uint128(20));
+        assert_eq!(res.bonds, Uint128(1000));
+    }
+
+    #[test]
+    fn bond_rechecks_validator_set() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let validator = HumanAddr::from(DEFAULT_VALIDATOR);
+        assert_validator_active(&deps.querier, &validator).unwrap();
+
+        // the validator drops out of the active set
+        deps.querier
+            .update_staking("ustake", &[sample_validator("other")], &[]);
+        let err = assert_validator_active(&deps.querier, &validator).unwrap_err();
+        match err {
+            StdError::GenericErr { msg, .. } => {
+                assert_eq!(msg, "default-validator is not in the current validator set")
+            }
+            err => panic!("Unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn delegation_preconditions_fail_clearly() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let querier = &deps.querier;
+        let validator = HumanAddr::from(DEFAULT_VALIDATOR);
+        let err = delegate_msg(querier, validator.clone(), coin(0, "ustake")).unwrap_err();
+        assert!(err.to_string().contains("Cannot delegate zero ustake"));
+        delegate_msg(querier, validator, coin(10, "ustake")).unwrap();
+
+        // rewards can't be bonded once the configured validator left the set
+        deps.querier
+            .update_staking("ustake", &[sample_validator("other")], &[]);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(100, "ustake"));
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let err = handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("default-validator is not in the current validator set"));
+    }
+
+    #[test]
+    fn unbond_status_after_unbond() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(0, 50)).unwrap();
+
+        let bob = HumanAddr::from("bob");
+        let info = mock_info(&bob, &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+        let res = query_unbond_status(&deps, bob.clone()).unwrap();
+        assert_eq!(res.unbond_flag, false);
+
+        let info = mock_info(&bob, &[]);
+        let msg = HandleMsg::Unbond { amount: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        let res = query_unbond_status(&deps, bob).unwrap();
+        assert_eq!(res.unbond_flag, true);
+        assert_eq!(res.eligible_at, mock_env().block.height + EXPIRY_BLOCKS + 1);
+        // the stake was undelegated with the request, only its rewards wait for the sweep
+        assert_eq!(res.amount, Uint128(0));
+    }
+
+    #[test]
+    fn set_min_withdrawal_by_owner() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let msg = HandleMsg::SetMinWithdrawal {
+            min_withdrawal: Uint128(80),
+        };
+        let info = mock_info("bob", &[]);
+        match handle(&mut deps, mock_env(), info, msg.clone()).unwrap_err() {
+            StakingError::Unauthorized { .. } => {}
+            err => panic!("Unexpected error: {:?}", err),
+        }
+
+        let info = mock_info("creator", &[]);
+        let res = handle(&mut deps, mock_env(), info, msg).unwrap();
+        assert!(res.attributes.contains(&attr("previous", 50)));
+        assert!(res.attributes.contains(&attr("min_withdrawal", 80)));
+        assert_eq!(query_investment(&deps).unwrap().min_withdrawal, Uint128(80));
+
+        let msg = HandleMsg::SetMinWithdrawal {
+            min_withdrawal: Uint128(0),
+        };
+        let info = mock_info("creator", &[]);
+        handle(&mut deps, mock_env(), info, msg).unwrap_err();
+    }
+
+    #[test]
+    fn set_withdraw_address_by_owner() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        let msg = InitMsg {
+            reward_fee: Some(Decimal::percent(10)),
+            ..default_init(2, 50)
+        };
+        init(&mut deps, mock_env(), info, msg).unwrap();
+        let info = mock_info("alice", &coins(1000, "ustake"));
+        handle(
+            &mut deps,
+            mock_env(),
+            info,
+            HandleMsg::Bond { validator: None },
+        )
+        .unwrap();
+        set_delegation(&mut deps.querier, 1000, "ustake");
+
+        let msg = HandleMsg::SetWithdrawAddress {
+            address: HumanAddr::from("treasury"),
+        };
+        let info = mock_info("bob", &[]);
+        match handle(&mut deps, mock_env(), info, msg.clone()).unwrap_err() {
+            StakingError::Unauthorized { .. } => {}
+            err => panic!("Unexpected error: {:?}", err),
+        }
+
+        let info = mock_info("creator", &[]);
+        let res = handle(&mut deps, mock_env(), info, msg).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![CosmosMsg::Staking(StakingMsg::Withdraw {
+                validator: HumanAddr::from(DEFAULT_VALIDATOR),
+                recipient: Some(HumanAddr::from("treasury")),
+            })]
+        );
+        let invest = query_investment(&deps).unwrap();
+        assert_eq!(invest.withdraw_address, Some(HumanAddr::from("treasury")));
+
+        // later rewards go to the treasury too, so they are neither charged, credited
+        // nor bonded here
+        deps.querier.update_staking(
+            "ustake",
+            &[sample_validator(DEFAULT_VALIDATOR)],
+            &[sample_delegation_with_rewards(
+                DEFAULT_VALIDATOR,
+                coin(1000, "ustake"),
+                coin(100, "ustake"),
+            )],
+        );
+        let info = mock_info("keeper", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::Reinvest {}).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert!(res.attributes.contains(&attr("fee", 0)));
+        let info = mock_info("keeper", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::ReinvestAll {}).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        let supply = total_supply_read(&deps.storage).load().unwrap();
+        assert_eq!(supply.reward_index, Decimal::zero());
+        assert_eq!(supply.fees, Uint128(0));
+
+        // pointing it back at the contract clears it
+        let msg = HandleMsg::SetWithdrawAddress {
+            address: HumanAddr::from(MOCK_CONTRACT_ADDR),
+        };
+        let info = mock_info("creator", &[]);
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        assert_eq!(query_investment(&deps).unwrap().withdraw_address, None);
+    }
+
+    #[test]
+    fn migrate_delegator_merges_records() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        for (addr, amount) in &[("bob", 1000), ("dan", 500)] {
+            let info = mock_info(*addr, &[coin(*amount, "ustake")]);
+            let msg = HandleMsg::Bond { validator: None };
+            handle(&mut deps, mock_env(), info, msg).unwrap();
+        }
+        let height = mock_env().block.height;
+        let bob_raw = deps.api.canonical_address(&HumanAddr::from("bob")).unwrap();
+        let dan_raw = deps.api.canonical_address(&HumanAddr::from("dan")).unwrap();
+        add_claim(&mut deps.storage, &dan_raw, Uint128(5), height + 10).unwrap();
+        add_claim(&mut deps.storage, &bob_raw, Uint128(40), height - 10).unwrap();
+
+        let msg = HandleMsg::MigrateDelegator {
+            from: HumanAddr::from("bob"),
+            to: HumanAddr::from("dan"),
+        };
+        let info = mock_info("bob", &[]);
+        match handle(&mut deps, mock_env(), info, msg.clone()).unwrap_err() {
+            StakingError::Unauthorized { .. } => {}
+            err => panic!("Unexpected error: {:?}", err),
+        }
+        let info = mock_info("creator", &[]);
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+
+        // the delegation, balance and claims all end up with dan
+        let delegation = query_delegation(&deps, HumanAddr::from("dan")).unwrap();
+        assert_eq!(delegation.amount, Uint128(1500));
+        assert_eq!(get_balance(&deps, "dan"), Uint128(1500));
+        assert_eq!(get_claims(&deps, "dan"), Uint128(45));
+        let entries = claims_read(&deps.storage).load(dan_raw.as_slice()).unwrap();
+        assert_eq!(entries[0].amount, Uint128(40));
+
+        // and nothing is left with bob
+        let delegation = delegations_read(&deps.storage)
+            .may_load(bob_raw.as_slice())
+            .unwrap();
+        assert_eq!(delegation, None);
+        assert_eq!(get_balance(&deps, "bob"), Uint128(0));
+        assert_eq!(get_claims(&deps, "bob"), Uint128(0));
+        let all = query_all_delegators(&deps).unwrap();
+        assert_eq!(all, vec![HumanAddr::from("dan")]);
+        assert_eq!(query_delegator_count(&deps).unwrap().count, 1);
+    }
+
+    #[test]
+    fn set_reward_corrects_delegation() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 1000, 100);
+
+        let bob = HumanAddr::from("bob");
+        let msg = HandleMsg::SetReward {
+            address: bob.clone(),
+            amount: Uint128(42),
+        };
+        let info = mock_info("bob", &[]);
+        match handle(&mut deps, mock_env(), info, msg.clone()).unwrap_err() {
+            StakingError::Unauthorized { .. } => {}
+            err => panic!("Unexpected error: {:?}", err),
+        }
+        let delegation = query_delegation(&deps, bob.clone()).unwrap();
+        assert_eq!(delegation.undelegate_reward, Uint128(0));
+
+        let info = mock_info("creator", &[]);
+        let res = handle(&mut deps, mock_env(), info, msg).unwrap();
+        assert!(res.attributes.contains(&attr("previous", 0)));
+        assert!(res.attributes.contains(&attr("amount", 42)));
+        let delegation = query_delegation(&deps, bob).unwrap();
+        assert_eq!(delegation.undelegate_reward, Uint128(42));
+
+        // there must be a delegation to correct
+        let msg = HandleMsg::SetReward {
+            address: HumanAddr::from("nobody"),
+            amount: Uint128(1),
+        };
+        let info = mock_info("creator", &[]);
+        handle(&mut deps, mock_env(), info, msg).unwrap_err();
+    }
+
+    #[test]
+    fn update_token_info_by_owner() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let msg = HandleMsg::UpdateTokenInfo {
+            name: None,
+            symbol: Some("DRVX".to_string()),
+        };
+        let info = mock_info("bob", &[]);
+        match handle(&mut deps, mock_env(), info, msg.clone()).unwrap_err() {
+            StakingError::Unauthorized { .. } => {}
+            err => panic!("Unexpected error: {:?}", err),
+        }
+
+        let bad = HandleMsg::UpdateTokenInfo {
+            name: Some("".to_string()),
+            symbol: None,
+        };
+        let info = mock_info("creator", &[]);
+        handle(&mut deps, mock_env(), info, bad).unwrap_err();
+
+        let info = mock_info("creator", &[]);
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        let token = query_token_info(&deps).unwrap();
+        assert_eq!(token.name, "Cool Derivative");
+        assert_eq!(token.symbol, "DRVX");
+        assert_eq!(token.decimals, 9);
+    }
+
+    #[test]
+    fn init_validates_token_info() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+
+        let mut msg = default_init(2, 50);
+        msg.symbol = "DR".to_string();
+        let info = mock_info("creator", &[]);
+        let err = init(&mut deps, mock_env(), info, msg).unwrap_err();
+        assert!(err.to_string().contains("must be 3 to 12 characters"));
+
+        let mut msg = default_init(2, 50);
+        msg.symbol = "DRV2".to_string();
+        let info = mock_info("creator", &[]);
+        let err = init(&mut deps, mock_env(), info, msg).unwrap_err();
+        assert!(err.to_string().contains("only contain letters"));
+
+        let mut msg = default_init(2, 50);
+        msg.name = "Cool\nDerivative".to_string();
+        let info = mock_info("creator", &[]);
+        let err = init(&mut deps, mock_env(), info, msg).unwrap_err();
+        assert!(err.to_string().contains("control characters"));
+    }
+
+    #[test]
+    fn bond_mint_overflow_is_an_error() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        // a contrived ratio of u128::MAX / 2 derivatives per bonded token
+        total_supply(&mut deps.storage)
+            .update(|mut supply| -> StdResult<_> {
+                supply.issued = Uint128(u128::MAX / 2);
+                supply.bonded = Uint128(1);
+                Ok(supply)
+            })
+            .unwrap();
+
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        match handle(&mut deps, mock_env(), info, msg).unwrap_err() {
+            StakingError::Std {
+                original: StdError::GenericErr { msg, .. },
+            } => assert!(msg.contains("overflows"), "{}", msg),
+            err => panic!("Unexpected error: {:?}", err),
+        }
+        assert_eq!(get_balance(&deps, "bob"), Uint128(0));
+    }
+
+    #[test]
+    fn reinvest_clamps_to_free_balance() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        // 100 is owed but only 30 of the 70 held are free of claims
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 1000, 100);
+        seed_reward(&mut deps, "bob", 100);
+        total_supply(&mut deps.storage)
+            .update(|mut supply| -> StdResult<_> {
+                supply.claims = Uint128(40);
+                Ok(supply)
+            })
+            .unwrap();
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(70, "ustake"));
+
+        let bob = HumanAddr::from("bob");
+        let res = reinvest(&mut deps, mock_env(), bob.clone()).unwrap();
+        match &res.messages[..] {
+            [CosmosMsg::Staking(StakingMsg::Delegate { amount, .. })] => {
+                assert_eq!(amount.amount, Uint128(30))
+            }
+            msgs => panic!("Unexpected messages: {:?}", msgs),
+        }
+        let delegation = query_delegation(&deps, bob.clone()).unwrap();
+        assert_eq!(delegation.amount, Uint128(1030));
+        assert_eq!(delegation.undelegate_reward, Uint128(70));
+
+        // with nothing free, no Delegate is sent at all
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(40, "ustake"));
+        let res = reinvest(&mut deps, mock_env(), bob).unwrap();
+        assert!(res.messages.is_empty());
+    }
 
-pub fn query_investment<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-) -> StdResult<InvestmentResponse> {
-    let invest = invest_info_read(&deps.storage).load()?;
-    let supply = total_supply_read(&deps.storage).load()?;
+    #[test]
+    fn reserved_balance_is_never_reinvested() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 0)).unwrap();
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 1000, 100);
+        seed_reward(&mut deps, "bob", 100);
 
-    let res = InvestmentResponse {
-        owner: deps.api.human_address(&invest.owner)?,
-        exit_tax: invest.exit_tax,
-        validator: invest.validator,
-        min_withdrawal: invest.min_withdrawal,
-        token_supply: supply.issued,
-        staked_tokens: coin(supply.bonded.u128(), &invest.bond_denom),
-        nominal_value: if supply.issued.is_zero() {
-            FALLBACK_RATIO
-        } else {
-            Decimal::from_ratio(supply.bonded, supply.issued)
-        },
-    };
-    Ok(res)
-}
+        // all 100 held are owed: 70 to claims and 30 to fees
+        total_supply(&mut deps.storage)
+            .update(|mut supply| -> StdResult<_> {
+                supply.claims = Uint128(70);
+                supply.fees = Uint128(30);
+                Ok(supply)
+            })
+            .unwrap();
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(100, "ustake"));
 
-fn query_validators<S: Storage, A: Api, Q: Querier>(
-    deps: &Extern<S, A, Q>,
-) -> StdResult<Vec<Validator>> {
-    let request = StakingQuery::Validators {}.into();
-    let res: ValidatorsResponse = deps.querier.query(&QueryRequest::Staking(request))?;
-    Ok(res.validators)
-}
+        let bob = HumanAddr::from("bob");
+        let res = reinvest(&mut deps, mock_env(), bob).unwrap();
+        assert!(res.messages.is_empty());
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap();
+        assert!(res.messages.is_empty());
+        let preview = query_reinvest_preview(&deps, mock_env()).unwrap();
+        assert_eq!(preview.to_bond.amount, Uint128(0));
+        let supply = total_supply_read(&deps.storage).load().unwrap();
+        assert_eq!(supply.claims, Uint128(70));
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cosmwasm_std::testing::{
-        mock_dependencies, mock_env, mock_info, MockQuerier, MOCK_CONTRACT_ADDR,
-    };
-    use cosmwasm_std::{coins, Coin, CosmosMsg, Decimal, FullDelegation, Validator};
-    use std::str::FromStr;
+    #[test]
+    fn withdraw_rewards_keeps_principal() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
 
-    fn sample_validator<U: Into<HumanAddr>>(addr: U) -> Validator {
-        Validator {
-            address: addr.into(),
-            commission: Decimal::percent(3),
-            max_commission: Decimal::percent(10),
-            max_change_rate: Decimal::percent(1),
-        }
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, 1000, 100);
+        seed_reward(&mut deps, "bob", 40);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(100, "ustake"));
+
+        // 40 is below the min_withdrawal of 50
+        let info = mock_info("bob", &[]);
+        handle(&mut deps, mock_env(), info, HandleMsg::WithdrawRewards {}).unwrap_err();
+
+        seed_reward(&mut deps, "bob", 80);
+        let info = mock_info("bob", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::WithdrawRewards {}).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                from_address: HumanAddr::from(MOCK_CONTRACT_ADDR),
+                to_address: HumanAddr::from("bob"),
+                amount: coins(80, "ustake"),
+            })]
+        );
+        let delegation = query_delegation(&deps, HumanAddr::from("bob")).unwrap();
+        assert_eq!(delegation.amount, Uint128(1000));
+        assert_eq!(delegation.undelegate_reward, Uint128(0));
     }
 
-    fn custom_sample_validator<U: Into<HumanAddr>>(
-        addr: U,
-        comm: u64,
-        max_comm: u64,
-        rate: u64,
-    ) -> Validator {
-        Validator {
-            address: addr.into(),
-            commission: Decimal::percent(comm),
-            max_commission: Decimal::percent(max_comm),
-            max_change_rate: Decimal::percent(rate),
+    #[test]
+    fn reinvest_unbonds_from_stale_validator() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        // bob's stake sits on a validator that has since left the set
+        seed_delegation(&mut deps, "bob", "gone", 1000, 100);
+        seed_reward(&mut deps, "bob", 100);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(100, "ustake"));
+
+        let bob = HumanAddr::from("bob");
+        let res = reinvest(&mut deps, mock_env(), bob.clone()).unwrap();
+        assert_eq!(2, res.messages.len());
+        match &res.messages[0] {
+            CosmosMsg::Staking(StakingMsg::Delegate { validator, .. }) => {
+                assert_eq!(validator.as_str(), DEFAULT_VALIDATOR)
+            }
+            msg => panic!("Unexpected message: {:?}", msg),
         }
+        assert_eq!(
+            res.messages[1],
+            StakingMsg::Undelegate {
+                validator: HumanAddr::from("gone"),
+                amount: coin(1000, "ustake"),
+            }
+            .into()
+        );
+
+        // it is tracked like an evacuation until it is bonded again
+        let supply = total_supply_read(&deps.storage).load().unwrap();
+        assert_eq!(supply.evacuating, Uint128(1000));
+        let delegation = query_delegation(&deps, bob).unwrap();
+        assert_eq!(delegation.validator.as_str(), DEFAULT_VALIDATOR);
+        assert_eq!(delegation.last_redelegate_height, None);
     }
 
-    fn sample_delegation<U: Into<HumanAddr>>(addr: U, amount: Coin) -> FullDelegation {
-        let can_redelegate = amount.clone();
-        FullDelegation {
-            validator: addr.into(),
-            delegator: HumanAddr::from(MOCK_CONTRACT_ADDR),
-            amount,
-            can_redelegate,
-            accumulated_rewards: Vec::new(),
+    #[test]
+    fn reinvest_overflow_is_an_error() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        seed_delegation(&mut deps, "bob", DEFAULT_VALIDATOR, u128::MAX, 100);
+        seed_reward(&mut deps, "bob", 1);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(1, "ustake"));
+
+        match reinvest(&mut deps, mock_env(), HumanAddr::from("bob")).unwrap_err() {
+            StdError::GenericErr { msg, .. } => assert!(msg.contains("overflows"), "{}", msg),
+            err => panic!("Unexpected error: {:?}", err),
         }
     }
 
-    fn set_validator(querier: &mut MockQuerier) {
-        querier.update_staking("ustake", &[sample_validator(DEFAULT_VALIDATOR)], &[]);
+    #[test]
+    fn bond_rejects_zero_amount() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+
+        let info = mock_info("bob", &[coin(0, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        let res = handle(&mut deps, mock_env(), info, msg);
+        match res.unwrap_err() {
+            StakingError::Std {
+                original: StdError::GenericErr { msg, .. },
+            } => assert_eq!(msg, "cannot bond zero"),
+            err => panic!("Unexpected error: {:?}", err),
+        }
+        let res = query_is_delegator(&deps, HumanAddr::from("bob")).unwrap();
+        assert_eq!(res.is_delegator, false);
     }
 
-    fn set_delegation(querier: &mut MockQuerier, amount: u128, denom: &str) {
-        querier.update_staking(
+    #[test]
+    fn validator_rewards_per_validator() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier.update_staking(
             "ustake",
-            &[sample_validator(DEFAULT_VALIDATOR)],
-            &[sample_delegation(DEFAULT_VALIDATOR, coin(amount, denom))],
+            &[sample_validator("john"), sample_validator("mary")],
+            &[
+                sample_delegation_with_rewards("john", coin(1000, "ustake"), coin(30, "ustake")),
+                sample_delegation_with_rewards("mary", coin(1000, "ustake"), coin(5, "ustake")),
+            ],
+        );
+        let mut msg = default_init(2, 50);
+        msg.validator = HumanAddr::from("john");
+        init(&mut deps, mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let res = query_validator_rewards(&deps, mock_env()).unwrap();
+        assert_eq!(
+            res.rewards,
+            vec![
+                (HumanAddr::from("john"), coin(30, "ustake")),
+                (HumanAddr::from("mary"), coin(5, "ustake")),
+            ]
         );
     }
 
-    const DEFAULT_VALIDATOR: &str = "default-validator";
+    #[test]
+    fn sweep_without_delegators_is_noop() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        assert!(query_all_delegators(&deps).unwrap().is_empty());
 
-    fn default_init(tax_percent: u64, min_withdrawal: u128) -> InitMsg {
-        InitMsg {
-            name: "Cool Derivative".to_string(),
-            symbol: "DRV".to_string(),
-            decimals: 9,
-            validator: HumanAddr::from(DEFAULT_VALIDATOR),
-            exit_tax: Decimal::percent(tax_percent),
-            min_withdrawal: Uint128(min_withdrawal),
-        }
-    }
+        let msg = HandleMsg::ProcessExpired { limit: None };
+        let res = handle(&mut deps, mock_env(), mock_info("keeper", &[]), msg).unwrap();
+        assert_eq!(res.messages.len(), 0);
+        assert_eq!(res.attributes[1], attr("processed", 0));
 
-    fn get_balance<S: Storage, A: Api, Q: Querier, U: Into<HumanAddr>>(
-        deps: &Extern<S, A, Q>,
-        addr: U,
-    ) -> Uint128 {
-        query_balance(&deps, addr.into()).unwrap().balance
+        // nothing to withdraw either, only the bond callback goes out
+        let info = mock_info("keeper", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::Reinvest {}).unwrap();
+        assert_eq!(res.messages.len(), 1);
     }
 
-    fn get_claims<S: Storage, A: Api, Q: Querier, U: Into<HumanAddr>>(
-        deps: &Extern<S, A, Q>,
-        addr: U,
-    ) -> Uint128 {
-        query_claims(&deps, addr.into()).unwrap().claims
+    #[test]
+    fn queries_survive_missing_supply() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        total_supply(&mut deps.storage).remove();
+
+        let invest = query_investment(&deps).unwrap();
+        assert_eq!(invest.token_supply, Uint128(0));
+        assert_eq!(invest.staked_tokens, coin(0, "ustake"));
+        let bob = HumanAddr::from("bob");
+        let redeemable = query_redeemable(&deps, mock_env(), bob.clone()).unwrap();
+        assert_eq!(redeemable.redeemable, coin(0, "ustake"));
+        let preview = query_exit_tax_preview(&deps, mock_env(), bob, Uint128(0)).unwrap();
+        assert_eq!(preview.gross_native, Uint128(0));
+
+        // accounting still refuses to run without it
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap_err();
     }
 
     #[test]
-    fn initialization_with_missing_validator() {
+    fn validator_active_follows_the_set() {
         let mut deps = mock_dependencies(&[]);
-        deps.querier
-            .update_staking("ustake", &[sample_validator("john")], &[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
 
-        let creator = HumanAddr::from("creator");
-        let msg = InitMsg {
-            name: "Cool Derivative".to_string(),
-            symbol: "DRV".to_string(),
-            decimals: 9,
-            validator: HumanAddr::from("my-validator"),
-            exit_tax: Decimal::percent(2),
-            min_withdrawal: Uint128(50),
-        };
-        let info = mock_info(&creator, &[]);
+        let res = query_validator_active(&deps).unwrap();
+        assert_eq!(res.validator, HumanAddr::from(DEFAULT_VALIDATOR));
+        assert!(res.active);
 
-        // make sure we can init with this
-        let res = init(&mut deps, mock_env(), info, msg.clone());
-        match res.unwrap_err() {
-            StdError::GenericErr { msg, .. } => {
-                assert_eq!(msg, "my-validator is not in the current validator set")
-            }
-            _ => panic!("expected unregistered validator error"),
-        }
+        // the validator left the set
+        deps.querier
+            .update_staking("ustake", &[sample_validator("other")], &[]);
+        assert!(!query_validator_active(&deps).unwrap().active);
     }
 
     #[test]
-    fn proper_initialization() {
+    fn validator_history_keeps_past_validators() {
         let mut deps = mock_dependencies(&[]);
         deps.querier.update_staking(
             "ustake",
             &[
-                sample_validator("john"),
-                sample_validator("mary"),
-                sample_validator("my-validator"),
+                custom_sample_validator(DEFAULT_VALIDATOR, 3, 10, 1),
+                custom_sample_validator("cheap", 1, 10, 1),
             ],
             &[],
         );
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
 
-        let creator = HumanAddr::from("creator");
-        let msg = InitMsg {
-            name: "Cool Derivative".to_string(),
-            symbol: "DRV".to_string(),
-            decimals: 0,
-            validator: HumanAddr::from("my-validator"),
-            exit_tax: Decimal::percent(2),
-            min_withdrawal: Uint128(50),
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond {
+            validator: Some(HumanAddr::from(DEFAULT_VALIDATOR)),
         };
-        let info = mock_info(&creator, &[]);
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        let history = query_validator_history(&deps).unwrap().validators;
+        assert_eq!(history.len(), 1);
 
-        // make sure we can init with this
-        let res = init(&mut deps, mock_env(), info, msg.clone()).unwrap();
-        assert_eq!(0, res.messages.len());
+        // the sweep moves all of bob's stake to the cheaper validator
+        seed_reward(&mut deps, "bob", 100);
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(100, "ustake"));
+        let mut env = mock_env();
+        env.block.height += EXPIRY_BLOCKS + 1;
+        let info = mock_info("keeper", &[]);
+        let msg = HandleMsg::ProcessExpired { limit: None };
+        handle(&mut deps, env.clone(), info, msg).unwrap();
 
-        // token info is proper
-        let token = query_token_info(&deps).unwrap();
-        assert_eq!(&token.name, &msg.name);
-        assert_eq!(&token.symbol, &msg.symbol);
-        assert_eq!(token.decimals, msg.decimals);
+        let history = query_validator_history(&deps).unwrap().validators;
+        assert_eq!(
+            history,
+            vec![
+                ValidatorHistoryEntry {
+                    validator: HumanAddr::from("cheap"),
+                    since: env.block.height,
+                },
+                ValidatorHistoryEntry {
+                    validator: HumanAddr::from(DEFAULT_VALIDATOR),
+                    since: mock_env().block.height,
+                },
+            ]
+        );
+    }
 
-        // no balance
-        assert_eq!(get_balance(&deps, &creator), Uint128(0));
-        // no claims
-        assert_eq!(get_claims(&deps, &creator), Uint128(0));
+    #[test]
+    fn validator_stats_show_drift() {
+        let mut deps = mock_dependencies(&[]);
+        let validators = [sample_validator("john"), sample_validator("mary")];
+        deps.querier.update_staking("ustake", &validators, &[]);
+        let mut msg = default_init(2, 50);
+        msg.validator = HumanAddr::from("john");
+        msg.validators = Some(vec![HumanAddr::from("john"), HumanAddr::from("mary")]);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
 
-        // investment info correct
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond {
+            validator: Some(HumanAddr::from("john")),
+        };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        let delegations = [sample_delegation("john", coin(1000, "ustake"))];
+        deps.querier
+            .update_staking("ustake", &validators, &delegations);
+
+        let stats = query_validator_stats(&deps, mock_env()).unwrap();
+        assert_eq!(
+            stats,
+            ValidatorStatsResponse {
+                configured: 2,
+                active: 1,
+                total_bonded: Uint128(1000),
+            }
+        );
+    }
+
+    #[test]
+    fn init_with_multiple_validators() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier.update_staking(
+            "ustake",
+            &[sample_validator("john"), sample_validator("mary")],
+            &[],
+        );
+
+        let mut msg = default_init(2, 50);
+        msg.validator = HumanAddr::from("john");
+        msg.validators = Some(vec![
+            HumanAddr::from("john"),
+            HumanAddr::from("jane"),
+            HumanAddr::from("mary"),
+        ]);
+        let info = mock_info("creator", &[]);
+        match init(&mut deps, mock_env(), info, msg).unwrap_err() {
+            StdError::GenericErr { msg, .. } => {
+                assert_eq!(msg, "jane is not in the current validator set")
+            }
+            err => panic!("Unexpected error: {:?}", err),
+        }
+
+        let mut msg = default_init(2, 50);
+        msg.validator = HumanAddr::from("john");
+        msg.validators = Some(vec![]);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap_err();
+
+        let mut msg = default_init(2, 50);
+        msg.validator = HumanAddr::from("john");
+        msg.validators = Some(vec![HumanAddr::from("john"), HumanAddr::from("mary")]);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
         let invest = query_investment(&deps).unwrap();
-        assert_eq!(&invest.owner, &creator);
-        assert_eq!(&invest.validator, &msg.validator);
-        assert_eq!(invest.exit_tax, msg.exit_tax);
-        assert_eq!(invest.min_withdrawal, msg.min_withdrawal);
+        assert_eq!(invest.validators.len(), 2);
+
+        let targets = query_target_validators(&deps).unwrap();
+        assert_eq!(
+            targets.validators,
+            vec![
+                (HumanAddr::from("john"), Decimal::percent(50)),
+                (HumanAddr::from("mary"), Decimal::percent(50)),
+            ]
+        );
+    }
+
+    #[test]
+    fn target_weights_add_up_to_one() {
+        let mut deps = mock_dependencies(&[]);
+        let names = ["john", "mary", "paul"];
+        let validators: Vec<_> = names.iter().map(|name| sample_validator(*name)).collect();
+        deps.querier.update_staking("ustake", &validators, &[]);
+
+        let mut msg = default_init(2, 50);
+        msg.validator = HumanAddr::from("john");
+        msg.validators = Some(names.iter().map(|name| HumanAddr::from(*name)).collect());
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+
+        // a third is rounded down, the last validator takes the remainder
+        let third = Decimal::from_str("0.333333333333333333").unwrap();
+        let targets = query_target_validators(&deps).unwrap().validators;
+        assert_eq!(
+            targets,
+            vec![
+                (HumanAddr::from("john"), third),
+                (HumanAddr::from("mary"), third),
+                (
+                    HumanAddr::from("paul"),
+                    Decimal::from_str("0.333333333333333334").unwrap()
+                ),
+            ]
+        );
+        let total = targets
+            .iter()
+            .fold(Decimal::zero(), |total, (_, weight)| total + *weight);
+        assert_eq!(total, Decimal::one());
+    }
+
+    #[test]
+    fn rebalance_moves_stake_to_targets() {
+        let mut deps = mock_dependencies(&[]);
+        let validators = [
+            sample_validator("john"),
+            sample_validator("mary"),
+            sample_validator("old"),
+        ];
+        deps.querier.update_staking("ustake", &validators, &[]);
+        let mut msg = default_init(2, 50);
+        msg.validator = HumanAddr::from("john");
+        msg.validators = Some(vec![HumanAddr::from("john"), HumanAddr::from("mary")]);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+
+        // 1200 in total, so john and mary should hold 600 each and old nothing
+        deps.querier.update_staking(
+            "ustake",
+            &validators,
+            &[
+                sample_delegation("john", coin(900, "ustake")),
+                sample_delegation("mary", coin(100, "ustake")),
+                sample_delegation("old", coin(200, "ustake")),
+            ],
+        );
+        let info = mock_info("anyone", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::Rebalance {}).unwrap();
+        assert_eq!(
+            res.messages,
+            vec![
+                CosmosMsg::Staking(StakingMsg::Redelegate {
+                    src_validator: HumanAddr::from("john"),
+                    dst_validator: HumanAddr::from("mary"),
+                    amount: coin(300, "ustake"),
+                }),
+                CosmosMsg::Staking(StakingMsg::Redelegate {
+                    src_validator: HumanAddr::from("old"),
+                    dst_validator: HumanAddr::from("mary"),
+                    amount: coin(200, "ustake"),
+                }),
+            ]
+        );
 
-        assert_eq!(invest.token_supply, Uint128(0));
-        assert_eq!(invest.staked_tokens, coin(0, "ustake"));
-        assert_eq!(invest.nominal_value, Decimal::one());
+        // a skew below min_withdrawal is dust
+        deps.querier.update_staking(
+            "ustake",
+            &validators,
+            &[
+                sample_delegation("john", coin(620, "ustake")),
+                sample_delegation("mary", coin(580, "ustake")),
+            ],
+        );
+        let info = mock_info("anyone", &[]);
+        let res = handle(&mut deps, mock_env(), info, HandleMsg::Rebalance {}).unwrap();
+        assert!(res.messages.is_empty());
     }
 
     #[test]
-    fn bonding_issues_tokens() {
+    fn redeemable_applies_ratio_and_tax() {
         let mut deps = mock_dependencies(&[]);
         set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
 
-        let creator = HumanAddr::from("creator");
-        let init_msg = default_init(2, 50);
-        let info = mock_info(&creator, &[]);
-
-        // make sure we can init with this
-        let res = init(&mut deps, mock_env(), info, init_msg).unwrap();
-        assert_eq!(0, res.messages.len());
-
-        // let's bond some tokens now
         let bob = HumanAddr::from("bob");
-        let bond_msg = HandleMsg::Bond {};
-        let info = mock_info(&bob, &[coin(10, "random"), coin(1000, "ustake")]);
+        let res = query_redeemable(&deps, mock_env(), bob.clone()).unwrap();
+        assert_eq!(res.redeemable, coin(0, "ustake"));
 
-        // try to bond and make sure we trigger delegation
-        let res = handle(&mut deps, mock_env(), info, bond_msg).unwrap();
-        assert_eq!(1, res.messages.len());
-        let delegate = &res.messages[0];
-        match delegate {
-            CosmosMsg::Staking(StakingMsg::Delegate { validator, amount }) => {
-                assert_eq!(validator.as_str(), DEFAULT_VALIDATOR);
-                assert_eq!(amount, &coin(1000, "ustake"));
-            }
-            _ => panic!("Unexpected message: {:?}", delegate),
-        }
+        let info = mock_info(&bob, &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
 
-        // bob got 1000 DRV for 1000 stake at a 1.0 ratio
-        assert_eq!(get_balance(&deps, &bob), Uint128(1000));
+        // a reinvestment pushes the ratio to 1.5
+        set_delegation(&mut deps.querier, 1000, "ustake");
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, coins(500, "ustake"));
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap();
 
-        // investment info correct (updated supply)
-        let invest = query_investment(&deps).unwrap();
-        assert_eq!(invest.token_supply, Uint128(1000));
-        assert_eq!(invest.staked_tokens, coin(1000, "ustake"));
-        assert_eq!(invest.nominal_value, Decimal::one());
+        // 1000 derivatives are worth 1500, minus the 2% exit tax
+        let res = query_redeemable(&deps, mock_env(), bob).unwrap();
+        assert_eq!(res.redeemable, coin(1470, "ustake"));
     }
 
     #[test]
-    fn rebonding_changes_pricing() {
+    fn unrealized_gain_follows_ratio() {
         let mut deps = mock_dependencies(&[]);
         set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
 
-        let creator = HumanAddr::from("creator");
-        let init_msg = default_init(2, 50);
-        let info = mock_info(&creator, &[]);
-
-        // make sure we can init with this
-        let res = init(&mut deps, mock_env(), info, init_msg).unwrap();
-        assert_eq!(0, res.messages.len());
-
-        // let's bond some tokens now
+        // bob bonds at a 1.0 ratio
         let bob = HumanAddr::from("bob");
-        let bond_msg = HandleMsg::Bond {};
-        let info = mock_info(&bob, &[coin(10, "random"), coin(1000, "ustake")]);
-        let res = handle(&mut deps, mock_env(), info, bond_msg).unwrap();
-        assert_eq!(1, res.messages.len());
+        let info = mock_info(&bob, &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond { validator: None };
+        handle(&mut deps, mock_env(), info, msg).unwrap();
+        let res = query_unrealized_gain(&deps, bob.clone()).unwrap();
+        assert_eq!(res.cost_basis, Uint128(1000));
+        assert_eq!(res.gain, Uint128(0));
 
-        // update the querier with new bond
+        // a reinvestment pushes the ratio to 1.5
         set_delegation(&mut deps.querier, 1000, "ustake");
-
-        // fake a reinvestment (this must be sent by the contract itself)
-        let rebond_msg = HandleMsg::_BondAllTokens {};
-        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
         deps.querier
             .update_balance(MOCK_CONTRACT_ADDR, coins(500, "ustake"));
-        let _ = handle(&mut deps, mock_env(), info, rebond_msg).unwrap();
+        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
+        handle(&mut deps, mock_env(), info, HandleMsg::_BondAllTokens {}).unwrap();
 
-        // update the querier with new bond
-        set_delegation(&mut deps.querier, 1500, "ustake");
+        let res = query_unrealized_gain(&deps, bob).unwrap();
+        assert_eq!(res.value, Uint128(1500));
+        assert_eq!(res.cost_basis, Uint128(1000));
+        assert_eq!(res.gain, Uint128(500));
+    }
 
-        // we should now see 1000 issues and 1500 bonded (and a price of 1.5)
-        let invest = query_investment(&deps).unwrap();
-        assert_eq!(invest.token_supply, Uint128(1000));
-        assert_eq!(invest.staked_tokens, coin(1500, "ustake"));
-        let ratio = Decimal::from_str("1.5").unwrap();
-        assert_eq!(invest.nominal_value, ratio);
+    #[test]
+    fn allowance_expires_at_height() {
+        let mut deps = mock_dependencies(&[]);
+        set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
 
-        // we bond some other tokens and get a different issuance price (maintaining the ratio)
-        let alice = HumanAddr::from("alice");
-        let bond_msg = HandleMsg::Bond {};
-        let info = mock_info(&alice, &[coin(3000, "ustake")]);
-        let res = handle(&mut deps, mock_env(), info, bond_msg).unwrap();
-        assert_eq!(1, res.messages.len());
+        let bob = HumanAddr::from("bob");
+        let carl = HumanAddr::from("carl");
+        let bob_raw = deps.api.canonical_address(&bob).unwrap();
+        balances(&mut deps.storage)
+            .save(bob_raw.as_slice(), &Uint128(1000))
+            .unwrap();
 
-        // update the querier with new bond
-        set_delegation(&mut deps.querier, 3000, "ustake");
+        let expires = Expiration::AtHeight(12_400);
+        let msg = HandleMsg::IncreaseAllowance {
+            spender: carl.clone(),
+            amount: Uint128(500),
+            expires: Some(expires),
+        };
+        handle(&mut deps, mock_env(), mock_info(&bob, &[]), msg).unwrap();
+        let allow = query_allowance(&deps, bob.clone(), carl.clone()).unwrap();
+        assert_eq!(allow.allowance, Uint128(500));
+        assert_eq!(allow.expires, expires);
 
-        // alice should have gotten 2000 DRV for the 3000 stake, keeping the ratio at 1.5
-        assert_eq!(get_balance(&deps, &alice), Uint128(2000));
+        // carl can spend before the expiration height
+        let msg = HandleMsg::TransferFrom {
+            owner: bob.clone(),
+            recipient: carl.clone(),
+            amount: Uint128(100),
+        };
+        handle(&mut deps, mock_env(), mock_info(&carl, &[]), msg).unwrap();
+        assert_eq!(get_balance(&deps, &bob), Uint128(900));
+        assert_eq!(get_balance(&deps, &carl), Uint128(100));
 
-        let invest = query_investment(&deps).unwrap();
-        assert_eq!(invest.token_supply, Uint128(3000));
-        assert_eq!(invest.staked_tokens, coin(4500, "ustake"));
-        assert_eq!(invest.nominal_value, ratio);
+        // but not once it is reached
+        let mut env = mock_env();
+        env.block.height = 12_400;
+        let msg = HandleMsg::TransferFrom {
+            owner: bob.clone(),
+            recipient: carl.clone(),
+            amount: Uint128(100),
+        };
+        let res = handle(&mut deps, env, mock_info(&carl, &[]), msg);
+        match res.unwrap_err() {
+            StakingError::Std {
+                original: StdError::Underflow { .. },
+            } => {}
+            err => panic!("Unexpected error: {:?}", err),
+        }
+        assert_eq!(get_balance(&deps, &bob), Uint128(900));
     }
 
     #[test]
-    fn bonding_fails_with_wrong_denom() {
+    fn decrease_allowance_removes_when_exhausted() {
         let mut deps = mock_dependencies(&[]);
         set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
 
-        let creator = HumanAddr::from("creator");
-        let init_msg = default_init(2, 50);
-        let info = mock_info(&creator, &[]);
-
-        // make sure we can init with this
-        let res = init(&mut deps, mock_env(), info, init_msg).unwrap();
-        assert_eq!(0, res.messages.len());
-
-        // let's bond some tokens now
         let bob = HumanAddr::from("bob");
-        let bond_msg = HandleMsg::Bond {};
-        let info = mock_info(&bob, &[coin(500, "photon")]);
+        let carl = HumanAddr::from("carl");
+        let msg = HandleMsg::IncreaseAllowance {
+            spender: carl.clone(),
+            amount: Uint128(500),
+            expires: None,
+        };
+        handle(&mut deps, mock_env(), mock_info(&bob, &[]), msg).unwrap();
 
-        // try to bond and make sure we trigger delegation
-        let res = handle(&mut deps, mock_env(), info, bond_msg);
-        match res.unwrap_err() {
-            StakingError::Std {
-                original: StdError::GenericErr { msg, .. },
-            } => assert_eq!(msg, "No ustake tokens sent"),
-            err => panic!("Unexpected error: {:?}", err),
+        let msg = HandleMsg::DecreaseAllowance {
+            spender: carl.clone(),
+            amount: Uint128(200),
+            expires: Some(Expiration::AtTime(1_600_000_000)),
+        };
+        handle(&mut deps, mock_env(), mock_info(&bob, &[]), msg).unwrap();
+        let allow = query_allowance(&deps, bob.clone(), carl.clone()).unwrap();
+        assert_eq!(allow.allowance, Uint128(300));
+        assert_eq!(allow.expires, Expiration::AtTime(1_600_000_000));
+
+        let msg = HandleMsg::DecreaseAllowance {
+            spender: carl.clone(),
+            amount: Uint128(1000),
+            expires: None,
         };
+        handle(&mut deps, mock_env(), mock_info(&bob, &[]), msg).unwrap();
+        let allow = query_allowance(&deps, bob, carl).unwrap();
+        assert_eq!(allow, AllowanceResponse::default());
     }
 
     #[test]
-    fn unbonding_maintains_price_ratio() {
+    fn all_allowances_pages_through_spenders() {
         let mut deps = mock_dependencies(&[]);
         set_validator(&mut deps.querier);
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
 
-        let creator = HumanAddr::from("creator");
-        let init_msg = default_init(10, 50);
-        let info = mock_info(&creator, &[]);
+        let bob = HumanAddr::from("bob");
+        for (spender, amount) in &[("carl", 100u128), ("dave", 200)] {
+            let msg = HandleMsg::IncreaseAllowance {
+                spender: HumanAddr::from(*spender),
+                amount: Uint128(*amount),
+                expires: None,
+            };
+            handle(&mut deps, mock_env(), mock_info(&bob, &[]), msg).unwrap();
+        }
 
-        // make sure we can init with this
-        let res = init(&mut deps, mock_env(), info, init_msg).unwrap();
-        assert_eq!(0, res.messages.len());
+        let all = query_all_allowances(&deps, bob.clone(), None, None).unwrap();
+        assert_eq!(all.allowances.len(), 2);
 
-        // let's bond some tokens now
-        let bob = HumanAddr::from("bob");
-        let bond_msg = HandleMsg::Bond {};
-        let info = mock_info(&bob, &[coin(10, "random"), coin(1000, "ustake")]);
-        let res = handle(&mut deps, mock_env(), info, bond_msg).unwrap();
-        assert_eq!(1, res.messages.len());
+        // walk the same set one page at a time
+        let first = query_all_allowances(&deps, bob.clone(), None, Some(1)).unwrap();
+        assert_eq!(first.allowances.len(), 1);
+        assert_eq!(first.allowances[0], all.allowances[0]);
+        let start_after = Some(first.allowances[0].spender.clone());
+        let second = query_all_allowances(&deps, bob.clone(), start_after, Some(1)).unwrap();
+        assert_eq!(second.allowances, vec![all.allowances[1].clone()]);
+        let start_after = Some(second.allowances[0].spender.clone());
+        let third = query_all_allowances(&deps, bob, start_after, Some(1)).unwrap();
+        assert!(third.allowances.is_empty());
 
-        // update the querier with new bond
-        set_delegation(&mut deps.querier, 1000, "ustake");
+        let mut spenders: Vec<_> = all
+            .allowances
+            .iter()
+            .map(|a| (a.spender.to_string(), a.allowance))
+            .collect();
+        spenders.sort();
+        assert_eq!(
+            spenders,
+            vec![
+                ("carl".to_string(), Uint128(100)),
+                ("dave".to_string(), Uint128(200))
+            ]
+        );
+    }
 
-        // fake a reinvestment (this must be sent by the contract itself)
-        // after this, we see 1000 issues and 1500 bonded (and a price of 1.5)
-        let rebond_msg = HandleMsg::_BondAllTokens {};
-        let info = mock_info(MOCK_CONTRACT_ADDR, &[]);
-        deps.querier
-            .update_balance(MOCK_CONTRACT_ADDR, coins(500, "ustake"));
-        let _ = handle(&mut deps, mock_env(), info, rebond_msg).unwrap();
+    #[test]
+    fn select_best_validator() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier.update_staking(
+            "ustake",
+            &[
+                custom_sample_validator("john", 1, 10, 5),
+                custom_sample_validator("mary", 2, 10, 1),
+                custom_sample_validator("my-validator", 1, 10, 3),
+            ],
+            &[],
+        );
+        let validator = select_validator(&mut deps).unwrap();
 
-        // update the querier with new bond, lower balance
-        set_delegation(&mut deps.querier, 1500, "ustake");
-        deps.querier.update_balance(MOCK_CONTRACT_ADDR, vec![]);
+        assert_eq!(validator, custom_sample_validator("my-validator", 1, 10, 3));
+    }
 
-        // creator now tries to unbond these tokens - this must fail
-        let unbond_msg = HandleMsg::Unbond {
-            amount: Uint128(600),
+    #[test]
+    fn ranked_validators_follow_selection() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier.update_staking(
+            "ustake",
+            &[
+                custom_sample_validator("john", 1, 10, 5),
+                custom_sample_validator("mary", 2, 10, 1),
+                custom_sample_validator("my-validator", 1, 10, 3),
+            ],
+            &[],
+        );
+        let mut msg = default_init(2, 50);
+        msg.validator = HumanAddr::from("john");
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
+
+        // the head of the ranking is what select_validator picks
+        let ranked = query_ranked_validators(&deps, None).unwrap().validators;
+        let names: Vec<&str> = ranked.iter().map(|v| v.address.as_str()).collect();
+        assert_eq!(names, vec!["my-validator", "john", "mary"]);
+        assert_eq!(select_validator(&mut deps).unwrap(), ranked[0]);
+
+        let ranked = query_ranked_validators(&deps, Some(2)).unwrap().validators;
+        assert_eq!(ranked.len(), 2);
+    }
+
+    #[test]
+    fn deny_list_skips_validator() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier.update_staking(
+            "ustake",
+            &[
+                custom_sample_validator(DEFAULT_VALIDATOR, 3, 10, 1),
+                custom_sample_validator("cheap", 1, 10, 1),
+            ],
+            &[],
+        );
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, default_init(2, 50)).unwrap();
+        let best = select_validator(&mut deps).unwrap();
+        assert_eq!(best.address.as_str(), "cheap");
+
+        let msg = HandleMsg::SetDenyList {
+            validators: vec![HumanAddr::from("cheap")],
         };
-        let info = mock_info(&creator, &[]);
-        let res = handle(&mut deps, mock_env(), info, unbond_msg);
-        match res.unwrap_err() {
-            StakingError::Std {
-                original: StdError::Underflow { .. },
-            } => {}
-            err => panic!("Unexpected error: {:?}", err),
-        }
+        handle(&mut deps, mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let best = select_validator(&mut deps).unwrap();
+        assert_eq!(best.address.as_str(), DEFAULT_VALIDATOR);
 
-        // bob unbonds 600 tokens at 10% tax...
-        // 60 are taken and send to the owner
-        // 540 are unbonded in exchange for 540 * 1.5 = 810 native tokens
-        let unbond_msg = HandleMsg::Unbond {
-            amount: Uint128(600),
+        // an explicit bond can't pick a denied validator either
+        let info = mock_info("bob", &[coin(1000, "ustake")]);
+        let msg = HandleMsg::Bond {
+            validator: Some(HumanAddr::from("cheap")),
         };
-        let owner_cut = Uint128(60);
-        let bobs_claim = Uint128(810);
-        let bobs_balance = Uint128(400);
-        let info = mock_info(&bob, &[]);
-        let res = handle(&mut deps, mock_env(), info, unbond_msg).unwrap();
-        assert_eq!(1, res.messages.len());
-        let delegate = &res.messages[0];
-        match delegate {
-            CosmosMsg::Staking(StakingMsg::Undelegate { validator, amount }) => {
-                assert_eq!(validator.as_str(), DEFAULT_VALIDATOR);
-                assert_eq!(amount, &coin(bobs_claim.u128(), "ustake"));
-            }
-            _ => panic!("Unexpected message: {:?}", delegate),
-        }
+        handle(&mut deps, mock_env(), info, msg).unwrap_err();
 
-        // update the querier with new bond, lower balance
-        set_delegation(&mut deps.querier, 690, "ustake");
+        let msg = HandleMsg::SetDenyList {
+            validators: vec![HumanAddr::from("cheap"), HumanAddr::from(DEFAULT_VALIDATOR)],
+        };
+        handle(&mut deps, mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let err = select_validator(&mut deps).unwrap_err();
+        assert!(err.to_string().contains("deny list"));
+    }
 
-        // check balances
-        assert_eq!(get_balance(&deps, &bob), bobs_balance);
-        assert_eq!(get_balance(&deps, &creator), owner_cut);
-        // proper claims
-        assert_eq!(get_claims(&deps, &bob), bobs_claim);
+    #[test]
+    fn select_within_commission_band() {
+        let mut deps = mock_dependencies(&[]);
+        deps.querier.update_staking(
+            "ustake",
+            &[
+                custom_sample_validator("john", 0, 10, 5),
+                custom_sample_validator("mary", 2, 10, 1),
+                custom_sample_validator("my-validator", 3, 10, 3),
+                custom_sample_validator("greedy", 20, 30, 3),
+            ],
+            &[],
+        );
+        let mut msg = default_init(2, 50);
+        msg.validator = HumanAddr::from("mary");
+        msg.min_commission = Some(Decimal::percent(1));
+        msg.max_commission = Some(Decimal::percent(15));
+        let info = mock_info("creator", &[]);
+        init(&mut deps, mock_env(), info, msg).unwrap();
 
-        // supplies updated, ratio the same (1.5)
-        let ratio = Decimal::from_str("1.5").unwrap();
+        // john is cheapest but suspiciously so, mary is the cheapest in the band
+        let validator = select_validator(&mut deps).unwrap();
+        assert_eq!(validator.address.as_str(), "mary");
 
-        let invest = query_investment(&deps).unwrap();
-        assert_eq!(invest.token_supply, bobs_balance + owner_cut);
-        assert_eq!(invest.staked_tokens, coin(690, "ustake")); // 1500 - 810
-        assert_eq!(invest.nominal_value, ratio);
+        // nobody qualifies once the band is empty
+        let mut invest = invest_info_read(&deps.storage).load().unwrap();
+        invest.min_commission = Decimal::percent(50);
+        invest.max_commission = Decimal::percent(60);
+        invest_info(&mut deps.storage).save(&invest).unwrap();
+        select_validator(&mut deps).unwrap_err();
     }
 
     #[test]
-    fn select_best_validator() {
+    fn select_highest_voting_power() {
         let mut deps = mock_dependencies(&[]);
         deps.querier.update_staking(
             "ustake",
@@ -942,9 +8303,38 @@ mod tests {
             ],
             &[],
         );
+        let creator = HumanAddr::from("creator");
+        let mut msg = default_init(2, 50);
+        msg.validator = HumanAddr::from("john");
+        msg.selection_strategy = Some(SelectionStrategy::HighestVotingPower);
+        init(&mut deps, mock_env(), mock_info(&creator, &[]), msg).unwrap();
+
+        // no power data yet, so the lowest commission wins
         let validator = select_validator(&mut deps).unwrap();
+        assert_eq!(validator.address.as_str(), "my-validator");
 
-        assert_eq!(validator, custom_sample_validator("my-validator", 1, 10, 3));
+        let powers = vec![
+            ValidatorPower {
+                validator: HumanAddr::from("john"),
+                power: Uint128(500),
+            },
+            ValidatorPower {
+                validator: HumanAddr::from("mary"),
+                power: Uint128(900),
+            },
+        ];
+        let msg = HandleMsg::SetValidatorPowers { powers };
+
+        // only the owner can report powers
+        let res = handle(&mut deps, mock_env(), mock_info("bob", &[]), msg.clone());
+        match res.unwrap_err() {
+            StakingError::Unauthorized { .. } => {}
+            err => panic!("Unexpected error: {:?}", err),
+        }
+        handle(&mut deps, mock_env(), mock_info(&creator, &[]), msg).unwrap();
+
+        let validator = select_validator(&mut deps).unwrap();
+        assert_eq!(validator.address.as_str(), "mary");
     }
 
     #[test]
@@ -9,6 +9,8 @@ pub enum StakingError {
     Std { original: StdError },
     #[snafu(display("Unauthorized"))]
     Unauthorized { backtrace: Option<snafu::Backtrace> },
+    #[snafu(display("Cannot register more than {} delegators", max))]
+    TooManyDelegators { max: u32 },
 }
 
 impl From<StdError> for StakingError {
@@ -1,7 +1,10 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Coin, Decimal, HumanAddr, Uint128};
+use cosmwasm_std::{
+    to_binary, Binary, BlockInfo, Coin, CosmosMsg, Decimal, HumanAddr, StdResult, Uint128,
+    Validator, WasmMsg,
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InitMsg {
@@ -16,6 +19,9 @@ pub struct InitMsg {
 
     /// This is the validator that all tokens will be bonded to
     pub validator: HumanAddr,
+    /// every validator the pool is set up to use, for multi-validator pools.
+    /// Defaults to just `validator`
+    pub validators: Option<Vec<HumanAddr>>,
 
     /// this is how much the owner takes as a cut when someone unbonds
     /// TODO
@@ -23,6 +29,74 @@ pub struct InitMsg {
     /// This is the minimum amount we will pull out to reinvest, as well as a minumum
     /// that can be unbonded (to avoid needless staking tx)
     pub min_withdrawal: Uint128,
+    /// how bonds pick their validator (defaults to lowest commission)
+    pub selection_strategy: Option<SelectionStrategy>,
+    /// caps how many addresses can register as delegators (unbounded if unset)
+    pub max_delegators: Option<u32>,
+    /// pay rewards out as claims instead of compounding them
+    pub distribute_rewards: bool,
+    /// share of the rewards that is compounded, the rest is paid out as claims
+    /// (defaults to 1). Ignored with distribute_rewards
+    pub compound_ratio: Option<Decimal>,
+    /// only reinvest once free rewards reach this share of the bonded tokens
+    pub reinvest_min_ratio: Option<Decimal>,
+    /// how UIs should show bond_denom, e.g. STAKE for ustake
+    pub display_denom: Option<String>,
+    /// decimal places between bond_denom and display_denom, e.g. 6 for ustake
+    pub display_exponent: Option<u32>,
+    /// who receives the exit tax and in which proportion; weights must add up to 1.
+    /// Defaults to the contract creator taking all of it
+    pub fee_recipients: Option<Vec<(HumanAddr, Decimal)>>,
+    /// blocks a delegation must age before reinvest may redelegate it (0 if unset)
+    pub min_age_before_redelegate: Option<u64>,
+    /// blocks a delegator must hold after its last delegation before it can unbond
+    /// (0 if unset)
+    pub min_hold_blocks: Option<u64>,
+    /// blocks per year, from which block windows are derived (defaults to 5s blocks)
+    pub blocks_per_year: Option<u64>,
+    /// exit tax by holding time, as (blocks held, rate) with ascending thresholds: the
+    /// first tier whose threshold the holding time is below applies, exit_tax after that
+    pub tax_tiers: Option<Vec<(u64, Decimal)>>,
+    /// whether Reinvest calls _BondAllTokens itself (defaults to true). When false the
+    /// owner bonds the withdrawn rewards with a separate _BondAllTokens call
+    pub auto_callback: Option<bool>,
+    /// validators that are never delegated to (defaults to none)
+    pub deny_list: Option<Vec<HumanAddr>>,
+    /// whether BondStray may bond bond_denom sent to the contract outside of Bond
+    /// (defaults to false)
+    pub bond_stray: Option<bool>,
+    /// how many balance snapshots to keep per holder for BalanceAt. Snapshots are off
+    /// when unset
+    pub snapshot_retention: Option<u32>,
+    /// fee charged instead of the exit tax to unbond without waiting, if offered
+    pub instant_unbond_fee: Option<Decimal>,
+    /// share of the withdrawn rewards set aside for the fee recipients (defaults to 0)
+    pub reward_fee: Option<Decimal>,
+    /// the largest share of the total stake a single validator may hold; bonds spill
+    /// over to the next best validator beyond it (defaults to 1, no cap)
+    pub max_validator_share: Option<Decimal>,
+    /// validators charging less commission than this are never picked (defaults to 0)
+    pub min_commission: Option<Decimal>,
+    /// validators charging more commission than this are never picked (defaults to 1)
+    pub max_commission: Option<Decimal>,
+}
+
+/// SelectionStrategy decides which validator new stake is delegated to
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionStrategy {
+    /// lowest commission, ties broken by lowest max change rate
+    LowestCommission,
+    /// highest reported voting power, falling back to LowestCommission
+    /// when no power data is available
+    HighestVotingPower,
+}
+
+/// ValidatorPower is a voting power snapshot for a validator
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ValidatorPower {
+    pub validator: HumanAddr,
+    pub power: Uint128,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -33,24 +107,218 @@ pub enum HandleMsg {
         recipient: HumanAddr,
         amount: Uint128,
     },
-    /// Bond will bond all staking tokens sent with the message and release derivative tokens
-    Bond {},
-    /// Unbond will "burn" the given amount of derivative tokens and send the unbonded
-    /// staking tokens to the message sender (after exit tax is deducted)
-    Unbond {},
+    /// TransferWithNonce is a Transfer that only succeeds with the sender's next nonce,
+    /// starting at 0, so a relayed transfer can't be replayed
+    TransferWithNonce {
+        recipient: HumanAddr,
+        amount: Uint128,
+        nonce: u64,
+    },
+    /// Send moves the derivative token to a contract and triggers its `Receive` handler
+    /// with the given msg (CW20 compatible)
+    Send {
+        contract: HumanAddr,
+        amount: Uint128,
+        msg: Option<Binary>,
+    },
+    /// IncreaseAllowance lets `spender` move up to `amount` more of the sender's tokens,
+    /// optionally (re)setting when the allowance expires
+    IncreaseAllowance {
+        spender: HumanAddr,
+        amount: Uint128,
+        expires: Option<Expiration>,
+    },
+    /// DecreaseAllowance lowers the allowance of `spender`, removing it once it reaches zero
+    DecreaseAllowance {
+        spender: HumanAddr,
+        amount: Uint128,
+        expires: Option<Expiration>,
+    },
+    /// TransferFrom moves tokens of `owner` to `recipient`, spending the sender's allowance
+    TransferFrom {
+        owner: HumanAddr,
+        recipient: HumanAddr,
+        amount: Uint128,
+    },
+    /// Bond will bond all staking tokens sent with the message and release derivative tokens.
+    /// `validator` overrides the automatic pick, it must be in the current validator set
+    Bond { validator: Option<HumanAddr> },
+    /// Unbond will "burn" the given amount of derivative tokens (all of them by default)
+    /// and claim the unbonded staking tokens for the message sender (after exit tax is
+    /// deducted), payable once the unbonding period has passed
+    Unbond { amount: Option<Uint128> },
+    /// InstantUnbond burns all of the sender's derivative tokens and pays what they are
+    /// worth out right away from the contract's free balance, minus instant_unbond_fee.
+    /// Without enough free balance it falls back to a normal Unbond
+    InstantUnbond {},
+    /// Reinvest will withdraw the contract's pending rewards from every validator it
+    /// delegates to, then bond them via the _BondAllTokens callback
+    Reinvest {},
+    /// ReinvestAll withdraws the contract's pending rewards and compounds every
+    /// delegator's share in one pass, bonding the total with a single delegation.
+    /// Anyone can call it
+    ReinvestAll {},
+    /// WithdrawRewards pays the caller's accrued rewards out in bond_denom, leaving the
+    /// bonded principal untouched. Rewards below min_withdrawal are rejected
+    WithdrawRewards {},
+    /// SetAutoReinvest lets a delegator opt out of compounding, so the expiry sweep
+    /// accrues their rewards as a claim instead
+    SetAutoReinvest { enabled: bool },
+    /// BondStray delegates bond_denom sent to the contract outside of Bond, and not owed
+    /// to anyone, for the benefit of all holders. No tokens are minted for it. Anyone
+    /// can call it while the owner allows it
+    BondStray {},
+    /// SetBondStray allows or forbids BondStray. Only the owner can call it
+    SetBondStray { enabled: bool },
+    /// ProcessExpired sweeps up to `limit` delegators whose delegation has expired,
+    /// unbonding those who asked for it and reinvesting the rest
+    ProcessExpired { limit: Option<u32> },
+    /// SetValidatorPowers records voting power snapshots used by the HighestVotingPower
+    /// strategy, as the staking query doesn't expose them. Only the owner can call it
+    SetValidatorPowers { powers: Vec<ValidatorPower> },
+    /// SetDenyList replaces the validators that are never picked or bonded to,
+    /// whatever their commission. Only the owner can call it
+    SetDenyList { validators: Vec<HumanAddr> },
+    /// SetWithdrawAddress sets the contract's withdraw address on the chain to `address`
+    /// and pays all pending rewards there. Later rewards follow it, so Reinvest and
+    /// ReinvestAll only withdraw until it points back at the contract, which is the
+    /// normal setting. Only the owner can call it
+    SetWithdrawAddress { address: HumanAddr },
+    /// ExpireClaims bonds a claim that has sat untouched for a year back
+    /// for the benefit of the remaining holders. Only the owner can call it
+    ExpireClaims { address: HumanAddr },
+    /// Claim pays out the sender's matured claims in a single send, oldest first and at
+    /// most MAX_CLAIM_ENTRIES of them per call
+    Claim {},
+    /// ClaimFor pays out the matured claims of `address` to `address`. Anyone can call
+    /// it, so keepers can finalize unbondings on the owner's behalf
+    ClaimFor { address: HumanAddr },
+    /// EvacuateValidator undelegates all of the contract's stake from `from` (e.g. a
+    /// tombstoned validator) so the next reinvest bonds it to the configured validator.
+    /// Only the owner can call it
+    EvacuateValidator { from: HumanAddr },
+    /// UpdateTokenInfo corrects the token name and/or symbol. Decimals can't change.
+    /// Only the owner can call it
+    UpdateTokenInfo {
+        name: Option<String>,
+        symbol: Option<String>,
+    },
+    /// SetReward overwrites the undelegate_reward of `address` to recover from accounting
+    /// errors. This is a repair tool, not for normal operation. Only the owner can call it
+    SetReward { address: HumanAddr, amount: Uint128 },
+    /// MigrateDelegator moves the delegation, balance and claims of `from` to `to`,
+    /// merging them into what `to` already has, e.g. when a key is lost. This is a
+    /// recovery tool. Only the owner can call it
+    MigrateDelegator { from: HumanAddr, to: HumanAddr },
+    /// SetMinWithdrawal changes the minimum reward balance that gets reinvested, e.g. as
+    /// the token's value changes. It must be nonzero. Only the owner can call it
+    SetMinWithdrawal { min_withdrawal: Uint128 },
+    /// Rebalance redelegates stake so each configured validator holds its target weight.
+    /// Anyone can call it
+    Rebalance {},
+    /// WithdrawFees sends the reward fees the contract set aside to the fee recipients.
+    /// Only the owner can call it
+    WithdrawFees {},
     /// _BondAllTokens can only be called by the contract itself, after all rewards have been
     /// withdrawn. This is an example of using "callbacks" in message flows.
-    /// This can only be invoked by the contract itself as a return from Reinvest
+    /// This can only be invoked by the contract itself as a return from Reinvest, or by
+    /// the owner when auto_callback is disabled
     _BondAllTokens {},
 }
 
+/// Expiration marks when an allowance stops being spendable
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Expiration {
+    /// AtHeight expires once `env.block.height` >= height
+    AtHeight(u64),
+    /// AtTime expires once `env.block.time` >= time (seconds since epoch)
+    AtTime(u64),
+    /// Never never expires
+    Never {},
+}
+
+impl Expiration {
+    pub fn is_expired(&self, block: &BlockInfo) -> bool {
+        match self {
+            Expiration::AtHeight(height) => block.height >= *height,
+            Expiration::AtTime(time) => block.time >= *time,
+            Expiration::Never {} => false,
+        }
+    }
+}
+
+impl Default for Expiration {
+    fn default() -> Self {
+        Expiration::Never {}
+    }
+}
+
+/// Cw20ReceiveMsg is sent to the recipient contract of a `Send`, wrapped in a `Receive` variant
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Cw20ReceiveMsg {
+    pub sender: HumanAddr,
+    pub amount: Uint128,
+    pub msg: Option<Binary>,
+}
+
+impl Cw20ReceiveMsg {
+    /// serializes the message as `{"receive": {...}}`
+    pub fn into_binary(self) -> StdResult<Binary> {
+        to_binary(&ReceiverHandleMsg::Receive(self))
+    }
+
+    /// creates a cosmos msg calling the `Receive` handler of the given contract
+    pub fn into_cosmos_msg(self, contract_addr: HumanAddr) -> StdResult<CosmosMsg> {
+        Ok(WasmMsg::Execute {
+            contract_addr,
+            msg: self.into_binary()?,
+            send: vec![],
+        }
+        .into())
+    }
+}
+
+/// ReceiverHandleMsg is the handler interface a contract must expose to accept `Send`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiverHandleMsg {
+    Receive(Cw20ReceiveMsg),
+}
+
+/// MigrateMsg upgrades the storage of an existing instance to the current layout
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MigrateMsg {}
+
+/// SudoMsg can only be dispatched by the chain itself, never by a user
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SudoMsg {
+    /// ProcessExpired runs the same sweep as HandleMsg::ProcessExpired, e.g. from a block hook
+    ProcessExpired { limit: Option<u32> },
+    /// ProcessClaims pays out the matured claims of up to `limit` addresses, so unbondings
+    /// complete without anyone claiming them
+    ProcessClaims { limit: Option<u32> },
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     /// Balance shows the number of staking derivatives
     Balance { address: HumanAddr },
+    /// BalanceAt returns the balance of the given address at the end of `height`. Only
+    /// heights within the configured snapshot_retention are covered
+    BalanceAt { address: HumanAddr, height: u64 },
     /// Claims shows the number of tokens this address can access when they are done unbonding
     Claims { address: HumanAddr },
+    /// UnbondSchedule lists the address' claims as (amount, release_height), earliest
+    /// release first
+    UnbondSchedule { address: HumanAddr },
+    /// Unbonding shows the address' claims that haven't matured yet
+    Unbonding { address: HumanAddr },
+    /// ClaimsBreakdown splits the claims of all addresses into those payable now and
+    /// those still unbonding, to plan how much liquidity to keep on hand
+    ClaimsBreakdown {},
     /// TokenInfo shows the metadata of the token for UIs
     TokenInfo {},
     /// Investment shows info on total staking tokens under custody,
@@ -58,6 +326,101 @@ pub enum QueryMsg {
     /// It also shows with the exit tax.
     Investment {},
     Validators {},
+    /// ReinvestPreview shows what a Reinvest call would do right now, without executing it
+    ReinvestPreview {},
+    /// Allowance shows how much `spender` may still move of `owner`'s tokens
+    Allowance { owner: HumanAddr, spender: HumanAddr },
+    /// AllAllowances pages through every allowance `owner` has granted
+    AllAllowances {
+        owner: HumanAddr,
+        start_after: Option<HumanAddr>,
+        limit: Option<u32>,
+    },
+    /// UnrealizedGain shows how much the address' derivative balance has appreciated
+    /// over what was paid in for it
+    UnrealizedGain { address: HumanAddr },
+    /// BlendedCommission shows the commission rate the pool effectively pays,
+    /// weighted by how much it has delegated to each validator
+    BlendedCommission {},
+    /// NextExpiry shows when the expiry sweep will next pick up this delegator
+    NextExpiry { address: HumanAddr },
+    /// ExpiredCount shows how many delegators the expiry sweep would currently process,
+    /// so keepers can pick its limit
+    ExpiredCount {},
+    /// AllDelegations pages through the delegation records of every delegator
+    AllDelegations {
+        start_after: Option<HumanAddr>,
+        limit: Option<u32>,
+    },
+    /// IsDelegator shows whether the address has registered as a delegator
+    IsDelegator { address: HumanAddr },
+    /// EffectiveRate shows the native value of one derivative token, both from the
+    /// bonded tokens alone and including rewards still pending reinvestment
+    EffectiveRate {},
+    /// BackedRate shows the native value of one derivative token backed by the stake
+    /// that remains once outstanding claims have left bonded
+    BackedRate {},
+    /// MinMeaningfulBond shows the smallest bond that mints at least one derivative
+    /// token at the current ratio, anything below it is absorbed by the pool
+    MinMeaningfulBond {},
+    /// Account shows the balance, claims and delegation record of an address at once
+    Account { address: HumanAddr },
+    /// Tvl shows everything the contract controls in bond_denom: delegated tokens,
+    /// its free balance and rewards pending withdrawal. The balance held for claims
+    /// and fees is reported apart
+    Tvl {},
+    /// BalanceSheet compares what the contract holds (the Tvl) with what it owes: the
+    /// native value of the issued tokens, claims and unpaid fees
+    BalanceSheet {},
+    /// UnbondStatus shows whether the address asked to unbond and when the sweep settles
+    /// its remaining rewards
+    UnbondStatus { address: HumanAddr },
+    /// ValidatorRewards shows the rewards accumulated with each validator the contract
+    /// delegates to, including the ones that have none
+    ValidatorRewards {},
+    /// Redeemable shows what the address would receive, after exit tax, for unbonding
+    /// its whole balance now
+    Redeemable { address: HumanAddr },
+    /// FeesEarned shows the cumulative derivative tokens the owner has received as fees
+    FeesEarned {},
+    /// OwnerFees splits the owner's derivative balance into fee income and its own bonds
+    OwnerFees {},
+    /// TargetValidators shows the validators the pool is configured to use and the share
+    /// of the stake each is meant to hold. This is not the chain's validator set
+    TargetValidators {},
+    /// ExitTaxPreview breaks down what unbonding `amount` derivative tokens would cost
+    /// the address in exit tax and pay out in staking tokens. The rate depends on how
+    /// long the address has held its bond
+    ExitTaxPreview { address: HumanAddr, amount: Uint128 },
+    /// DelegatorCount shows how many addresses have bonded
+    DelegatorCount {},
+    /// PendingUnbondCount shows how many delegators asked to unbond and are waiting
+    /// for the expiry sweep
+    PendingUnbondCount {},
+    /// RankedValidators lists the validators in the order the selection strategy would
+    /// pick them, best first
+    RankedValidators { limit: Option<u32> },
+    /// CanBond shows whether a new bond would currently succeed, and if not, why
+    CanBond {},
+    /// ContractBalance shows the contract's bond_denom balance in the bank as it is,
+    /// including what is held for claims and fees
+    ContractBalance {},
+    /// ValidatorStats compares the configured validators with the ones the contract
+    /// actually has stake on
+    ValidatorStats {},
+    /// ValidatorActive shows whether the configured validator is still in the validator
+    /// set. When it isn't, the owner should move the stake, e.g. with EvacuateValidator
+    ValidatorActive {},
+    /// ValidatorHistory lists every validator the contract has ever delegated or
+    /// redelegated to, including those it has since moved all stake away from
+    ValidatorHistory {},
+    /// DelegatorsByValidator pages through the delegation records currently assigned
+    /// to `validator`
+    DelegatorsByValidator {
+        validator: HumanAddr,
+        start_after: Option<HumanAddr>,
+        limit: Option<u32>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -65,9 +428,45 @@ pub struct BalanceResponse {
     pub balance: Uint128,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+pub struct AllowanceResponse {
+    pub allowance: Uint128,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AllowanceInfo {
+    pub spender: HumanAddr,
+    pub allowance: Uint128,
+    pub expires: Expiration,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AllAllowancesResponse {
+    pub allowances: Vec<AllowanceInfo>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ClaimsResponse {
     pub claims: Uint128,
+    /// the same claims, in the denom they are paid out in
+    pub coin: Coin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UnbondScheduleResponse {
+    pub schedule: Vec<(Uint128, u64)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ClaimsBreakdownResponse {
+    pub matured: Uint128,
+    pub pending: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UnbondingResponse {
+    pub unbonding: Coin,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -78,6 +477,217 @@ pub struct DelegateResponse{
     pub last_delegate_height: u64,
     pub unbond_flag: bool,
     pub undelegate_reward: Uint128,
+    pub last_redelegate_height: Option<u64>,
+    pub cost_basis: Uint128,
+    pub auto_reinvest: bool,
+    pub last_reward_index: Decimal,
+    #[serde(default)]
+    pub first_bond_height: u64,
+    #[serde(default)]
+    pub last_bond_height: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AllDelegationsResponse {
+    pub delegations: Vec<DelegateResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ReinvestPreviewResponse {
+    /// validators that rewards would be withdrawn from
+    pub withdraw_from: Vec<HumanAddr>,
+    /// the amount that would be bonded by the _BondAllTokens callback
+    /// (zero if it is below min_withdrawal or reinvest_min_ratio)
+    pub to_bond: Coin,
+    /// the rewards the callback would add to the delegators' claims instead, as set by
+    /// distribute_rewards and compound_ratio
+    pub to_distribute: Coin,
+    /// the validator the amount would be bonded to
+    pub validator: HumanAddr,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UnrealizedGainResponse {
+    /// native value of the derivative balance at the current nominal_value
+    pub value: Uint128,
+    /// native tokens paid in for the position
+    pub cost_basis: Uint128,
+    /// value above the cost basis (zero if the position is under water)
+    pub gain: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RankedValidatorsResponse {
+    pub validators: Vec<Validator>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct CanBondResponse {
+    pub can_bond: bool,
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ContractBalanceResponse {
+    pub balance: Coin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ValidatorActiveResponse {
+    pub validator: HumanAddr,
+    pub active: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ValidatorHistoryEntry {
+    pub validator: HumanAddr,
+    /// height of the contract's first delegation to it
+    pub since: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ValidatorHistoryResponse {
+    pub validators: Vec<ValidatorHistoryEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ValidatorStatsResponse {
+    /// how many validators the pool is configured to use
+    pub configured: u32,
+    /// how many validators the contract has a nonzero delegation with
+    pub active: u32,
+    /// the bond_denom stake across all of those delegations
+    pub total_bonded: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DelegatorCountResponse {
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingUnbondCountResponse {
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExitTaxPreviewResponse {
+    /// staking tokens `amount` is worth before tax
+    pub gross_native: Uint128,
+    /// derivative tokens taken as exit tax
+    pub tax_derivative: Uint128,
+    /// staking tokens paid out for what is left after tax
+    pub net_native: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TargetValidatorsResponse {
+    /// configured validators with their target weight, the weights add up to 1
+    pub validators: Vec<(HumanAddr, Decimal)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OwnerFeesResponse {
+    pub fees: Uint128,
+    pub bonds: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FeesEarnedResponse {
+    pub fees_earned: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RedeemableResponse {
+    pub redeemable: Coin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ValidatorRewardsResponse {
+    pub rewards: Vec<(HumanAddr, Coin)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct UnbondStatusResponse {
+    pub unbond_flag: bool,
+    /// first height at which the expiry sweep processes the delegation
+    pub eligible_at: u64,
+    /// native tokens delegated on behalf of the address
+    pub amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TvlResponse {
+    /// delegated tokens, the free balance and rewards pending withdrawal
+    pub tvl: Coin,
+    /// the part of the balance held for claims and fees
+    pub reserved: Coin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BalanceSheetResponse {
+    /// delegated tokens, the contract's balance and rewards pending withdrawal
+    pub assets: Uint128,
+    /// native value of the issued tokens, reserved claims and unpaid fees
+    pub obligations: Uint128,
+    /// assets / obligations, 1 when nothing is owed. Below 1 the pool is short, e.g.
+    /// after a slash
+    pub coverage: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AccountResponse {
+    pub balance: Uint128,
+    pub claims: Uint128,
+    /// None if the address never bonded
+    pub delegation: Option<DelegateResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BackedRateResponse {
+    /// bonded / issued, the same as InvestmentResponse.nominal_value
+    pub nominal: Decimal,
+    /// bonded / issued once the claims have left bonded, never above nominal
+    pub backed: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MinMeaningfulBondResponse {
+    /// ceil(bonded / issued) in bond_denom, or 1 before anything is issued
+    pub minimum: Coin,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EffectiveRateResponse {
+    /// bonded / issued, the same as InvestmentResponse.nominal_value
+    pub raw: Decimal,
+    /// (bonded + pending rewards less the reward fee) / issued, the price after the next
+    /// reinvest
+    pub effective: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct IsDelegatorResponse {
+    pub is_delegator: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExpiredCountResponse {
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NextExpiryResponse {
+    /// the delegation expires once the chain is past this height
+    pub expires_at: u64,
+    /// whether the next sweep would process this delegator
+    pub eligible: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BlendedCommissionResponse {
+    /// stake weighted commission (zero if nothing is delegated)
+    pub commission: Decimal,
 }
 
 /// TokenInfoResponse is info to display the derivative token in a UI
@@ -93,6 +703,9 @@ pub struct TokenInfoResponse {
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InvestmentResponse {
+    /// schema_version changes whenever this response's shape does, so contracts reading
+    /// it can tell which fields to expect
+    pub schema_version: u16,
     pub token_supply: Uint128,
     pub staked_tokens: Coin,
     // ratio of staked_tokens / token_supply (or how many native tokens that one derivative token is nominally worth)
@@ -104,7 +717,42 @@ pub struct InvestmentResponse {
     pub exit_tax: Decimal,
     /// All tokens are bonded to this validator
     pub validator: HumanAddr,
+    /// all validators the pool is configured to use
+    pub validators: Vec<HumanAddr>,
     /// This is the minimum amount we will pull out to reinvest, as well as a minumum
     /// that can be unbonded (to avoid needless staking tx)
     pub min_withdrawal: Uint128,
+    /// how bonds pick their validator
+    pub selection_strategy: SelectionStrategy,
+    /// how many addresses can register as delegators, if capped
+    pub max_delegators: Option<u32>,
+    /// whether rewards are paid out as claims instead of compounded
+    pub distribute_rewards: bool,
+    pub compound_ratio: Decimal,
+    /// the share of bonded tokens rewards must reach before being reinvested, if any
+    pub reinvest_min_ratio: Option<Decimal>,
+    /// display name of the bond denom, if configured
+    pub display_denom: Option<String>,
+    /// decimal places between the bond denom and display_denom, if configured
+    pub display_exponent: Option<u32>,
+    /// weighted recipients of the exit tax
+    pub fee_recipients: Vec<(HumanAddr, Decimal)>,
+    pub min_age_before_redelegate: u64,
+    pub min_hold_blocks: u64,
+    pub blocks_per_year: u64,
+    pub tax_tiers: Vec<(u64, Decimal)>,
+    pub auto_callback: bool,
+    pub min_commission: Decimal,
+    pub max_commission: Decimal,
+    pub max_validator_share: Decimal,
+    pub instant_unbond_fee: Option<Decimal>,
+    pub reward_fee: Decimal,
+    pub snapshot_retention: Option<u32>,
+    pub deny_list: Vec<HumanAddr>,
+    pub withdraw_address: Option<HumanAddr>,
+    pub bond_stray: bool,
+    /// block height at which the contract was initialized
+    pub init_height: u64,
+    /// block time (seconds since epoch) at which the contract was initialized
+    pub init_time: u64,
 }
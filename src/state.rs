@@ -7,16 +7,27 @@ use cosmwasm_storage::{
     Singleton,
 };
 
-use crate::msg::{DelegateResponse, TokenInfoResponse};
+use crate::msg::{AllowanceResponse, DelegateResponse, SelectionStrategy, TokenInfoResponse};
 
 pub const KEY_DELEGATORS: &[u8] = b"delegator";
+pub const KEY_DELEGATOR_COUNT: &[u8] = b"delegator_count";
+pub const KEY_CONTRACT_VERSION: &[u8] = b"contract_version";
+pub const KEY_FEES_EARNED: &[u8] = b"fees_earned";
 pub const KEY_INVESTMENT: &[u8] = b"invest";
+pub const KEY_PENDING_UNBONDS: &[u8] = b"pending_unbonds";
+pub const KEY_SWEEP_CURSOR: &[u8] = b"sweep_cursor";
 pub const KEY_TOKEN_INFO: &[u8] = b"token";
 pub const KEY_TOTAL_SUPPLY: &[u8] = b"total_supply";
 
+pub const PREFIX_ALLOWANCE: &[u8] = b"allowance";
 pub const PREFIX_BALANCE: &[u8] = b"balance";
 pub const PREFIX_CLAIMS: &[u8] = b"claim";
+pub const PREFIX_CLAIM_HEIGHTS: &[u8] = b"claim_height";
 pub const PREFIX_DELEGATIONS: &[u8] = b"delegation";
+pub const PREFIX_NONCES: &[u8] = b"nonce";
+pub const PREFIX_SNAPSHOTS: &[u8] = b"snapshot";
+pub const PREFIX_VALIDATOR_HISTORY: &[u8] = b"validator_history";
+pub const PREFIX_VALIDATOR_POWERS: &[u8] = b"validator_power";
 
 /// balances are state of the erc20 tokens
 pub fn balances<S: Storage>(storage: &mut S) -> Bucket<S, Uint128> {
@@ -27,15 +38,86 @@ pub fn balances_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, Uint1
     bucket_read(storage, PREFIX_BALANCE)
 }
 
+/// nonces are the next nonce each sender must use for TransferWithNonce
+pub fn nonces<S: Storage>(storage: &mut S) -> Bucket<S, u64> {
+    bucket(storage, PREFIX_NONCES)
+}
+
+pub fn nonces_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, u64> {
+    bucket_read(storage, PREFIX_NONCES)
+}
+
+/// allowances are keyed by owner, then by spender
+pub fn allowances<'a, S: Storage>(
+    storage: &'a mut S,
+    owner: &CanonicalAddr,
+) -> Bucket<'a, S, AllowanceResponse> {
+    Bucket::multilevel(storage, &[PREFIX_ALLOWANCE, owner.as_slice()])
+}
+
+pub fn allowances_read<'a, S: ReadonlyStorage>(
+    storage: &'a S,
+    owner: &CanonicalAddr,
+) -> ReadonlyBucket<'a, S, AllowanceResponse> {
+    ReadonlyBucket::multilevel(storage, &[PREFIX_ALLOWANCE, owner.as_slice()])
+}
+
+/// balance_snapshots are keyed by holder, then by the big-endian height the balance
+/// was set at
+pub fn balance_snapshots<'a, S: Storage>(
+    storage: &'a mut S,
+    holder: &CanonicalAddr,
+) -> Bucket<'a, S, Uint128> {
+    Bucket::multilevel(storage, &[PREFIX_SNAPSHOTS, holder.as_slice()])
+}
+
+pub fn balance_snapshots_read<'a, S: ReadonlyStorage>(
+    storage: &'a S,
+    holder: &CanonicalAddr,
+) -> ReadonlyBucket<'a, S, Uint128> {
+    ReadonlyBucket::multilevel(storage, &[PREFIX_SNAPSHOTS, holder.as_slice()])
+}
+
+/// Claim is an amount of staking tokens owed to an address, payable from release_height
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Claim {
+    pub amount: Uint128,
+    pub release_height: u64,
+}
+
 /// claims are the claims to money being unbonded
-pub fn claims<S: Storage>(storage: &mut S) -> Bucket<S, Uint128> {
+pub fn claims<S: Storage>(storage: &mut S) -> Bucket<S, Vec<Claim>> {
     bucket(storage, PREFIX_CLAIMS)
 }
 
-pub fn claims_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, Uint128> {
+pub fn claims_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, Vec<Claim>> {
     bucket_read(storage, PREFIX_CLAIMS)
 }
 
+/// legacy_claims reads the claims bucket in its version 1 format, a bare amount per
+/// address. Only the migration should use it
+pub fn legacy_claims_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, Uint128> {
+    bucket_read(storage, PREFIX_CLAIMS)
+}
+
+/// contract_version is the storage layout version, used to guard migrations
+pub fn contract_version<S: Storage>(storage: &mut S) -> Singleton<S, u16> {
+    singleton(storage, KEY_CONTRACT_VERSION)
+}
+
+pub fn contract_version_read<S: ReadonlyStorage>(storage: &S) -> ReadonlySingleton<S, u16> {
+    singleton_read(storage, KEY_CONTRACT_VERSION)
+}
+
+/// claim_heights record when each claim was last credited, so abandoned ones can expire
+pub fn claim_heights<S: Storage>(storage: &mut S) -> Bucket<S, u64> {
+    bucket(storage, PREFIX_CLAIM_HEIGHTS)
+}
+
+pub fn claim_heights_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, u64> {
+    bucket_read(storage, PREFIX_CLAIM_HEIGHTS)
+}
+
 pub fn delegations<S: Storage>(storage: &mut S) -> Bucket<S, DelegateInfo> {
     bucket(storage, PREFIX_DELEGATIONS)
 }
@@ -44,7 +126,9 @@ pub fn delegations_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, De
     bucket_read(storage, PREFIX_DELEGATIONS)
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+/// Records from older versions lack the fields added since and load them as in Default
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(default)]
 pub struct DelegateInfo {
     pub delegator: HumanAddr,
     pub validator: HumanAddr,
@@ -52,6 +136,58 @@ pub struct DelegateInfo {
     pub last_delegate_height: u64,
     pub unbond_flag: bool,
     pub undelegate_reward: Uint128,
+    /// height of the last redelegation of this stake, if any
+    pub last_redelegate_height: Option<u64>,
+    /// native tokens paid in for the current position, to compute gains against
+    pub cost_basis: Uint128,
+    /// when false the expiry sweep leaves rewards as a claim instead of compounding
+    pub auto_reinvest: bool,
+    /// Supply.reward_index when this delegation's rewards were last settled
+    pub last_reward_index: Decimal,
+    /// height of the first bond, kept across later bonds to measure the holding time.
+    /// Records from before version 3 lack it until migrate fills it in
+    pub first_bond_height: u64,
+    /// height of the latest bond, which min_hold_blocks counts from. Unlike
+    /// last_delegate_height, the sweeps and reinvests leave it alone
+    pub last_bond_height: u64,
+}
+
+impl Default for DelegateInfo {
+    fn default() -> Self {
+        DelegateInfo {
+            delegator: HumanAddr::default(),
+            validator: HumanAddr::default(),
+            amount: Uint128::zero(),
+            last_delegate_height: 0,
+            unbond_flag: false,
+            undelegate_reward: Uint128::zero(),
+            last_redelegate_height: None,
+            cost_basis: Uint128::zero(),
+            auto_reinvest: true,
+            last_reward_index: Decimal::zero(),
+            first_bond_height: 0,
+            last_bond_height: 0,
+        }
+    }
+}
+
+/// validator_history is every validator the contract has delegated to, keyed by address,
+/// with the height of the first delegation
+pub fn validator_history<S: Storage>(storage: &mut S) -> Bucket<S, u64> {
+    bucket(storage, PREFIX_VALIDATOR_HISTORY)
+}
+
+pub fn validator_history_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, u64> {
+    bucket_read(storage, PREFIX_VALIDATOR_HISTORY)
+}
+
+/// validator_powers are the last reported voting powers, keyed by validator address
+pub fn validator_powers<S: Storage>(storage: &mut S) -> Bucket<S, Uint128> {
+    bucket(storage, PREFIX_VALIDATOR_POWERS)
+}
+
+pub fn validator_powers_read<S: ReadonlyStorage>(storage: &S) -> ReadonlyBucket<S, Uint128> {
+    bucket_read(storage, PREFIX_VALIDATOR_POWERS)
 }
 
 pub fn delegators<S: Storage>(storage: &mut S) -> Singleton<S, Vec<HumanAddr>> {
@@ -62,8 +198,48 @@ pub fn delegators_read<S: ReadonlyStorage>(storage: &S) -> ReadonlySingleton<S,
     singleton_read(storage, KEY_DELEGATORS)
 }
 
-/// Investment info is fixed at initialization, and is used to control the function of the contract
+/// delegator_count tracks the length of the delegator list so it can be read in O(1)
+pub fn delegator_count<S: Storage>(storage: &mut S) -> Singleton<S, u64> {
+    singleton(storage, KEY_DELEGATOR_COUNT)
+}
+
+pub fn delegator_count_read<S: ReadonlyStorage>(storage: &S) -> ReadonlySingleton<S, u64> {
+    singleton_read(storage, KEY_DELEGATOR_COUNT)
+}
+
+/// pending_unbonds counts the delegations flagged for unbonding that the sweep hasn't
+/// processed yet
+pub fn pending_unbonds<S: Storage>(storage: &mut S) -> Singleton<S, u64> {
+    singleton(storage, KEY_PENDING_UNBONDS)
+}
+
+pub fn pending_unbonds_read<S: ReadonlyStorage>(storage: &S) -> ReadonlySingleton<S, u64> {
+    singleton_read(storage, KEY_PENDING_UNBONDS)
+}
+
+/// sweep_cursor is the last delegator the expiry sweep looked at, the next sweep
+/// resumes after it
+pub fn sweep_cursor<S: Storage>(storage: &mut S) -> Singleton<S, HumanAddr> {
+    singleton(storage, KEY_SWEEP_CURSOR)
+}
+
+pub fn sweep_cursor_read<S: ReadonlyStorage>(storage: &S) -> ReadonlySingleton<S, HumanAddr> {
+    singleton_read(storage, KEY_SWEEP_CURSOR)
+}
+
+/// fees_earned is the cumulative amount of derivative tokens credited to the owner as fees
+pub fn fees_earned<S: Storage>(storage: &mut S) -> Singleton<S, Uint128> {
+    singleton(storage, KEY_FEES_EARNED)
+}
+
+pub fn fees_earned_read<S: ReadonlyStorage>(storage: &S) -> ReadonlySingleton<S, Uint128> {
+    singleton_read(storage, KEY_FEES_EARNED)
+}
+
+/// Investment info is fixed at initialization, and is used to control the function of the contract.
+/// Settings added since version 1 load as in Default until migrate stores them
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(default)]
 pub struct InvestmentInfo {
     /// owner created the contract and takes a cut
     pub owner: CanonicalAddr,
@@ -74,13 +250,130 @@ pub struct InvestmentInfo {
     /// All tokens are bonded to this validator
     /// FIXME: humanize/canonicalize address doesn't work for validator addrresses
     pub validator: HumanAddr,
+    /// validators the pool is configured to use, all checked to be in the validator set
+    /// at init
+    pub validators: Vec<HumanAddr>,
     /// This is the minimum amount we will pull out to reinvest, as well as a minumum
     /// that can be unbonded (to avoid needless staking tx)
     pub min_withdrawal: Uint128,
+    /// how bonds pick their validator
+    pub selection_strategy: SelectionStrategy,
+    /// bounds the delegator list so expiry sweeps stay affordable
+    pub max_delegators: Option<u32>,
+    /// when set, withdrawn rewards are split across delegators' claims instead of
+    /// being bonded again
+    pub distribute_rewards: bool,
+    /// share of the withdrawn rewards bonded again when not distributing them all; the
+    /// rest goes to delegators' claims by bonded amount
+    pub compound_ratio: Decimal,
+    /// relative reinvest threshold: when set, the free balance must also reach this
+    /// fraction of the bonded tokens (on top of min_withdrawal) to be reinvested
+    pub reinvest_min_ratio: Option<Decimal>,
+    /// display metadata for UIs only, it never affects accounting
+    pub display_denom: Option<String>,
+    pub display_exponent: Option<u32>,
+    /// fee_recipients split the exit tax and reward fee by weight, the weights add up to 1
+    pub fee_recipients: Vec<(HumanAddr, Decimal)>,
+    /// fresh stake only compounds in place until it is this many blocks old, so it
+    /// isn't churned between validators
+    pub min_age_before_redelegate: u64,
+    /// unbonding is refused until this many blocks after the last delegation, so
+    /// tokens can't be bonded and unbonded around a reward to game the rate
+    pub min_hold_blocks: u64,
+    /// how many blocks the chain produces in a year, used to turn durations into blocks
+    pub blocks_per_year: u64,
+    /// exit tax rates for delegations held less than each threshold (in blocks),
+    /// thresholds ascending. Longer holdings pay exit_tax
+    pub tax_tiers: Vec<(u64, Decimal)>,
+    /// when false, reinvest only withdraws and the owner triggers _BondAllTokens
+    pub auto_callback: bool,
+    /// commission band validator selection is restricted to
+    pub min_commission: Decimal,
+    pub max_commission: Decimal,
+    /// no validator is delegated more than this share of the total stake by a bond
+    pub max_validator_share: Decimal,
+    /// validators excluded from selection and from explicit bonds
+    pub deny_list: Vec<HumanAddr>,
+    /// where the chain sends withdrawn rewards, None for the contract itself
+    pub withdraw_address: Option<HumanAddr>,
+    /// whether stray bond_denom sent to the contract may be bonded for the pool
+    pub bond_stray: bool,
+    /// when set, balance changes are snapshotted and each holder's latest this many
+    /// snapshots are kept
+    pub snapshot_retention: Option<u32>,
+    /// when set, delegators can be paid out immediately from the free balance at this fee
+    pub instant_unbond_fee: Option<Decimal>,
+    /// share of the withdrawn rewards kept as fees instead of going to the delegators
+    pub reward_fee: Decimal,
+    /// block height at which the contract was initialized
+    pub init_height: u64,
+    /// block time (seconds since epoch) at which the contract was initialized
+    pub init_time: u64,
+}
+
+pub const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+/// 6_307_200 blocks a year is a 5s block time
+pub const DEFAULT_BLOCKS_PER_YEAR: u64 = 6_307_200;
+
+/// the defaults are what init uses when a setting isn't given. validators and
+/// fee_recipients depend on other settings, so migrate fills them in
+impl Default for InvestmentInfo {
+    fn default() -> Self {
+        InvestmentInfo {
+            owner: CanonicalAddr::default(),
+            bond_denom: String::default(),
+            exit_tax: Decimal::zero(),
+            validator: HumanAddr::default(),
+            validators: vec![],
+            min_withdrawal: Uint128::zero(),
+            selection_strategy: SelectionStrategy::LowestCommission,
+            max_delegators: None,
+            distribute_rewards: false,
+            compound_ratio: Decimal::one(),
+            reinvest_min_ratio: None,
+            display_denom: None,
+            display_exponent: None,
+            fee_recipients: vec![],
+            min_age_before_redelegate: 0,
+            min_hold_blocks: 0,
+            blocks_per_year: DEFAULT_BLOCKS_PER_YEAR,
+            tax_tiers: vec![],
+            auto_callback: true,
+            min_commission: Decimal::zero(),
+            max_commission: Decimal::one(),
+            max_validator_share: Decimal::one(),
+            deny_list: vec![],
+            withdraw_address: None,
+            bond_stray: false,
+            snapshot_retention: None,
+            instant_unbond_fee: None,
+            reward_fee: Decimal::zero(),
+            init_height: 0,
+            init_time: 0,
+        }
+    }
+}
+
+impl InvestmentInfo {
+    /// blocks_for_duration is how many blocks the chain takes for `seconds`
+    pub fn blocks_for_duration(&self, seconds: u64) -> u64 {
+        (seconds as u128 * self.blocks_per_year as u128 / SECONDS_PER_YEAR as u128) as u64
+    }
+
+    /// exit_tax_for is the exit tax rate for a delegation held `blocks_held` blocks
+    pub fn exit_tax_for(&self, blocks_held: u64) -> Decimal {
+        self.tax_tiers
+            .iter()
+            .find(|(threshold, _)| blocks_held < *threshold)
+            .map(|(_, rate)| *rate)
+            .unwrap_or(self.exit_tax)
+    }
 }
 
 /// Supply is dynamic and tracks the current supply of staked and ERC20 tokens.
+/// Amounts added since version 1 start at zero
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema, Default)]
+#[serde(default)]
 pub struct Supply {
     /// issued is how many derivative tokens this contract has issued
     pub issued: Uint128,
@@ -88,6 +381,15 @@ pub struct Supply {
     pub bonded: Uint128,
     /// claims is how many tokens need to be reserved paying back those who unbonded
     pub claims: Uint128,
+    /// evacuating is stake undelegated from a dead validator that still counts as
+    /// bonded, and is bonded again without minting once it is back in our balance
+    pub evacuating: Uint128,
+    /// fees are native reward fees held for the fee recipients, so they are never
+    /// reinvested or counted as backing
+    pub fees: Uint128,
+    /// reward_index is the accumulated reward per delegated token, bumped on every
+    /// reward withdrawal
+    pub reward_index: Decimal,
 }
 
 pub fn invest_info<S: Storage>(storage: &mut S) -> Singleton<S, InvestmentInfo> {
@@ -56,6 +56,28 @@ fn initialization_with_missing_validator() {
         validator: HumanAddr::from("my-validator"),
         exit_tax: Decimal::percent(2),
         min_withdrawal: Uint128(50),
+        selection_strategy: None,
+        max_delegators: None,
+        distribute_rewards: false,
+        reinvest_min_ratio: None,
+        display_denom: None,
+        display_exponent: None,
+        fee_recipients: None,
+        validators: None,
+        min_age_before_redelegate: None,
+        auto_callback: None,
+        min_commission: None,
+        max_commission: None,
+        blocks_per_year: None,
+        tax_tiers: None,
+        min_hold_blocks: None,
+        max_validator_share: None,
+        instant_unbond_fee: None,
+        reward_fee: None,
+        snapshot_retention: None,
+        deny_list: None,
+        bond_stray: None,
+        compound_ratio: None,
     };
     let info = mock_info(&creator, &[]);
 
@@ -93,6 +115,28 @@ fn proper_initialization() {
         validator: HumanAddr::from("my-validator"),
         exit_tax: Decimal::percent(2),
         min_withdrawal: Uint128(50),
+        selection_strategy: None,
+        max_delegators: None,
+        distribute_rewards: false,
+        reinvest_min_ratio: None,
+        display_denom: None,
+        display_exponent: None,
+        fee_recipients: None,
+        validators: None,
+        min_age_before_redelegate: None,
+        auto_callback: None,
+        min_commission: None,
+        max_commission: None,
+        blocks_per_year: None,
+        tax_tiers: None,
+        min_hold_blocks: None,
+        max_validator_share: None,
+        instant_unbond_fee: None,
+        reward_fee: None,
+        snapshot_retention: None,
+        deny_list: None,
+        bond_stray: None,
+        compound_ratio: None,
     };
     let info = mock_info(&creator, &[]);
 